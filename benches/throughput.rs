@@ -0,0 +1,108 @@
+//! Throughput of set/get/delete across secret sizes, against the in-memory
+//! `SpyBackend` so it runs without a platform keyring.
+//!
+//! Run with `cargo bench --features testing`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use keyring_cursed::{Backend, Entry, SpyBackend};
+
+const SIZES: [(&str, usize); 4] = [
+    ("64B", 64),
+    ("1KB", 1024),
+    ("64KB", 64 * 1024),
+    ("1MB", 1024 * 1024),
+];
+
+/// The service every benchmark stores under.
+const SERVICE: &str = "keyring-cursed-bench";
+
+/// How long each measurement runs for.
+const BUDGET: Duration = Duration::from_millis(500);
+
+fn main() {
+    small_secrets();
+    for (label, size) in SIZES {
+        let secret: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+        let spy = SpyBackend::new();
+        let entry = Entry::new(SERVICE, "bench-user")
+            .unwrap()
+            .with_backend(Box::new(spy.clone()));
+
+        report("set_secret", label, size, || {
+            entry.set_secret(black_box(&secret)).unwrap();
+            spy.take_operations();
+            None
+        });
+
+        entry.set_secret(&secret).unwrap();
+        report("get_secret", label, size, || {
+            black_box(entry.get_secret().unwrap());
+            spy.take_operations();
+            None
+        });
+
+        report("delete_credential", label, size, || {
+            entry.set_secret(&secret).unwrap();
+            spy.take_operations();
+            let start = Instant::now();
+            entry.delete_credential().unwrap();
+            let elapsed = start.elapsed();
+            spy.take_operations();
+            Some(elapsed)
+        });
+    }
+}
+
+/// A single-part secret through the backend alone, as raw `keyring` would
+/// store it, and through entries with and without the total cache.
+fn small_secrets() {
+    let secret = [7u8; 64];
+    let spy = SpyBackend::new();
+    report("raw set+get", "64B", secret.len(), || {
+        spy.set_secret(SERVICE, "bench-raw", black_box(&secret))
+            .unwrap();
+        black_box(spy.get_secret(SERVICE, "bench-raw").unwrap());
+        spy.take_operations();
+        None
+    });
+
+    for (name, cache) in [("set+get", false), ("cached set+get", true)] {
+        let entry = Entry::builder(SERVICE)
+            .cache_total(cache)
+            .build("bench-small")
+            .unwrap()
+            .with_backend(Box::new(spy.clone()));
+        entry.set_secret(secret).unwrap();
+        report(name, "64B", secret.len(), || {
+            entry.set_secret(black_box(secret)).unwrap();
+            black_box(entry.get_secret().unwrap());
+            spy.take_operations();
+            None
+        });
+    }
+}
+
+/// Run `op` until the time budget is spent and print its throughput.
+///
+/// `op` returns the time to count for the iteration, or `None` to count
+/// the whole call.
+fn report(name: &str, label: &str, size: usize, mut op: impl FnMut() -> Option<Duration>) {
+    let mut iterations = 0u32;
+    let mut measured = Duration::ZERO;
+    let started = Instant::now();
+    while started.elapsed() < BUDGET {
+        let start = Instant::now();
+        let counted = op().unwrap_or_else(|| start.elapsed());
+        measured += counted;
+        iterations += 1;
+    }
+
+    let bytes = size as f64 * f64::from(iterations);
+    let mb_per_sec = bytes / measured.as_secs_f64() / (1024.0 * 1024.0);
+    let per_op = measured / iterations;
+    println!(
+        "{name:<18} {label:>5}: {mb_per_sec:>10.1} MB/s ({per_op:?}/op, {iterations} iterations)"
+    );
+}
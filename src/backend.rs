@@ -0,0 +1,356 @@
+use std::collections::HashMap;
+use std::fmt;
+
+/// A credential store holding the individual parts of a secret.
+///
+/// Each part is addressed by the service and the part's user name
+/// (`{user}.{part}`). Implementations report a missing part as
+/// [`keyring::Error::NoEntry`], which the chunking logic relies on.
+pub trait Backend: Send + Sync + fmt::Debug {
+    /// Read the stored bytes for the given service and user.
+    fn get_secret(&self, service: &str, user: &str) -> keyring::Result<Vec<u8>>;
+
+    /// Store bytes for the given service and user, replacing any existing value.
+    fn set_secret(&self, service: &str, user: &str, secret: &[u8]) -> keyring::Result<()>;
+
+    /// Delete the stored value for the given service and user.
+    fn delete_credential(&self, service: &str, user: &str) -> keyring::Result<()>;
+
+    /// Attach descriptive attributes to an existing value, as
+    /// `keyring::Entry::update_attributes` does.
+    ///
+    /// Stores ignore attribute names they do not support. The default
+    /// supports none and does nothing.
+    fn update_attributes(
+        &self,
+        service: &str,
+        user: &str,
+        attributes: &HashMap<&str, &str>,
+    ) -> keyring::Result<()> {
+        let _ = (service, user, attributes);
+        Ok(())
+    }
+
+    /// Durably persist every write made so far before returning.
+    ///
+    /// Called before part 1 is written, so a credential is never committed
+    /// while a later part is still buffered. The default does nothing,
+    /// which suits stores whose writes are durable on return.
+    fn flush(&self) -> keyring::Result<()> {
+        Ok(())
+    }
+}
+
+/// The default backend, storing each part as a platform keyring entry.
+///
+/// Entries use it unless given another backend; it is public for
+/// [`Entry::migrate_backend`](crate::Entry::migrate_backend).
+#[derive(Debug, Default)]
+pub struct KeyringBackend {
+    target: Option<String>,
+}
+
+impl KeyringBackend {
+    /// Create a backend whose entries use the given keyring target, as
+    /// [`EntryBuilder::target`](crate::EntryBuilder::target) sets.
+    pub fn new(target: Option<&str>) -> Self {
+        Self {
+            target: target.map(str::to_string),
+        }
+    }
+
+    fn entry(&self, service: &str, user: &str) -> keyring::Result<keyring::Entry> {
+        match &self.target {
+            Some(target) => keyring::Entry::new_with_target(target, service, user),
+            None => keyring::Entry::new(service, user),
+        }
+    }
+}
+
+impl Backend for KeyringBackend {
+    fn get_secret(&self, service: &str, user: &str) -> keyring::Result<Vec<u8>> {
+        self.entry(service, user)?.get_secret()
+    }
+
+    fn set_secret(&self, service: &str, user: &str, secret: &[u8]) -> keyring::Result<()> {
+        self.entry(service, user)?.set_secret(secret)
+    }
+
+    fn delete_credential(&self, service: &str, user: &str) -> keyring::Result<()> {
+        self.entry(service, user)?.delete_credential()
+    }
+
+    fn update_attributes(
+        &self,
+        service: &str,
+        user: &str,
+        attributes: &HashMap<&str, &str>,
+    ) -> keyring::Result<()> {
+        // keyring's Apple stores ignore every attribute after reading the
+        // secret back, so skip the wasted round trip
+        if cfg!(any(target_os = "macos", target_os = "ios")) {
+            return Ok(());
+        }
+        self.entry(service, user)?.update_attributes(attributes)
+    }
+
+    // keyring has no flush: each platform call returns once the store has
+    // taken the write, which is the strongest ordering it offers
+}
+
+/// The `keyring` major version this crate is built against.
+///
+/// Kept in step with the `keyring` requirement in Cargo.toml.
+const KEYRING_VERSION: &str = "3";
+
+/// The store `keyring` uses by default with the features this crate
+/// enables: only `apple-native`, so other platforms get keyring's mock.
+#[cfg(target_os = "macos")]
+const DEFAULT_BACKEND: &str = "macOS Keychain";
+#[cfg(target_os = "ios")]
+const DEFAULT_BACKEND: &str = "iOS Keychain";
+#[cfg(not(any(target_os = "macos", target_os = "ios")))]
+const DEFAULT_BACKEND: &str = "keyring mock store";
+
+/// Which credential store and library versions are compiled in, returned
+/// by [`backend_info`](crate::backend_info).
+///
+/// Meant for bug reports: the [`Display`](fmt::Display) form is one line
+/// naming all three.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackendInfo {
+    /// The store entries use unless given another backend, such as
+    /// "macOS Keychain".
+    pub backend: &'static str,
+    /// The `keyring` major version, such as "3".
+    pub keyring_version: &'static str,
+    /// This crate's version.
+    pub crate_version: &'static str,
+}
+
+impl BackendInfo {
+    pub(crate) fn current() -> Self {
+        Self {
+            backend: DEFAULT_BACKEND,
+            keyring_version: KEYRING_VERSION,
+            crate_version: env!("CARGO_PKG_VERSION"),
+        }
+    }
+}
+
+impl fmt::Display for BackendInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} via keyring {} (keyring-cursed {})",
+            self.backend, self.keyring_version, self.crate_version
+        )
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+pub use spy::{Operation, SpyBackend};
+
+#[cfg(any(test, feature = "testing"))]
+mod spy {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex, MutexGuard};
+
+    use super::Backend;
+
+    /// A backend call recorded by [`SpyBackend`].
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum Operation {
+        /// A read of the given service and user.
+        Get { service: String, user: String },
+        /// A write of `secret` to the given service and user.
+        Set {
+            service: String,
+            user: String,
+            secret: Vec<u8>,
+        },
+        /// A delete of the given service and user.
+        Delete { service: String, user: String },
+        /// An update of the given service and user's attributes, sorted by
+        /// name.
+        UpdateAttributes {
+            service: String,
+            user: String,
+            attributes: Vec<(String, String)>,
+        },
+    }
+
+    impl Operation {
+        /// The user name the operation addressed.
+        pub fn user(&self) -> &str {
+            match self {
+                Operation::Get { user, .. }
+                | Operation::Set { user, .. }
+                | Operation::Delete { user, .. }
+                | Operation::UpdateAttributes { user, .. } => user,
+            }
+        }
+    }
+
+    #[derive(Debug, Default)]
+    struct SpyState {
+        secrets: HashMap<(String, String), Vec<u8>>,
+        operations: Vec<Operation>,
+    }
+
+    /// An in-memory backend that records every call it receives.
+    ///
+    /// Clones share the same storage and log, so keep a clone to inspect
+    /// the operations after handing one to [`Entry::with_backend`](crate::Entry::with_backend).
+    #[derive(Debug, Clone, Default)]
+    pub struct SpyBackend {
+        state: Arc<Mutex<SpyState>>,
+    }
+
+    impl SpyBackend {
+        /// Create an empty backend.
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// All operations recorded so far, oldest first.
+        pub fn operations(&self) -> Vec<Operation> {
+            self.state().operations.clone()
+        }
+
+        /// Return and clear the recorded operations.
+        pub fn take_operations(&self) -> Vec<Operation> {
+            std::mem::take(&mut self.state().operations)
+        }
+
+        /// Whether a value is stored for the given service and user.
+        ///
+        /// This is not recorded as an operation.
+        pub fn contains(&self, service: &str, user: &str) -> bool {
+            let key = (service.to_string(), user.to_string());
+            self.state().secrets.contains_key(&key)
+        }
+
+        fn state(&self) -> MutexGuard<'_, SpyState> {
+            self.state.lock().unwrap_or_else(|e| e.into_inner())
+        }
+    }
+
+    impl Backend for SpyBackend {
+        fn get_secret(&self, service: &str, user: &str) -> keyring::Result<Vec<u8>> {
+            let mut state = self.state();
+            state.operations.push(Operation::Get {
+                service: service.to_string(),
+                user: user.to_string(),
+            });
+            let key = (service.to_string(), user.to_string());
+            state
+                .secrets
+                .get(&key)
+                .cloned()
+                .ok_or(keyring::Error::NoEntry)
+        }
+
+        fn set_secret(&self, service: &str, user: &str, secret: &[u8]) -> keyring::Result<()> {
+            let mut state = self.state();
+            state.operations.push(Operation::Set {
+                service: service.to_string(),
+                user: user.to_string(),
+                secret: secret.to_vec(),
+            });
+            let key = (service.to_string(), user.to_string());
+            state.secrets.insert(key, secret.to_vec());
+            Ok(())
+        }
+
+        fn delete_credential(&self, service: &str, user: &str) -> keyring::Result<()> {
+            let mut state = self.state();
+            state.operations.push(Operation::Delete {
+                service: service.to_string(),
+                user: user.to_string(),
+            });
+            let key = (service.to_string(), user.to_string());
+            match state.secrets.remove(&key) {
+                Some(_) => Ok(()),
+                None => Err(keyring::Error::NoEntry),
+            }
+        }
+
+        fn update_attributes(
+            &self,
+            service: &str,
+            user: &str,
+            attributes: &HashMap<&str, &str>,
+        ) -> keyring::Result<()> {
+            let mut state = self.state();
+            let mut attributes: Vec<_> = attributes
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect();
+            attributes.sort();
+            state.operations.push(Operation::UpdateAttributes {
+                service: service.to_string(),
+                user: user.to_string(),
+                attributes,
+            });
+            let key = (service.to_string(), user.to_string());
+            match state.secrets.contains_key(&key) {
+                true => Ok(()),
+                false => Err(keyring::Error::NoEntry),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spy_records_operations_with_arguments() {
+        let spy = SpyBackend::new();
+        spy.set_secret("svc", "alice.1", b"data").unwrap();
+        assert_eq!(spy.get_secret("svc", "alice.1").unwrap(), b"data");
+        spy.delete_credential("svc", "alice.1").unwrap();
+        assert!(matches!(
+            spy.get_secret("svc", "alice.1"),
+            Err(keyring::Error::NoEntry)
+        ));
+
+        let user = || "alice.1".to_string();
+        let service = || "svc".to_string();
+        assert_eq!(
+            spy.operations(),
+            [
+                Operation::Set {
+                    service: service(),
+                    user: user(),
+                    secret: b"data".to_vec()
+                },
+                Operation::Get {
+                    service: service(),
+                    user: user()
+                },
+                Operation::Delete {
+                    service: service(),
+                    user: user()
+                },
+                Operation::Get {
+                    service: service(),
+                    user: user()
+                },
+            ]
+        );
+        assert!(!spy.contains("svc", "alice.1"));
+    }
+
+    #[test]
+    fn test_backend_info_names_versions() {
+        let info = BackendInfo::current();
+        assert_eq!(info.keyring_version, "3");
+        assert_eq!(info.crate_version, env!("CARGO_PKG_VERSION"));
+        let line = info.to_string();
+        assert!(line.starts_with(info.backend));
+        assert!(line.contains("keyring 3"));
+    }
+}
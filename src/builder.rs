@@ -0,0 +1,458 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::clock::Clock;
+use crate::entry::Entry;
+use crate::{Error, Result};
+
+/// Which stored value marks a write as complete.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CommitMarker {
+    /// Part 1 is written last; a readable part 1 means the write finished.
+    #[default]
+    Part1,
+    /// A separate `{user}#complete` entry holding the total is written after
+    /// every part, and deleted before any part is touched by a later write
+    /// or delete. Reads require it, so an interrupted write is reported even
+    /// if part 1 itself was written.
+    Sentinel,
+    /// A `{user}#manifest` entry listing the stored length and SHA-256 of
+    /// every part is written after every part, and deleted before any part
+    /// is touched by a later write or delete. Reads check each part against
+    /// it, so a missing, truncated or altered part is detected even when
+    /// part 1's total is stale.
+    ///
+    /// A credential without a manifest, such as one written before this was
+    /// enabled, is read as under [`CommitMarker::Part1`]. The manifest must
+    /// fit one entry, which limits secrets to roughly one part per 90 bytes
+    /// of the entry size limit.
+    Manifest,
+}
+
+/// The order [`Entry::set_secret`] writes parts in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WriteOrder {
+    /// Parts N down to 2, then part 1 last, so a readable part 1 means
+    /// every other part was written first.
+    #[default]
+    ReverseForCommit,
+    /// Parts 1 up to N, then the commit marker.
+    ///
+    /// Only allowed with [`CommitMarker::Sentinel`] or
+    /// [`CommitMarker::Manifest`], whose marker written after every part
+    /// already commits the write, so part 1 need not go last.
+    Forward,
+}
+
+/// Where [`Entry::set_secret`] cuts a secret into parts.
+///
+/// Reads concatenate the parts whichever was used, so this can be changed
+/// at any time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Chunking {
+    /// Every part but the last holds exactly the chunk size.
+    #[default]
+    Exact,
+    /// Prefer to end a part just after a newline that falls within the last
+    /// eighth of the chunk size, and cut mid-line only when none does.
+    ///
+    /// Parts of a multi-line text secret, such as a PEM bundle, then hold
+    /// whole lines and are readable on their own when inspected. This only
+    /// changes where parts end, never the stored bytes, though a secret can
+    /// need a few more parts.
+    TextAware,
+}
+
+/// Called with the part count and secret size when a write exceeds
+/// [`EntryBuilder::warn_parts_threshold`].
+#[derive(Clone)]
+pub(crate) struct PartsWarning(pub(crate) Arc<dyn Fn(usize, usize) + Send + Sync>);
+
+impl fmt::Debug for PartsWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PartsWarning(..)")
+    }
+}
+
+/// Configuration shared by every entry built from an [`EntryBuilder`].
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Config {
+    pub(crate) target: Option<String>,
+    pub(crate) cache_total: bool,
+    pub(crate) commit_marker: CommitMarker,
+    pub(crate) write_order: WriteOrder,
+    pub(crate) chunking: Chunking,
+    pub(crate) hash_chain: bool,
+    pub(crate) verify_writes: bool,
+    /// The clock to use instead of the system time.
+    pub(crate) clock: Option<Arc<dyn Clock>>,
+    pub(crate) warn_parts_threshold: Option<usize>,
+    pub(crate) on_many_parts: Option<PartsWarning>,
+    /// The per-entry size limit to use instead of the platform's.
+    pub(crate) max_entry_size: Option<usize>,
+    /// The number of digits part numbers are zero-padded to.
+    pub(crate) part_width: Option<usize>,
+    /// Prepended to every user in backend names.
+    pub(crate) namespace: Option<String>,
+    /// The most parts a write may use, below the format's maximum.
+    pub(crate) max_parts: Option<usize>,
+    /// The label every part is tagged with, followed by its part number.
+    pub(crate) label: Option<String>,
+    /// The comment every part is tagged with.
+    pub(crate) comment: Option<String>,
+    /// Whether writes store a parity part.
+    pub(crate) parity: bool,
+}
+
+impl Config {
+    /// The keyring target that each part entry is created with.
+    pub(crate) fn target(&self) -> Option<&str> {
+        self.target.as_deref()
+    }
+}
+
+/// A builder for entries that share a service and configuration.
+///
+/// ```no_run
+/// use keyring_cursed::{Entry, Result};
+///
+/// fn main() -> Result<()> {
+///     let builder = Entry::builder("my-service").cache_total(true);
+///     let entry = builder.build("my-user")?;
+///     entry.set_password("my-secret-password")?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct EntryBuilder {
+    service: String,
+    config: Config,
+}
+
+impl EntryBuilder {
+    /// Create a builder for entries in the given service.
+    pub fn new(service: &str) -> Self {
+        Self {
+            service: service.to_string(),
+            config: Config::default(),
+        }
+    }
+
+    /// Create every part's keyring entry with the given target, as
+    /// `keyring::Entry::new_with_target` does.
+    ///
+    /// What the target means depends on the platform's credential store.
+    pub fn target(mut self, target: &str) -> Self {
+        self.config.target = Some(target.to_string());
+        self
+    }
+
+    /// Remember the part count between calls instead of re-reading part 1.
+    ///
+    /// Writing a single-part secret over a cached single part then costs
+    /// one backend write, as with `keyring` itself.
+    ///
+    /// The cache assumes the entry is the only writer of its credential.
+    /// If the credential is modified by anything else, such as another
+    /// process or an entry that is not a clone of this one, call
+    /// [`Entry::invalidate_cache`] before relying on it again.
+    pub fn cache_total(mut self, enabled: bool) -> Self {
+        self.config.cache_total = enabled;
+        self
+    }
+
+    /// Choose which stored value marks a write as complete.
+    ///
+    /// Credentials must be read with the strategy they were written with:
+    /// under [`CommitMarker::Sentinel`], a credential written without a
+    /// marker reads as an interrupted write until it is stored again.
+    /// [`CommitMarker::Manifest`] still reads credentials without one.
+    pub fn commit_marker(mut self, marker: CommitMarker) -> Self {
+        self.config.commit_marker = marker;
+        self
+    }
+
+    /// Choose the order parts are written in. Defaults to
+    /// [`WriteOrder::ReverseForCommit`].
+    ///
+    /// Reads address parts by index, so they handle either order.
+    /// [`Entry::set_secret_resumable`] always writes in reverse, since its
+    /// progress records the lowest part written, and so do
+    /// [`PreparedWrite`](crate::PreparedWrite) commits.
+    /// [`build`](EntryBuilder::build) returns
+    /// [`Error::InvalidArgument`](crate::Error::InvalidArgument) for
+    /// [`WriteOrder::Forward`] under [`CommitMarker::Part1`], which relies
+    /// on part 1 going last.
+    pub fn write_order(mut self, order: WriteOrder) -> Self {
+        self.config.write_order = order;
+        self
+    }
+
+    /// Choose where secrets are cut into parts. Defaults to
+    /// [`Chunking::Exact`].
+    pub fn chunking(mut self, chunking: Chunking) -> Self {
+        self.config.chunking = chunking;
+        self
+    }
+
+    /// Link every part into a hash chain so tampering is detected on read.
+    ///
+    /// Each part's header records a running SHA-256 over the payloads
+    /// before it, and part 1 records the hash over all of them, so a
+    /// reordered or substituted part fails [`Entry::get_secret`] with
+    /// [`Error::CorruptedSecret`](crate::Error::CorruptedSecret). The chain
+    /// needs no key, so it does not stop anyone who rewrites every part.
+    ///
+    /// Chained secrets are verified on read whatever this option says; it
+    /// only controls whether writes add the chain. Each chained header is 67
+    /// bytes longer, which comes out of the chunk size.
+    pub fn hash_chain(mut self, enabled: bool) -> Self {
+        self.config.hash_chain = enabled;
+        self
+    }
+
+    /// Read part 1 back after every write and check it is what was written.
+    ///
+    /// This catches backends that silently store something else, such as a
+    /// value cut short by a size limit, or another user's data when long
+    /// names are truncated onto the same entry. It costs one extra read per
+    /// write, and a mismatch fails the write with
+    /// [`Error::CorruptedSecret`](crate::Error::CorruptedSecret).
+    pub fn verify_writes(mut self, enabled: bool) -> Self {
+        self.config.verify_writes = enabled;
+        self
+    }
+
+    /// Read the time from `clock` instead of the system.
+    #[cfg(any(test, feature = "testing"))]
+    pub fn clock(mut self, clock: Box<dyn Clock>) -> Self {
+        self.config.clock = Some(Arc::from(clock));
+        self
+    }
+
+    /// Warn when a write splits a secret into more than `parts` parts.
+    ///
+    /// After each successful write over the threshold, the callback set
+    /// with [`on_many_parts`](EntryBuilder::on_many_parts) is called once,
+    /// or a warning is logged if there is none. The write itself is not
+    /// affected.
+    pub fn warn_parts_threshold(mut self, parts: usize) -> Self {
+        self.config.warn_parts_threshold = Some(parts);
+        self
+    }
+
+    /// Call `callback` with the part count and secret size in bytes when a
+    /// write exceeds [`warn_parts_threshold`](EntryBuilder::warn_parts_threshold).
+    pub fn on_many_parts(
+        mut self,
+        callback: impl Fn(usize, usize) + Send + Sync + 'static,
+    ) -> Self {
+        self.config.on_many_parts = Some(PartsWarning(Arc::new(callback)));
+        self
+    }
+
+    /// Assume the backend stores at most `bytes` per keyring entry.
+    ///
+    /// This replaces the compiled-in limit for the current platform in
+    /// every size calculation these entries make, headers included, for
+    /// backends with a smaller limit than their platform usually has. When
+    /// it is not set, the compiled default applies; the free functions
+    /// [`max_chunk_size`](crate::max_chunk_size) and
+    /// [`estimate_parts`](crate::estimate_parts) always report the compiled
+    /// defaults. An update still reuses the chunk size recorded by the
+    /// previous write if it fits the new limit.
+    ///
+    /// [`build`](EntryBuilder::build) returns
+    /// [`Error::InvalidArgument`](crate::Error::InvalidArgument) if the
+    /// limit leaves no room for any payload next to the largest header.
+    pub fn max_entry_size(mut self, bytes: usize) -> Self {
+        self.config.max_entry_size = Some(bytes);
+        self
+    }
+
+    /// Refuse writes that would split a secret into more than `parts` parts.
+    ///
+    /// A policy limit below the format's [`MAX_PARTS`](crate::MAX_PARTS),
+    /// for environments where a secret needing that many entries is a bug.
+    /// Such writes fail with
+    /// [`Error::InvalidArgument`](crate::Error::InvalidArgument) before
+    /// anything is written, and [`Entry::max_secret_size`] shrinks to
+    /// match. Reads are not limited. [`build`](EntryBuilder::build) returns
+    /// the same error if `parts` is 0 or above the format's maximum.
+    pub fn max_parts(mut self, parts: usize) -> Self {
+        self.config.max_parts = Some(parts);
+        self
+    }
+
+    /// Zero-pad part numbers in backend names to `digits` digits.
+    ///
+    /// Parts are then stored as `{user}.001`, `{user}.002` and so on for a
+    /// width of 3, which sorts correctly in listings and matches scripts
+    /// that expect a fixed width. By default part numbers are not padded.
+    /// The width is part of every name, so credentials must be read and
+    /// deleted with the width they were written with.
+    ///
+    /// A write needing more parts than `digits` digits can number fails
+    /// with [`Error::InvalidArgument`](crate::Error::InvalidArgument).
+    pub fn part_width(mut self, digits: usize) -> Self {
+        self.config.part_width = Some(digits);
+        self
+    }
+
+    /// Scope every entry to `namespace`, such as a tenant id.
+    ///
+    /// Backend names are built from `{namespace}:{user}` instead of the
+    /// user alone, so entries for the same user in different namespaces
+    /// never see each other's credentials. Callers keep passing the plain
+    /// user. The namespace is percent-encoded like users are, with `:`
+    /// encoded as `%3A` too, so the first `:` always ends it; a namespaced
+    /// credential is therefore also what an entry without a namespace sees
+    /// for the user `{namespace}:{user}`.
+    ///
+    /// [`build`](EntryBuilder::build) returns
+    /// [`Error::InvalidArgument`](crate::Error::InvalidArgument) if the
+    /// namespace is empty.
+    pub fn namespace(mut self, namespace: &str) -> Self {
+        self.config.namespace = Some(namespace.to_string());
+        self
+    }
+
+    /// Tag every part with a label naming it, so the parts are
+    /// recognizable when browsing the credential store.
+    ///
+    /// Each part written is labelled `{label} (part {part} of {total})`
+    /// after it is stored, where the store supports labels. Of the stores
+    /// `keyring` 3 drives, only the Secret Service has them; elsewhere the
+    /// label is ignored. Failing to set it is logged rather than failing
+    /// the write. Parts restored by [`Entry::import_raw`] are not labelled.
+    pub fn label(mut self, label: &str) -> Self {
+        self.config.label = Some(label.to_string());
+        self
+    }
+
+    /// Tag every part with a comment, where the store supports comments.
+    ///
+    /// Of the stores `keyring` 3 drives, the Windows Credential Manager
+    /// shows it as the credential's comment and the Secret Service keeps it
+    /// as a searchable `comment` attribute; elsewhere it is ignored. It is
+    /// applied like [`EntryBuilder::label`].
+    pub fn comment(mut self, comment: &str) -> Self {
+        self.config.comment = Some(comment.to_string());
+        self
+    }
+
+    /// Store an XOR parity part alongside every secret of more than one
+    /// part, so a single lost or damaged part can be rebuilt.
+    ///
+    /// [`Entry::set_secret`] writes the parity part, `{user}#parity`, after
+    /// part 1, and [`Entry::get_secret`] only reads it when a part after
+    /// part 1 is missing or fails its checks, rebuilding that part from
+    /// the parity and the others. If a second part is also lost, the read
+    /// fails with [`Error::CorruptedSecret`](crate::Error::CorruptedSecret).
+    /// Part 1 carries the layout and is the commit point, so it cannot be
+    /// rebuilt this way; see [`Entry::rebuild_part1`]. A parity part left
+    /// by a different write is recognized by its generation and ignored.
+    ///
+    /// This costs one extra entry per secret, about one chunk in size, and
+    /// one extra write. Writes committed by [`PreparedWrite`](crate::PreparedWrite)
+    /// and [`Entry::import_raw`] delete the parity part instead, and only
+    /// [`Entry::get_secret`] and methods built on it use it.
+    #[cfg(feature = "parity")]
+    pub fn parity(mut self, enabled: bool) -> Self {
+        self.config.parity = enabled;
+        self
+    }
+
+    /// Build an entry for the given user.
+    pub fn build(&self, user: &str) -> Result<Entry> {
+        Entry::with_config(&self.service, user, self.config.clone())
+    }
+
+    /// Build an entry for each of `users`, all sharing this configuration.
+    ///
+    /// Fails with [`Error::InvalidUser`] for the first user
+    /// [`build`](EntryBuilder::build) rejects, recording its index.
+    pub fn build_many(&self, users: &[&str]) -> Result<Vec<Entry>> {
+        users
+            .iter()
+            .enumerate()
+            .map(|(index, user)| {
+                self.build(user).map_err(|e| Error::InvalidUser {
+                    index,
+                    source: Box::new(e),
+                })
+            })
+            .collect()
+    }
+
+    /// Delete the credential of each of `users`, continuing past failures.
+    ///
+    /// Each deletion is [`Entry::delete_credential`], so users with nothing
+    /// stored succeed. Returns every user paired with its result, in order,
+    /// so a cleanup job can retry just the failures; a user that cannot be
+    /// built into an entry gets that error. Fails as a whole only if the
+    /// service is empty.
+    pub fn delete_many(&self, users: &[&str]) -> Result<Vec<(String, Result<()>)>> {
+        crate::entry::validate_service(&self.service)?;
+        Ok(users
+            .iter()
+            .map(|user| {
+                let result = self.build(user).and_then(|entry| entry.delete_credential());
+                (user.to_string(), result)
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_validates_user() {
+        let builder = EntryBuilder::new("service");
+        assert!(builder.build("user").is_ok());
+        assert!(builder.build("").is_err());
+        assert!(EntryBuilder::new("").build("user").is_err());
+    }
+
+    #[test]
+    fn test_build_many_reports_first_invalid_index() {
+        let builder = EntryBuilder::new("service").hash_chain(true);
+        let entries = builder.build_many(&["a", "b", "c"]).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert!(Entry::many("service", &[]).unwrap().is_empty());
+
+        let err = Entry::many("service", &["a", "", "c", ""]).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InvalidUser { index: 1, ref source }
+                if matches!(**source, Error::EmptyField(crate::Field::User))
+        ));
+    }
+
+    #[test]
+    fn test_delete_many_reports_each_user() {
+        let results = Entry::delete_many("service", &["a", "", "c"]).unwrap();
+        let users: Vec<_> = results.iter().map(|(user, _)| user.as_str()).collect();
+        assert_eq!(users, ["a", "", "c"]);
+        assert!(results[0].1.is_ok());
+        assert!(matches!(
+            results[1].1,
+            Err(Error::EmptyField(crate::Field::User))
+        ));
+        assert!(results[2].1.is_ok());
+
+        assert!(matches!(
+            Entry::delete_many("", &["a"]),
+            Err(Error::EmptyField(crate::Field::Service))
+        ));
+    }
+
+    #[test]
+    fn test_target_is_passed_through() {
+        assert_eq!(EntryBuilder::new("service").config.target(), None);
+        let config = EntryBuilder::new("service").target("target").config;
+        assert_eq!(config.target(), Some("target"));
+    }
+}
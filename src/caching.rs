@@ -0,0 +1,221 @@
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+
+use crate::entry::Entry;
+use crate::{Error, Result};
+
+/// An entry that keeps its secret in memory between reads, created by
+/// [`Entry::caching`].
+///
+/// Reads within the time to live of the last one are answered from memory
+/// without contacting the credential store, which avoids the latency and,
+/// on macOS, the prompts of a keychain read. This is a trade: the secret
+/// then lives in this process's memory for as long as it is cached, where
+/// a crash dump, swap or a memory disclosure bug can expose it. Only opt in
+/// for credentials read often enough to need it, and keep the time to live
+/// short.
+///
+/// The cached copy is overwritten with zeros whenever it is dropped from
+/// the cache: when it expires, on [`CachingEntry::set_secret`],
+/// [`CachingEntry::delete_credential`] and [`CachingEntry::invalidate`],
+/// and when the last clone of the entry is dropped. Copies returned to
+/// callers are theirs to wipe. Writes made through another entry for the
+/// same credential are not seen until the cached copy expires.
+///
+/// Expiry is judged by the entry's clock, so a test clock set with
+/// `EntryBuilder::clock` controls it too. Clones share the cache.
+#[derive(Clone)]
+pub struct CachingEntry {
+    entry: Entry,
+    ttl: Duration,
+    capacity: usize,
+    cached: Arc<Mutex<Option<Cached>>>,
+}
+
+/// A secret read at `read_at`.
+struct Cached {
+    secret: Vec<u8>,
+    read_at: SystemTime,
+}
+
+impl Drop for Cached {
+    fn drop(&mut self) {
+        wipe(&mut self.secret);
+    }
+}
+
+impl fmt::Debug for CachingEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never print the cached secret, only whether there is one
+        f.debug_struct("CachingEntry")
+            .field("entry", &self.entry)
+            .field("ttl", &self.ttl)
+            .field("capacity", &self.capacity)
+            .field("cached", &self.lock().is_some())
+            .finish()
+    }
+}
+
+impl CachingEntry {
+    /// Retrieve the secret, from memory if a read within the time to live
+    /// cached it.
+    ///
+    /// Otherwise it is read as by [`Entry::get_secret`] and cached, unless
+    /// it is larger than the capacity. Errors are not cached.
+    pub fn get_secret(&self) -> Result<Vec<u8>> {
+        let mut cached = self.lock();
+        let now = self.entry.now();
+        if let Some(hit) = cached.as_ref() {
+            match now.duration_since(hit.read_at) {
+                Ok(age) if age < self.ttl => return Ok(hit.secret.clone()),
+                // Expired, or the clock went back: read afresh
+                _ => *cached = None,
+            }
+        }
+
+        let secret = self.entry.get_secret()?;
+        if secret.len() <= self.capacity {
+            *cached = Some(Cached {
+                secret: secret.clone(),
+                read_at: now,
+            });
+        }
+        Ok(secret)
+    }
+
+    /// Retrieve a password (UTF-8 string), from memory if it is cached.
+    ///
+    /// See [`CachingEntry::get_secret`].
+    pub fn get_password(&self) -> Result<String> {
+        String::from_utf8(self.get_secret()?).map_err(|e| {
+            let mut bytes = e.into_bytes();
+            wipe(&mut bytes);
+            Error::BadEncoding
+        })
+    }
+
+    /// Store a secret as by [`Entry::set_secret`], dropping the cached one.
+    ///
+    /// The cache is cleared before writing, so it is empty afterwards even if
+    /// the write fails part way.
+    pub fn set_secret(&self, secret: impl AsRef<[u8]>) -> Result<()> {
+        let mut cached = self.lock();
+        *cached = None;
+        self.entry.set_secret(secret)
+    }
+
+    /// Store a password (UTF-8 string), dropping the cached secret.
+    pub fn set_password(&self, password: &str) -> Result<()> {
+        self.set_secret(password.as_bytes())
+    }
+
+    /// Delete the credential as by [`Entry::delete_credential`], dropping
+    /// the cached secret.
+    pub fn delete_credential(&self) -> Result<()> {
+        let mut cached = self.lock();
+        *cached = None;
+        self.entry.delete_credential()
+    }
+
+    /// Wipe and drop the cached secret, so the next read goes to the store.
+    pub fn invalidate(&self) {
+        *self.lock() = None;
+    }
+
+    /// The wrapped entry. Writes made through it bypass the cache.
+    pub fn entry(&self) -> &Entry {
+        &self.entry
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<Cached>> {
+        self.cached.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Entry {
+    /// Cache this entry's secret in memory for up to `ttl` after each read.
+    ///
+    /// Secrets larger than `capacity` bytes are never cached. See
+    /// [`CachingEntry`] for the security tradeoff this makes.
+    pub fn caching(self, ttl: Duration, capacity: usize) -> CachingEntry {
+        CachingEntry {
+            entry: self,
+            ttl,
+            capacity,
+            cached: Arc::new(Mutex::new(None)),
+        }
+    }
+}
+
+/// Overwrite `buf` with zeros, including any spare capacity.
+fn wipe(buf: &mut Vec<u8>) {
+    buf.clear();
+    buf.resize(buf.capacity(), 0);
+    // Keep the optimizer from dropping writes to memory about to be freed
+    std::hint::black_box(buf.as_slice());
+    buf.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::SpyBackend;
+    use crate::clock::ManualClock;
+    use crate::test_util::{spy_entry, take_ops, write_raw, SERVICE};
+    use std::time::UNIX_EPOCH;
+
+    fn caching_entry(user: &str, ttl: u64) -> (CachingEntry, SpyBackend, ManualClock) {
+        let clock = ManualClock::new(UNIX_EPOCH);
+        let spy = SpyBackend::new();
+        let entry = Entry::builder(SERVICE)
+            .clock(Box::new(clock.clone()))
+            .build(user)
+            .unwrap()
+            .with_backend(Box::new(spy.clone()))
+            .caching(Duration::from_secs(ttl), 64);
+        (entry, spy, clock)
+    }
+
+    #[test]
+    fn test_reads_within_ttl_come_from_memory() {
+        let (entry, spy, clock) = caching_entry("caching", 60);
+        entry.set_password("cached").unwrap();
+        assert_eq!(entry.get_password().unwrap(), "cached");
+        take_ops(&spy);
+
+        write_raw(entry.entry(), 1, b"v1/1/1|changed behind its back");
+        assert_eq!(entry.get_password().unwrap(), "cached");
+        assert!(take_ops(&spy).iter().all(|(kind, _)| *kind == "set"));
+
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(entry.get_password().unwrap(), "changed behind its back");
+    }
+
+    #[test]
+    fn test_writes_and_deletes_drop_the_cached_secret() {
+        let (entry, _spy, _clock) = caching_entry("caching-writes", 60);
+        entry.set_password("old").unwrap();
+        assert_eq!(entry.get_password().unwrap(), "old");
+        entry.set_password("new").unwrap();
+        assert_eq!(entry.get_password().unwrap(), "new");
+
+        entry.clone().delete_credential().unwrap();
+        assert!(matches!(
+            entry.get_secret(),
+            Err(Error::Keyring(keyring::Error::NoEntry))
+        ));
+        assert!(!format!("{:?}", entry).contains("new"));
+    }
+
+    #[test]
+    fn test_secrets_over_capacity_are_read_through() {
+        let (entry, spy) = spy_entry("caching-capacity");
+        let entry = entry.caching(Duration::from_secs(60), 4);
+        entry.set_secret(b"too large").unwrap();
+        take_ops(&spy);
+        entry.get_secret().unwrap();
+        entry.get_secret().unwrap();
+        assert_eq!(take_ops(&spy).len(), 2);
+    }
+}
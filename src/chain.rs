@@ -0,0 +1,149 @@
+//! The optional hash chain linking every part of a secret.
+//!
+//! Links are a running SHA-256 over the payloads: link 1 is the hash of
+//! part 1's payload, and link `k` hashes link `k - 1` followed by part `k`'s
+//! payload. Part `k > 1` stores link `k - 1`, so each part vouches for
+//! everything before it, and part 1 stores the final link, closing the
+//! chain over the last part too.
+
+use crate::format::Header;
+use crate::sha256::{Sha256, DIGEST_LEN};
+use crate::{Error, Result};
+
+type Link = [u8; DIGEST_LEN];
+
+fn next_link(previous: Option<&Link>, payload: &[u8]) -> Link {
+    let mut hasher = Sha256::new();
+    if let Some(previous) = previous {
+        hasher.update(previous);
+    }
+    hasher.update(payload);
+    hasher.finalize()
+}
+
+/// The link each part's header stores, indexed from part 1.
+pub(crate) fn links<'a>(payloads: impl Iterator<Item = &'a [u8]>) -> Vec<Link> {
+    let mut running: Vec<Link> = Vec::new();
+    for payload in payloads {
+        let link = next_link(running.last(), payload);
+        running.push(link);
+    }
+
+    // Part k stores link k - 1; part 1 takes the last link instead
+    let Some(&last) = running.last() else {
+        return running;
+    };
+    running.pop();
+    running.insert(0, last);
+    running
+}
+
+/// Verifies the chain as parts are read in order.
+///
+/// Does nothing if part 1 carries no link, i.e. the secret was written
+/// without a hash chain.
+#[derive(Debug, Default)]
+pub(crate) struct ChainCheck {
+    root: Option<Link>,
+    running: Option<Link>,
+}
+
+impl ChainCheck {
+    /// Check part `part` of `total`, which must follow the previous call's part.
+    pub(crate) fn verify(
+        &mut self,
+        header: &Header,
+        part: usize,
+        total: usize,
+        payload: &[u8],
+    ) -> Result<()> {
+        if part == 1 {
+            self.root = header.chain;
+        }
+        if self.root.is_none() {
+            return Ok(());
+        }
+
+        if part > 1 && header.chain != self.running {
+            return Err(Error::CorruptedSecret(format!(
+                "hash chain broken at part {}",
+                part
+            )));
+        }
+        self.running = Some(next_link(self.running.as_ref(), payload));
+
+        if part == total && self.running != self.root {
+            return Err(Error::CorruptedSecret(
+                "hash chain on part 1 does not match the last part".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Take part `part` of `total`, rebuilt rather than read, on trust.
+    ///
+    /// It has no stored link to check, but the next part's link, or part
+    /// 1's if it is the last, vouches for its payload.
+    pub(crate) fn recovered(&mut self, part: usize, total: usize, payload: &[u8]) -> Result<()> {
+        if self.root.is_none() {
+            return Ok(());
+        }
+        self.running = Some(next_link(self.running.as_ref(), payload));
+        if part == total && self.running != self.root {
+            return Err(Error::CorruptedSecret(
+                "hash chain on part 1 does not match the last part".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(payloads: &[&[u8]]) -> Vec<Header> {
+        let total = payloads.len();
+        links(payloads.iter().copied())
+            .into_iter()
+            .enumerate()
+            .map(|(i, link)| Header {
+                chain: Some(link),
+                ..Header::new(i + 1, total)
+            })
+            .collect()
+    }
+
+    fn verify_all(headers: &[Header], payloads: &[&[u8]]) -> Result<()> {
+        let mut check = ChainCheck::default();
+        for (i, (header, payload)) in headers.iter().zip(payloads).enumerate() {
+            check.verify(header, i + 1, headers.len(), payload)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_chain_verifies() {
+        let payloads: [&[u8]; 3] = [b"one", b"two", b"three"];
+        assert!(verify_all(&headers(&payloads), &payloads).is_ok());
+
+        let single: [&[u8]; 1] = [b"only"];
+        assert!(verify_all(&headers(&single), &single).is_ok());
+    }
+
+    #[test]
+    fn test_chain_detects_substitution() {
+        let payloads: [&[u8]; 3] = [b"one", b"two", b"three"];
+        let headers = headers(&payloads);
+        assert!(verify_all(&headers, &[b"one", b"TWO", b"three"]).is_err());
+        assert!(verify_all(&headers, &[b"ONE", b"two", b"three"]).is_err());
+        // Only part 1's link covers the last payload
+        assert!(verify_all(&headers, &[b"one", b"two", b"THREE"]).is_err());
+    }
+
+    #[test]
+    fn test_unchained_secret_is_not_checked() {
+        let headers = [Header::new(1, 2), Header::new(2, 2)];
+        assert!(verify_all(&headers, &[b"any", b"thing"]).is_ok());
+    }
+}
@@ -1,71 +1,231 @@
-/// Returns the maximum payload size per chunk for the current platform.
-/// This accounts for the metadata header overhead.
-pub fn max_chunk_size() -> usize {
-    max_raw_size() - max_header_overhead()
+use crate::format::{encode_part, Header};
+use crate::sha256::DIGEST_LEN;
+
+/// Maximum number of parts a secret may be split into.
+///
+/// Part numbers stay within four digits, which bounds the header size that
+/// every chunk size is derived from. Writes needing more parts are refused.
+pub const MAX_PARTS: usize = 9999;
+
+/// A platform credential store, for sizing secrets stored on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Platform {
+    /// Windows Credential Manager.
+    Windows,
+    /// The macOS keychain.
+    MacOs,
+    /// The iOS keychain.
+    Ios,
+    /// Secret Service or keyutils on Linux.
+    Linux,
+    /// Any other platform, sized conservatively.
+    Other,
 }
 
-/// Maximum raw secret size the platform can store per entry.
-fn max_raw_size() -> usize {
-    #[cfg(target_os = "windows")]
-    {
-        2048 // Windows Credential Manager ~2.5KB limit, leave margin
+impl Platform {
+    /// The platform this crate was compiled for.
+    pub fn current() -> Self {
+        if cfg!(target_os = "windows") {
+            Platform::Windows
+        } else if cfg!(target_os = "macos") {
+            Platform::MacOs
+        } else if cfg!(target_os = "ios") {
+            Platform::Ios
+        } else if cfg!(target_os = "linux") {
+            Platform::Linux
+        } else {
+            Platform::Other
+        }
     }
 
-    #[cfg(target_os = "macos")]
-    {
-        16384 // macOS keychain practical limit
+    /// Maximum raw secret size the platform can store per entry.
+    pub(crate) fn max_raw_size(self) -> usize {
+        match self {
+            Platform::Windows => 2048, // Windows Credential Manager ~2.5KB limit, leave margin
+            Platform::MacOs => 16384,  // macOS keychain practical limit
+            Platform::Ios => 16384,
+            Platform::Linux => 8192, // Secret Service varies, conservative default
+            Platform::Other => 2048, // Safe fallback for unknown platforms
+        }
     }
 
-    #[cfg(target_os = "ios")]
-    {
-        16384
+    /// Maximum length of the name an entry is stored under, in characters,
+    /// if the platform has one.
+    pub(crate) fn max_name_len(self) -> Option<usize> {
+        match self {
+            Platform::Windows => Some(32767), // CRED_MAX_GENERIC_TARGET_NAME_LENGTH
+            Platform::Linux => Some(4095),    // keyutils descriptions, NUL excluded
+            Platform::MacOs | Platform::Ios | Platform::Other => None,
+        }
     }
+}
 
-    #[cfg(target_os = "linux")]
-    {
-        8192 // Secret Service varies, conservative default
-    }
+/// Returns the maximum payload size per chunk for the current platform.
+/// This accounts for the metadata header overhead.
+pub fn max_chunk_size() -> usize {
+    max_chunk_size_for(Platform::current())
+}
 
-    #[cfg(not(any(
-        target_os = "windows",
-        target_os = "macos",
-        target_os = "ios",
-        target_os = "linux"
-    )))]
-    {
-        2048 // Safe fallback for unknown platforms
-    }
+/// Like [`max_chunk_size`], for the given platform.
+pub fn max_chunk_size_for(platform: Platform) -> usize {
+    chunk_limit(platform.max_raw_size(), false, false)
+}
+
+/// How many entries a secret of `secret_len` bytes occupies on `platform`
+/// when written with the default settings.
+pub fn estimate_parts(secret_len: usize, platform: Platform) -> usize {
+    chunks_needed(secret_len, max_chunk_size_for(platform))
 }
 
-/// Maximum overhead for the header format "{part}/{total}|"
-/// Assuming up to 9999 parts, header is at most "9999/9999|" = 10 bytes
-fn max_header_overhead() -> usize {
-    10
+/// The raw size limit per entry compiled in for the current platform.
+pub(crate) fn max_raw_size() -> usize {
+    Platform::current().max_raw_size()
 }
 
-/// Calculate how many chunks are needed for a given data size.
-pub fn chunks_needed(data_len: usize) -> usize {
+/// The largest payload that fits an entry of `raw_size` bytes, leaving room
+/// for a hash chain link in every header and an expiry on part 1 as
+/// requested. Returns 0 if not even the header fits.
+pub(crate) fn chunk_limit(raw_size: usize, chained: bool, expiring: bool) -> usize {
+    raw_size.saturating_sub(max_header_overhead(raw_size, chained, expiring))
+}
+
+/// Maximum overhead for the header format "v1/{part}/{total}/c={chunk_size}/s={slot}/t=1/g={generation}|"
+/// Assuming up to [`MAX_PARTS`] parts, the largest header is part 1 recording the
+/// platform's raw size, the alternate slot, a content type and the largest
+/// generation, e.g. "v1/9999/9999/c=16384/s=1/t=1/g=4294967295|" = 42 bytes, plus a
+/// 67-byte "/h=..." field when the secret is hash chained and up to 23 bytes
+/// of "/x=..." when it expires
+fn max_header_overhead(raw_size: usize, chained: bool, expiring: bool) -> usize {
+    let largest = Header {
+        chunk_size: Some(raw_size),
+        chain: chained.then_some([0; DIGEST_LEN]),
+        expires: expiring.then_some(u64::MAX),
+        slot: 1,
+        typed: true,
+        generation: Some(u32::MAX),
+        ..Header::new(MAX_PARTS, MAX_PARTS)
+    };
+    encode_part(&largest, &[]).len()
+}
+
+/// Calculate how many chunks of `chunk_size` are needed for a given data size.
+///
+/// The result is not capped; writes check it against [`MAX_PARTS`].
+pub fn chunks_needed(data_len: usize, chunk_size: usize) -> usize {
     if data_len == 0 {
         return 1; // Even empty data needs one chunk
     }
-    let chunk_size = max_chunk_size();
-    (data_len + chunk_size - 1) / chunk_size
+    data_len.div_ceil(chunk_size)
+}
+
+/// The byte range part `part` of `total` holds when `data_len` bytes are
+/// split into chunks of `chunk_size`.
+///
+/// # Panics
+///
+/// If `part` is 0 or above `total`, or `total` is not
+/// [`chunks_needed`]`(data_len, chunk_size)`.
+pub fn part_range(
+    part: usize,
+    total: usize,
+    data_len: usize,
+    chunk_size: usize,
+) -> std::ops::Range<usize> {
+    assert!(
+        (1..=total).contains(&part),
+        "part {} is not within 1 to {}",
+        part,
+        total
+    );
+    assert_eq!(
+        total,
+        chunks_needed(data_len, chunk_size),
+        "{} bytes in chunks of {} is not {} parts",
+        data_len,
+        chunk_size,
+        total
+    );
+    let start = (part - 1) * chunk_size;
+    start..std::cmp::min(start + chunk_size, data_len)
+}
+
+/// The byte range of each chunk when `data_len` bytes are split into
+/// chunks of `chunk_size`, one range per part.
+pub(crate) fn chunk_ranges(
+    data_len: usize,
+    chunk_size: usize,
+) -> impl Iterator<Item = std::ops::Range<usize>> {
+    let total = chunks_needed(data_len, chunk_size);
+    (1..=total).map(move |part| part_range(part, total, data_len, chunk_size))
+}
+
+/// Chunk ranges for [`Chunking::TextAware`](crate::Chunking::TextAware).
+///
+/// Each part ends just after the last newline in the final eighth of its
+/// `chunk_size` bytes, or at `chunk_size` if that window holds none, so no
+/// part is larger than with [`chunk_ranges`].
+pub(crate) fn text_ranges(data: &[u8], chunk_size: usize) -> Vec<std::ops::Range<usize>> {
+    let window = (chunk_size / 8).max(1);
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    while data.len() - start > chunk_size {
+        let end = start + chunk_size;
+        let cut = data[end - window..end]
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(end, |i| end - window + i + 1);
+        ranges.push(start..cut);
+        start = cut;
+    }
+    ranges.push(start..data.len());
+    ranges
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_part_range_boundaries() {
+        assert_eq!(part_range(1, 1, 0, 10), 0..0);
+        assert_eq!(part_range(1, 1, 10, 10), 0..10);
+        assert_eq!(part_range(1, 2, 11, 10), 0..10);
+        assert_eq!(part_range(2, 2, 11, 10), 10..11);
+        assert_eq!(part_range(3, 3, 30, 10), 20..30);
+        let ranges: Vec<_> = chunk_ranges(25, 10).collect();
+        assert_eq!(ranges, [0..10, 10..20, 20..25]);
+    }
+
+    #[test]
+    #[should_panic(expected = "not within")]
+    fn test_part_range_rejects_part_zero() {
+        part_range(0, 1, 10, 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "is not 3 parts")]
+    fn test_part_range_rejects_wrong_total() {
+        part_range(1, 3, 11, 10);
+    }
+
     #[test]
     fn test_chunks_needed() {
         let chunk_size = max_chunk_size();
 
-        assert_eq!(chunks_needed(0), 1);
-        assert_eq!(chunks_needed(1), 1);
-        assert_eq!(chunks_needed(chunk_size), 1);
-        assert_eq!(chunks_needed(chunk_size + 1), 2);
-        assert_eq!(chunks_needed(chunk_size * 3), 3);
-        assert_eq!(chunks_needed(chunk_size * 3 + 1), 4);
+        assert_eq!(chunks_needed(0, chunk_size), 1);
+        assert_eq!(chunks_needed(1, chunk_size), 1);
+        assert_eq!(chunks_needed(chunk_size, chunk_size), 1);
+        assert_eq!(chunks_needed(chunk_size + 1, chunk_size), 2);
+        assert_eq!(chunks_needed(chunk_size * 3, chunk_size), 3);
+        assert_eq!(chunks_needed(chunk_size * 3 + 1, chunk_size), 4);
+    }
+
+    #[test]
+    fn test_chunk_ranges() {
+        let ranges = |len| chunk_ranges(len, 4).collect::<Vec<_>>();
+        assert_eq!(ranges(0), vec![0..0]);
+        assert_eq!(ranges(4), vec![0..4]);
+        assert_eq!(ranges(9), vec![0..4, 4..8, 8..9]);
     }
 
     #[test]
@@ -73,4 +233,83 @@ mod tests {
         assert!(max_chunk_size() > 0);
         assert!(max_chunk_size() >= 1000); // Should be at least 1KB usable
     }
+
+    #[test]
+    fn test_estimate_parts_per_platform() {
+        assert_eq!(max_chunk_size_for(Platform::Windows), 2007);
+        assert_eq!(max_chunk_size_for(Platform::MacOs), 16342);
+        assert_eq!(max_chunk_size_for(Platform::Ios), 16342);
+        assert_eq!(max_chunk_size_for(Platform::Linux), 8151);
+        assert_eq!(max_chunk_size_for(Platform::Other), 2007);
+        assert_eq!(max_chunk_size_for(Platform::current()), max_chunk_size());
+
+        assert_eq!(estimate_parts(10_000, Platform::Windows), 5);
+        assert_eq!(estimate_parts(10_000, Platform::MacOs), 1);
+        assert_eq!(estimate_parts(10_000, Platform::Ios), 1);
+        assert_eq!(estimate_parts(10_000, Platform::Linux), 2);
+        assert_eq!(estimate_parts(10_000, Platform::Other), 5);
+        assert_eq!(estimate_parts(0, Platform::Windows), 1);
+    }
+
+    #[test]
+    fn test_max_parts_boundary() {
+        let size = max_chunk_size_for(Platform::Windows);
+        assert_eq!(
+            estimate_parts(size * MAX_PARTS, Platform::Windows),
+            MAX_PARTS
+        );
+        assert_eq!(
+            estimate_parts(size * MAX_PARTS + 1, Platform::Windows),
+            MAX_PARTS + 1
+        );
+    }
+
+    #[test]
+    fn test_text_ranges_prefer_newlines_near_the_limit() {
+        // A newline in the last eighth of a 16-byte chunk is used
+        let data = b"aaaaaaaaaaaaaa\nbbbbbbbbbbbbbbbbbbb";
+        assert_eq!(text_ranges(data, 16), [0..15, 15..31, 31..34]);
+
+        // Too early to be in the window, so the cut is exact
+        let data = b"aaaa\nbbbbbbbbbbbbbbbbbbbb";
+        assert_eq!(text_ranges(data, 16), [0..16, 16..25]);
+
+        assert_eq!(text_ranges(b"", 16), vec![0..0; 1]);
+        assert_eq!(text_ranges(&[b'\n'; 32], 16), [0..16, 16..32]);
+    }
+
+    #[test]
+    fn test_chunk_limit_of_tiny_entries() {
+        assert_eq!(chunk_limit(16, false, false), 0);
+        assert_eq!(chunk_limit(1024, false, false), 1024 - 41);
+    }
+
+    #[test]
+    fn test_max_header_fits_overhead() {
+        let header = Header {
+            chunk_size: Some(max_chunk_size()),
+            generation: Some(u32::MAX),
+            ..Header::new(MAX_PARTS, MAX_PARTS)
+        };
+        assert!(
+            encode_part(&header, &[]).len() <= max_header_overhead(max_raw_size(), false, false)
+        );
+
+        let extended = Header {
+            chain: Some([0xff; DIGEST_LEN]),
+            expires: Some(u64::MAX),
+            ..header
+        };
+        assert!(
+            encode_part(&extended, &[]).len() <= max_header_overhead(max_raw_size(), true, true)
+        );
+        assert_eq!(
+            max_chunk_size() - chunk_limit(max_raw_size(), true, false),
+            67
+        );
+        assert_eq!(
+            max_chunk_size() - chunk_limit(max_raw_size(), false, true),
+            23
+        );
+    }
 }
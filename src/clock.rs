@@ -0,0 +1,82 @@
+use std::fmt;
+use std::time::SystemTime;
+
+/// A source of the current time, for anything stored with a timestamp.
+pub trait Clock: Send + Sync + fmt::Debug {
+    /// The current time.
+    fn now(&self) -> SystemTime;
+}
+
+/// The default clock, reading the system time.
+#[derive(Debug, Default)]
+pub(crate) struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+pub use manual::ManualClock;
+
+#[cfg(any(test, feature = "testing"))]
+mod manual {
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, SystemTime};
+
+    use super::Clock;
+
+    /// A clock that only moves when told to.
+    ///
+    /// Clones share the same time, so keep a clone to advance it after
+    /// handing one to [`EntryBuilder::clock`](crate::EntryBuilder::clock).
+    #[derive(Debug, Clone)]
+    pub struct ManualClock {
+        now: Arc<Mutex<SystemTime>>,
+    }
+
+    impl ManualClock {
+        /// Create a clock stopped at `start`.
+        pub fn new(start: SystemTime) -> Self {
+            Self {
+                now: Arc::new(Mutex::new(start)),
+            }
+        }
+
+        /// Set the current time.
+        pub fn set(&self, now: SystemTime) {
+            *self.now.lock().unwrap_or_else(|e| e.into_inner()) = now;
+        }
+
+        /// Move the current time forward by `by`.
+        pub fn advance(&self, by: Duration) {
+            *self.now.lock().unwrap_or_else(|e| e.into_inner()) += by;
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> SystemTime {
+            *self.now.lock().unwrap_or_else(|e| e.into_inner())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+
+    #[test]
+    fn test_manual_clock_moves_only_when_told() {
+        let clock = ManualClock::new(UNIX_EPOCH);
+        let shared = clock.clone();
+        assert_eq!(shared.now(), UNIX_EPOCH);
+
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(shared.now(), UNIX_EPOCH + Duration::from_secs(5));
+        clock.set(UNIX_EPOCH);
+        assert_eq!(shared.now(), UNIX_EPOCH);
+    }
+}
@@ -0,0 +1,90 @@
+use crate::chunk::{chunk_limit, chunks_needed, Platform};
+use crate::entry::Entry;
+use crate::{Error, Result};
+
+/// The most parts [`Entry::compat_write`] will split a secret into.
+pub(crate) const MAX_COMPAT_PARTS: usize = 64;
+
+impl Entry {
+    /// Store a secret so that clients on every one of `platforms` can read
+    /// it.
+    ///
+    /// Each platform assumes its own entry size, so a secret chunked for
+    /// one may be unreadable by a client that expects smaller parts. This
+    /// writes at the smallest raw entry size among `platforms` and this
+    /// entry's own limit, trading more parts, and so more backend writes
+    /// and reads, for universal readability. Later [`Entry::set_secret`]
+    /// calls keep the smaller chunk size recorded on part 1.
+    ///
+    /// Returns [`Error::InvalidArgument`] without writing anything if
+    /// `platforms` is empty, or if the secret would need more than 64
+    /// parts at that size.
+    pub fn compat_write(&self, secret: impl AsRef<[u8]>, platforms: &[Platform]) -> Result<()> {
+        let secret = secret.as_ref();
+        let Some(smallest) = platforms
+            .iter()
+            .map(|platform| platform.max_raw_size())
+            .min()
+        else {
+            return Err(Error::InvalidArgument(
+                "compat_write needs at least one platform".into(),
+            ));
+        };
+        let raw_size = smallest.min(self.max_entry_size);
+
+        let parts = chunks_needed(secret.len(), chunk_limit(raw_size, self.hash_chain, false));
+        if parts > MAX_COMPAT_PARTS {
+            return Err(Error::InvalidArgument(format!(
+                "secret needs {} parts at {} bytes per entry, which exceeds the \
+                 compatible write limit ({})",
+                parts, raw_size, MAX_COMPAT_PARTS
+            )));
+        }
+
+        // A narrower copy of this entry; clones share the total cache
+        let mut narrow = self.clone();
+        narrow.max_entry_size = raw_size;
+        narrow.set_secret(secret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{part_exists, spy_entry};
+
+    #[test]
+    fn test_compat_write_uses_smallest_platform() {
+        let (entry, spy) = spy_entry("compat-smallest");
+        let windows = crate::max_chunk_size_for(Platform::Windows);
+        let secret = vec![7; windows * 2 + 1];
+        entry
+            .compat_write(&secret, &[Platform::MacOs, Platform::Windows])
+            .unwrap();
+        assert_eq!(entry.part_count().unwrap(), 3);
+        assert!(part_exists(&spy, &entry, 3));
+        assert_eq!(entry.get_secret().unwrap(), secret);
+
+        // The recorded chunk size sticks for plain writes
+        entry.set_secret(&secret).unwrap();
+        assert_eq!(entry.part_count().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_compat_write_rejects_empty_set_and_too_many_parts() {
+        let (entry, spy) = spy_entry("compat-limits");
+        assert!(matches!(
+            entry.compat_write(b"x", &[]),
+            Err(Error::InvalidArgument(_))
+        ));
+        let windows = crate::max_chunk_size_for(Platform::Windows);
+        assert!(matches!(
+            entry.compat_write(
+                vec![0; windows * MAX_COMPAT_PARTS + 1],
+                &[Platform::Windows]
+            ),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(!part_exists(&spy, &entry, 1));
+    }
+}
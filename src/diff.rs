@@ -0,0 +1,125 @@
+use crate::entry::{encode_layout_part, Entry};
+use crate::Result;
+
+/// How the stored parts compare with a fresh write of a secret, returned
+/// by [`Entry::diff_layout`].
+///
+/// Every part index of either layout is in one list, in ascending order,
+/// except that a part moving off the alternate names is both removed and
+/// added.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LayoutDiff {
+    /// Parts whose stored bytes the write would leave as they are.
+    pub unchanged: Vec<usize>,
+    /// Parts the write would overwrite with different bytes.
+    pub changed: Vec<usize>,
+    /// Parts the write would create.
+    pub added: Vec<usize>,
+    /// Parts the write would delete.
+    pub removed: Vec<usize>,
+}
+
+impl LayoutDiff {
+    /// Whether the stored credential differs from a fresh write at all.
+    pub fn needs_write(&self) -> bool {
+        !(self.changed.is_empty() && self.added.is_empty() && self.removed.is_empty())
+    }
+
+    /// The number of backend writes and deletes the write would make.
+    pub fn disruption(&self) -> usize {
+        self.changed.len() + self.added.len() + self.removed.len()
+    }
+}
+
+impl Entry {
+    /// Compare the stored parts with what [`Entry::set_secret`] would
+    /// write for `secret` now.
+    ///
+    /// The layout is planned exactly as the write would plan it, reusing
+    /// the chunk size part 1 records, and every part is encoded and
+    /// compared byte for byte with the stored one, headers included, so a
+    /// changed part 1 reveals a different total, expiry or content type
+    /// flag too. The generation every write stamps is left out of the
+    /// comparison. Parts of a secret stored by [`Entry::replace_secret`] live
+    /// under the alternate names, which a plain write moves off, so those
+    /// count as removed and the new ones as added. Commit markers are not
+    /// compared. Nothing is written.
+    ///
+    /// Fails like [`Entry::set_secret`] if the secret cannot be stored, or
+    /// if part 1 cannot be decoded.
+    pub fn diff_layout(&self, secret: &[u8]) -> Result<LayoutDiff> {
+        let (mut layout, old) = self.plan(secret, None, true)?;
+        // Every write stamps a new generation, which is not a change of content
+        layout.generation = old.and_then(|old| old.generation);
+        let (old_total, old_slot) = old.map_or((0, 0), |old| (old.total, old.slot));
+
+        let mut diff = LayoutDiff::default();
+        let mut buf = Vec::new();
+        for part in 1..=layout.total.max(old_total) {
+            // Parts in different slots are stored under different names
+            let stored = part <= old_total && (part == 1 || old_slot == layout.slot);
+            if part > layout.total {
+                diff.removed.push(part);
+                continue;
+            }
+            if !stored {
+                diff.added.push(part);
+                if part <= old_total {
+                    diff.removed.push(part);
+                }
+                continue;
+            }
+            encode_layout_part(part, &layout, secret, &mut buf)?;
+            match self.read_part_in(part, old_slot)? == buf {
+                true => diff.unchanged.push(part),
+                false => diff.changed.push(part),
+            }
+        }
+        Ok(diff)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::spy_entry;
+
+    #[test]
+    fn test_diff_layout_against_stored_parts() {
+        let (entry, _spy) = spy_entry("diff-layout");
+        let chunk = crate::max_chunk_size();
+        let diff = entry.diff_layout(b"new").unwrap();
+        assert_eq!(diff.added, [1]);
+        assert!(diff.needs_write());
+
+        let secret: Vec<u8> = [vec![1; chunk], vec![2; chunk], vec![3; 5]].concat();
+        entry.set_secret(&secret).unwrap();
+        let diff = entry.diff_layout(&secret).unwrap();
+        assert_eq!(diff.unchanged, [1, 2, 3]);
+        assert!(!diff.needs_write());
+
+        // Same total, one payload differs
+        let edited: Vec<u8> = [vec![1; chunk], vec![9; chunk], vec![3; 5]].concat();
+        let diff = entry.diff_layout(&edited).unwrap();
+        assert_eq!((diff.unchanged, diff.changed), (vec![1, 3], vec![2]));
+
+        // Shrinking rewrites part 1's total and drops the tail
+        let diff = entry.diff_layout(&secret[..chunk + 1]).unwrap();
+        assert_eq!(diff.changed, [1, 2]);
+        assert_eq!(diff.removed, [3]);
+        assert_eq!(diff.disruption(), 3);
+    }
+
+    #[test]
+    fn test_diff_layout_moves_off_alternate_slot() {
+        let (entry, _spy) = spy_entry("diff-layout-slot");
+        let secret = vec![4; crate::max_chunk_size() + 1];
+        entry.set_secret(b"old").unwrap();
+        entry.replace_secret(&secret).unwrap();
+        entry.replace_secret(&secret).unwrap();
+
+        let diff = entry.diff_layout(&secret).unwrap();
+        assert_eq!(diff.changed, [1]);
+        assert_eq!(diff.added, [2]);
+        assert_eq!(diff.removed, [2]);
+    }
+}
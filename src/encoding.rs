@@ -0,0 +1,70 @@
+/// A text encoding [`Entry::get_password_with_encoding`] can decode.
+///
+/// [`Entry::get_password_with_encoding`]: crate::Entry::get_password_with_encoding
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    /// UTF-8, as [`Entry::get_password`](crate::Entry::get_password) reads.
+    #[default]
+    Utf8,
+    /// UTF-16, least significant byte first, as Windows tools write it.
+    Utf16Le,
+    /// UTF-16, most significant byte first.
+    Utf16Be,
+    /// ISO-8859-1, where every byte is the code point of the same value.
+    ///
+    /// This is not Windows-1252, which assigns printable characters to the
+    /// bytes `0x80` to `0x9F` instead of control codes.
+    Latin1,
+}
+
+impl Encoding {
+    /// Decode `bytes`, or `None` if they are not valid in this encoding.
+    ///
+    /// A byte-order mark is kept as `U+FEFF`, like any other character.
+    pub(crate) fn decode(self, bytes: Vec<u8>) -> Option<String> {
+        match self {
+            Encoding::Utf8 => String::from_utf8(bytes).ok(),
+            Encoding::Utf16Le => decode_utf16(&bytes, u16::from_le_bytes),
+            Encoding::Utf16Be => decode_utf16(&bytes, u16::from_be_bytes),
+            Encoding::Latin1 => Some(bytes.iter().map(|&b| char::from(b)).collect()),
+        }
+    }
+}
+
+fn decode_utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> Option<String> {
+    let pairs = bytes.chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return None;
+    }
+    let units: Vec<u16> = pairs.map(|pair| unit([pair[0], pair[1]])).collect();
+    String::from_utf16(&units).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_each_encoding() {
+        assert_eq!(Encoding::Utf8.decode("héllo".into()).unwrap(), "héllo");
+        assert_eq!(
+            Encoding::Utf16Le.decode(vec![b'h', 0, 0xe9, 0]).unwrap(),
+            "hé"
+        );
+        assert_eq!(
+            Encoding::Utf16Be
+                .decode(vec![0xd8, 0x3d, 0xde, 0x00])
+                .unwrap(),
+            "\u{1f600}"
+        );
+        assert_eq!(Encoding::Latin1.decode(vec![b'h', 0xe9]).unwrap(), "hé");
+    }
+
+    #[test]
+    fn test_invalid_input_is_rejected() {
+        assert_eq!(Encoding::Utf8.decode(vec![0xe9]), None);
+        assert_eq!(Encoding::Utf16Le.decode(vec![b'h']), None);
+        // An unpaired high surrogate
+        assert_eq!(Encoding::Utf16Be.decode(vec![0xd8, 0x3d]), None);
+    }
+}
@@ -1,30 +1,227 @@
-use crate::chunk::{chunks_needed, max_chunk_size};
-use crate::format::{decode_part, encode_part};
-use crate::{Error, Result};
+use std::collections::HashMap;
+use std::ops::{Range, RangeInclusive};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::backend::{Backend, KeyringBackend};
+use crate::builder::{Chunking, CommitMarker, Config, EntryBuilder, PartsWarning, WriteOrder};
+use crate::chain::{self, ChainCheck};
+use crate::chunk::{chunk_limit, chunk_ranges, max_raw_size, text_ranges, Platform, MAX_PARTS};
+use crate::clock::{Clock, SystemClock};
+use crate::encoding::Encoding;
+use crate::format::{
+    check_generation, check_header, decode_marker, decode_part, decode_part_expecting,
+    encode_marker, encode_part_into, Header,
+};
+use crate::manifest::{self, decode_manifest, encode_manifest, manifest_len, ManifestPart};
+use crate::sha256::DIGEST_LEN;
+use crate::{Error, Field, Result};
 
 /// An entry in the credential store that can hold secrets of any size.
 ///
 /// Large secrets are automatically split across multiple underlying keyring entries.
 /// Small secrets use a single entry with minimal overhead.
+///
+/// The methods shared with `keyring::Entry` (`new`, `new_with_target`,
+/// `set_password`, `get_password`, `set_secret`, `get_secret` and
+/// `delete_credential`) take the same arguments, so switching the import is
+/// enough for most code. They return this crate's [`Error`](enum@Error),
+/// which wraps keyring's as [`Error::Keyring`]. `new_with_credential`,
+/// `get_credential`, `get_attributes` and `update_attributes` have no
+/// equivalent, since a secret here spans several keyring credentials.
 #[derive(Debug, Clone)]
 pub struct Entry {
     service: String,
     user: String,
+    /// `user` with separators percent-encoded, computed once for part names.
+    pub(crate) encoded_user: String,
+    pub(crate) backend: Arc<dyn Backend>,
+    /// The last total seen in the low 32 bits, or 0 if unknown, and its
+    /// generation above them; `None` when caching is off. Clones share the
+    /// cache.
+    total_cache: Option<Arc<AtomicU64>>,
+    pub(crate) commit_marker: CommitMarker,
+    write_order: WriteOrder,
+    chunking: Chunking,
+    pub(crate) hash_chain: bool,
+    pub(crate) verify_writes: bool,
+    clock: Arc<dyn Clock>,
+    warn_parts_threshold: Option<usize>,
+    on_many_parts: Option<PartsWarning>,
+    /// The most bytes the backend stores per part, headers included.
+    pub(crate) max_entry_size: usize,
+    /// The number of digits part numbers are zero-padded to, if any.
+    part_width: Option<usize>,
+    /// The most parts a write may use.
+    pub(crate) max_parts: usize,
+    /// The label each part is tagged with, see [`EntryBuilder::label`].
+    label: Option<String>,
+    /// The comment each part is tagged with.
+    comment: Option<String>,
+    /// Whether writes store a parity part and reads use it.
+    pub(crate) parity: bool,
 }
 
 impl Entry {
     /// Create a new entry for the given service and user.
     pub fn new(service: &str, user: &str) -> Result<Self> {
-        if service.is_empty() {
-            return Err(Error::InvalidArgument("service cannot be empty".into()));
+        Self::builder(service).build(user)
+    }
+
+    /// Create an entry for `user` in [`DEFAULT_SERVICE`](crate::DEFAULT_SERVICE).
+    ///
+    /// A shortcut for scripts and apps with a single namespace. The default
+    /// is only used when no service is given: [`Entry::new`] and
+    /// [`Entry::builder`] always use the service they are passed. To share a
+    /// service of your own, build entries from one [`EntryBuilder`].
+    pub fn for_user(user: &str) -> Result<Self> {
+        Self::new(crate::DEFAULT_SERVICE, user)
+    }
+
+    /// Create a new entry whose parts use the given keyring target.
+    ///
+    /// See [`EntryBuilder::target`].
+    pub fn new_with_target(target: &str, service: &str, user: &str) -> Result<Self> {
+        Self::builder(service).target(target).build(user)
+    }
+
+    /// Create a new entry and check that the backend is reachable.
+    ///
+    /// [`Entry::new`] only validates the strings, so an unavailable keyring
+    /// is reported at first use. This additionally reads a reserved name no
+    /// credential is ever stored under: a missing entry means the backend
+    /// responded, anything else is returned as an error. Nothing is created.
+    pub fn try_new(service: &str, user: &str) -> Result<Self> {
+        let entry = Self::new(service, user)?;
+        entry.probe()?;
+        Ok(entry)
+    }
+
+    /// Create a new entry from byte-valued service and user identifiers.
+    ///
+    /// Every backend keyring supports (macOS and iOS keychains, Windows
+    /// Credential Manager, Secret Service and keyutils) takes service and
+    /// user as UTF-8 strings, so identifiers that are not valid UTF-8 return
+    /// [`Error::NotUtf8`]. Valid UTF-8 behaves exactly like [`Entry::new`].
+    pub fn new_bytes(service: &[u8], user: &[u8]) -> Result<Self> {
+        let service = std::str::from_utf8(service).map_err(|_| Error::NotUtf8(Field::Service))?;
+        let user = std::str::from_utf8(user).map_err(|_| Error::NotUtf8(Field::User))?;
+        Self::new(service, user)
+    }
+
+    /// Create an entry for each of `users` in the given service.
+    ///
+    /// Fails with [`Error::InvalidUser`] for the first user that
+    /// [`Entry::new`] would reject. Use [`EntryBuilder::build_many`] to give
+    /// them all a shared configuration.
+    pub fn many(service: &str, users: &[&str]) -> Result<Vec<Self>> {
+        Self::builder(service).build_many(users)
+    }
+
+    /// Delete the credential of each of `users` in the given service.
+    ///
+    /// See [`EntryBuilder::delete_many`], which this calls with a default
+    /// configuration.
+    pub fn delete_many(service: &str, users: &[&str]) -> Result<Vec<(String, Result<()>)>> {
+        Self::builder(service).delete_many(users)
+    }
+
+    /// Create a builder for configuring entries in the given service.
+    pub fn builder(service: &str) -> EntryBuilder {
+        EntryBuilder::new(service)
+    }
+
+    /// Create a new entry with the given configuration.
+    pub(crate) fn with_config(service: &str, user: &str, config: Config) -> Result<Self> {
+        validate_service(service)?;
+        validate_user(user)?;
+        let max_entry_size = config.max_entry_size.unwrap_or_else(max_raw_size);
+        // Expiring writes have the largest headers, so they must fit too
+        if chunk_limit(max_entry_size, config.hash_chain, true) == 0 {
+            return Err(Error::InvalidArgument(format!(
+                "an entry size of {} bytes leaves no room for part data",
+                max_entry_size
+            )));
+        }
+        if let Some(target) = config.target() {
+            check_name_len(target)?;
+        }
+        let max_parts = config.max_parts.unwrap_or(MAX_PARTS);
+        if !(1..=MAX_PARTS).contains(&max_parts) {
+            return Err(Error::InvalidArgument(format!(
+                "a part limit of {} is outside 1 to {}",
+                max_parts, MAX_PARTS
+            )));
         }
-        if user.is_empty() {
-            return Err(Error::InvalidArgument("user cannot be empty".into()));
+        if config.write_order == WriteOrder::Forward && config.commit_marker == CommitMarker::Part1
+        {
+            return Err(Error::InvalidArgument(
+                "forward write order needs a sentinel or manifest commit marker".into(),
+            ));
         }
-        Ok(Self {
+        if config.namespace.as_deref() == Some("") {
+            return Err(Error::InvalidArgument("namespace cannot be empty".into()));
+        }
+        let backend = Arc::new(KeyringBackend::new(config.target()));
+        let total_cache = config.cache_total.then(|| Arc::new(AtomicU64::new(0)));
+        let entry = Self {
             service: service.to_string(),
             user: user.to_string(),
-        })
+            encoded_user: match &config.namespace {
+                Some(namespace) => format!("{}:{}", encode_namespace(namespace), encode_user(user)),
+                None => encode_user(user),
+            },
+            backend,
+            total_cache,
+            commit_marker: config.commit_marker,
+            write_order: config.write_order,
+            chunking: config.chunking,
+            hash_chain: config.hash_chain,
+            verify_writes: config.verify_writes,
+            clock: config.clock.unwrap_or_else(|| Arc::new(SystemClock)),
+            warn_parts_threshold: config.warn_parts_threshold,
+            on_many_parts: config.on_many_parts,
+            max_entry_size,
+            part_width: config.part_width,
+            max_parts,
+            label: config.label,
+            comment: config.comment,
+            parity: config.parity,
+        };
+        entry.check_names()?;
+        Ok(entry)
+    }
+
+    /// Store this entry's parts in the given backend instead of the platform keyring.
+    ///
+    /// The backend replaces the keyring entirely, so keyring-specific
+    /// configuration such as the target no longer applies.
+    #[cfg(any(test, feature = "testing"))]
+    pub fn with_backend(mut self, backend: Box<dyn Backend>) -> Self {
+        self.backend = Arc::from(backend);
+        self
+    }
+
+    /// The largest secret a fresh write can store, in bytes.
+    ///
+    /// This is the entry's chunk size times [`MAX_PARTS`](crate::MAX_PARTS),
+    /// or the limit set with [`EntryBuilder::max_parts`]. An update
+    /// reuses the chunk size recorded by the previous write, so the limit
+    /// for it can be lower if that write used a smaller chunk size.
+    pub fn max_secret_size(&self) -> usize {
+        self.chunk_limit(false) * self.max_parts
+    }
+
+    /// Whether a fresh write of `len` bytes fits in part 1 alone.
+    ///
+    /// Compares against this entry's chunk size, so it honors
+    /// [`EntryBuilder::max_entry_size`] and the hash chain's larger
+    /// headers. A write with a TTL has a slightly smaller limit, and an
+    /// update reuses the chunk size recorded by the previous write, which
+    /// can be smaller still.
+    pub fn fits_in_one_chunk(&self, len: usize) -> bool {
+        len <= self.chunk_limit(false)
     }
 
     /// Store a password (UTF-8 string) in the credential store.
@@ -38,114 +235,3265 @@ impl Entry {
         String::from_utf8(secret).map_err(|_| Error::BadEncoding)
     }
 
+    /// Retrieve a password stored as text in another encoding.
+    ///
+    /// For secrets written by tools that store UTF-16 or Latin-1 text. The
+    /// reassembled secret is decoded as `encoding`, failing with
+    /// [`Error::BadEncoding`] if it is not valid in it. With
+    /// [`Encoding::Utf8`] this is [`Entry::get_password`].
+    pub fn get_password_with_encoding(&self, encoding: Encoding) -> Result<String> {
+        let secret = self.get_secret()?;
+        encoding.decode(secret).ok_or(Error::BadEncoding)
+    }
+
+    /// Retrieve a password like [`Entry::get_password`], dropping a leading
+    /// UTF-8 byte-order mark.
+    ///
+    /// Some tools write text secrets starting with `U+FEFF`. Only the string
+    /// API strips it, and only here: [`Entry::get_password`] and
+    /// [`Entry::get_secret`] return the stored bytes untouched.
+    pub fn get_password_trimmed(&self) -> Result<String> {
+        let mut password = self.get_password()?;
+        if password.starts_with('\u{feff}') {
+            password.drain(..'\u{feff}'.len_utf8());
+        }
+        Ok(password)
+    }
+
     /// Store binary data in the credential store.
     ///
     /// The data is automatically split across multiple entries if it exceeds
     /// the platform's per-entry limit.
-    pub fn set_secret(&self, secret: &[u8]) -> Result<()> {
-        // First, clean up any existing parts
-        self.delete_credential()?;
+    ///
+    /// Existing parts are overwritten in place: parts `2..=N` are written
+    /// first, surplus parts left by a previous larger secret are deleted, and
+    /// part 1 is written last as the commit marker. Under
+    /// [`WriteOrder::Forward`] parts are instead written from 1 to N, and
+    /// the separate marker commits them. The backend's `flush` is called
+    /// just before the commit, so a backend that buffers writes commits
+    /// the other parts first. The platform stores keyring wraps offer no
+    /// flush, so for them this ordering is the best guarantee available.
+    ///
+    /// An update reuses the chunk size recorded in the existing part 1 as
+    /// long as it still fits the platform's limit, so the stored geometry
+    /// survives changes to the default chunk size.
+    ///
+    /// With [`CommitMarker::Sentinel`], the marker is deleted before any
+    /// part is written and stored again once part 1 is written.
+    ///
+    /// Returns [`Error::InvalidArgument`] without writing anything if the
+    /// secret would need more than [`MAX_PARTS`](crate::MAX_PARTS) parts, or
+    /// more than [`EntryBuilder::max_parts`] allows.
+    ///
+    /// Anything that views as bytes can be passed, such as `&[u8]`,
+    /// `Vec<u8>`, `&str`, `String` or a byte array.
+    pub fn set_secret(&self, secret: impl AsRef<[u8]>) -> Result<()> {
+        self.store(secret.as_ref(), None, WriteMode::Update, None)
+    }
 
-        let chunk_size = max_chunk_size();
-        let total = chunks_needed(secret.len());
+    /// Store the concatenation of `fragments`, as [`Entry::set_secret`] would.
+    ///
+    /// For secrets produced piece by piece, such as by a serializer, so the
+    /// caller needs no buffer of its own. Every part's header records the
+    /// total, which is only known once the iterator is exhausted, so the
+    /// fragments are gathered first and nothing is written until then; part
+    /// 1 is still written last. Gathering stops with
+    /// [`Error::InvalidArgument`], before anything is written, as soon as
+    /// the fragments exceed [`Entry::max_secret_size`].
+    pub fn set_secret_from_chunks<'a>(
+        &self,
+        fragments: impl IntoIterator<Item = &'a [u8]>,
+    ) -> Result<()> {
+        let limit = self.max_secret_size();
+        let mut secret = Vec::new();
+        for fragment in fragments {
+            if secret.len() + fragment.len() > limit {
+                return Err(Error::InvalidArgument(format!(
+                    "secret exceeds the maximum of {} bytes",
+                    limit
+                )));
+            }
+            secret.extend_from_slice(fragment);
+        }
+        self.set_secret(&secret)
+    }
+
+    /// Store a secret that expires after `ttl`.
+    ///
+    /// Part 1 records the expiry time, to the whole second, from the
+    /// entry's clock. Once it has passed, [`Entry::get_secret`] deletes the
+    /// credential and returns [`Error::Expired`]. A later write without a
+    /// TTL clears the expiry.
+    pub fn set_secret_with_ttl(&self, secret: impl AsRef<[u8]>, ttl: Duration) -> Result<()> {
+        let expires = (self.now() + ttl)
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| Error::InvalidArgument("expiry is before the Unix epoch".into()))?;
+        // Round up so the secret never expires before `ttl` has passed
+        let expires = expires.as_secs() + u64::from(expires.subsec_nanos() > 0);
+        self.store(secret.as_ref(), Some(expires), WriteMode::Update, None)
+    }
 
-        // Write parts in reverse order (N down to 1)
-        // This ensures part 1 acts as a "commit" marker
-        for part in (1..=total).rev() {
-            let start = (part - 1) * chunk_size;
-            let end = std::cmp::min(part * chunk_size, secret.len());
-            let chunk_data = &secret[start..end];
+    /// Store a secret so that concurrent readers never find it missing.
+    ///
+    /// [`Entry::set_secret`] overwrites parts in place, so a reader that
+    /// runs during the write can see a mix of old and new parts and fail.
+    /// This writes the new parts under the alternate names (`{user}.{part}b`
+    /// instead of `{user}.{part}`, or back again), then replaces part 1,
+    /// which records which names are in use, and only then deletes the old
+    /// parts. Any single read sees either the complete old secret or the
+    /// complete new one; [`Entry::get_secret`] retries once if the secret
+    /// is replaced between reading part 1 and a later part.
+    ///
+    /// A secret that fits in one part is written to part 1 directly, which
+    /// is already a single backend write. The old parts are kept until the
+    /// end, so a chunked update briefly uses twice the storage.
+    ///
+    /// Returns [`Error::InvalidArgument`] under [`CommitMarker::Sentinel`]
+    /// or [`CommitMarker::Manifest`], whose marker cannot be replaced in the
+    /// same write as part 1.
+    pub fn replace_secret(&self, secret: impl AsRef<[u8]>) -> Result<()> {
+        if self.commit_marker != CommitMarker::Part1 {
+            return Err(Error::InvalidArgument(
+                "replace_secret needs the part 1 commit marker".into(),
+            ));
+        }
+        self.store(secret.as_ref(), None, WriteMode::Replace, None)
+    }
+
+    /// Store a secret along with a content type hint, such as
+    /// `application/json`.
+    ///
+    /// The type is metadata for tools that display secrets and does not
+    /// change how the secret is split. It is stored in a separate
+    /// `{user}#type` entry, written before any part, and part 1 is flagged
+    /// so [`Entry::content_type`] knows to look for it. A later write
+    /// without a type clears it.
+    ///
+    /// Returns [`Error::InvalidArgument`] without writing anything if the
+    /// type is empty, contains anything but printable ASCII, or does not
+    /// fit one entry.
+    pub fn set_secret_with_type(&self, secret: impl AsRef<[u8]>, content_type: &str) -> Result<()> {
+        if content_type.is_empty()
+            || !content_type
+                .bytes()
+                .all(|b| b.is_ascii_graphic() || b == b' ')
+            || content_type.len() > self.max_entry_size
+        {
+            return Err(Error::InvalidArgument(format!(
+                "invalid content type {:?}",
+                content_type
+            )));
+        }
+        self.store(secret.as_ref(), None, WriteMode::Update, Some(content_type))
+    }
+
+    /// The content type stored by [`Entry::set_secret_with_type`].
+    ///
+    /// Returns `None` for a secret stored without one, and
+    /// [`keyring::Error::NoEntry`] if nothing is stored. Only part 1 and,
+    /// if it is flagged, the type entry are read.
+    pub fn content_type(&self) -> Result<Option<String>> {
+        let Some(header) = self.stored_header()? else {
+            return Err(Error::Keyring(keyring::Error::NoEntry));
+        };
+        if !header.typed {
+            return Ok(None);
+        }
+        match self.backend_get(&self.type_user()) {
+            Ok(data) => String::from_utf8(data)
+                .map(Some)
+                .map_err(|_| Error::CorruptedSecret("invalid content type".into())),
+            Err(keyring::Error::NoEntry) => {
+                Err(Error::CorruptedSecret("missing content type".into()))
+            }
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    /// The non-generic body of [`Entry::set_secret`], optionally recording
+    /// an expiry and a content type on part 1.
+    pub(crate) fn store(
+        &self,
+        secret: &[u8],
+        expires: Option<u64>,
+        mode: WriteMode,
+        content_type: Option<&str>,
+    ) -> Result<()> {
+        // A cached single part is overwritten whole by a single-part secret,
+        // and caching implies it is in the primary slot and untyped, so its
+        // header is all that is needed to clean up after it
+        let (old, cached) = match self.cached() {
+            Some((1, generation)) if secret.len() <= self.chunk_limit(expires.is_some()) => (
+                Some(Header {
+                    generation,
+                    ..Header::new(1, 1)
+                }),
+                true,
+            ),
+            _ => (self.stored_header()?, false),
+        };
+        // A failed write leaves the stored total unknown
+        self.invalidate_cache();
+
+        let reshaping = matches!(mode, WriteMode::Reshape(_));
+        let seamless =
+            mode == WriteMode::Replace || reshaping && self.commit_marker == CommitMarker::Part1;
+        let mut layout = match mode {
+            WriteMode::Reshape(chunk_size) => self.layout_at(secret, expires, chunk_size, old)?,
+            _ => self.plan_over(secret, expires, mode != WriteMode::Compact, old)?,
+        };
+        layout.typed = content_type.is_some();
+        if seamless && layout.total > 1 {
+            // Write into whichever set of names the current secret is not using
+            layout.slot = match old {
+                Some(old) if old.total > 1 => 1 - old.slot,
+                _ => 0,
+            };
+        }
+        let Layout {
+            total,
+            chunk_size,
+            slot,
+            typed,
+            generation,
+            ..
+        } = layout;
+
+        if self.commit_marker != CommitMarker::Part1 {
+            self.delete_marker()?;
+        }
+        if let Some(content_type) = content_type {
+            // Before part 1 is flagged, so a flagged part 1 always has one
+            self.backend_set(&self.type_user(), content_type.as_bytes())?;
+        }
+
+        log::trace!(
+            "storing {} bytes for {}/{} in {} parts of {} bytes",
+            secret.len(),
+            self.service,
+            self.user,
+            total,
+            chunk_size
+        );
+
+        // One encode buffer serves every part, since each write copies it
+        let mut buf = Vec::with_capacity(chunk_size.min(secret.len()) + 64);
+        let mut manifest = self.manifest_for(total);
+
+        let mut progress = match mode {
+            WriteMode::Resumable if total > 1 => Some(self.progress_for(secret, &layout)?),
+            _ => None,
+        };
+        // Any other write supersedes an unfinished resumable one. A cached
+        // single part is trusted to have no leftovers around it
+        if progress.is_none() && !cached {
+            self.delete_progress()?;
+        }
+
+        // Without a commit marker to protect, parts can go out in order;
+        // progress records the lowest part written, so it needs reverse
+        let forward = self.write_order == WriteOrder::Forward && progress.is_none();
+        if forward {
+            self.write_part1(&layout, secret, &mut buf, reshaping)?;
+            record(&mut manifest, 1, &buf);
+        }
+
+        // Write parts N down to 2, holding back part 1, or 2 up to N after it
+        let later: Vec<usize> = match forward {
+            true => (2..=total).collect(),
+            false => (2..=total).rev().collect(),
+        };
+        for part in later {
+            match &mut progress {
+                Some(progress) => {
+                    self.write_resumable_part(progress, part, &layout, secret, &mut buf)?
+                }
+                None => self.write_part(part, &layout, secret, &mut buf)?,
+            }
+            if reshaping && self.read_part_in(part, slot)? != buf {
+                return Err(Error::CorruptedSecret("write verification failed".into()));
+            }
+            record(&mut manifest, part, &buf);
+        }
+
+        // Parts the new secret does not overwrite: beyond the new total in
+        // the same slot, or every later part in the other one
+        let stale = old.map(|old| {
+            let first = if old.slot == slot { total + 1 } else { 2 };
+            (first..=old.total, old.slot)
+        });
+        // In place, surplus parts go before part 1 is committed; a reader
+        // may still be using the old part 1 otherwise, so they go after it
+        let clean_after = seamless || old.is_some_and(|old| old.slot != slot);
+        if !clean_after {
+            if let Some((parts, old_slot)) = stale.clone() {
+                self.delete_parts_in(parts, old_slot)?;
+            }
+        }
 
-            let encoded = encode_part(part, total, chunk_data);
-            let entry = self.part_entry(part)?;
-            entry.set_secret(&encoded).map_err(Error::from)?;
+        self.barrier()?;
+        if !forward {
+            // Part 1 goes last so it acts as a "commit" marker
+            self.write_part1(&layout, secret, &mut buf, reshaping)?;
+            record(&mut manifest, 1, &buf);
+        }
+        self.write_marker(total, &manifest)?;
+        if clean_after {
+            if let Some((parts, old_slot)) = stale {
+                self.delete_parts_in(parts, old_slot)?;
+            }
+        }
+        if !typed && old.is_some_and(|old| old.typed) {
+            self.delete_content_type()?;
         }
+        if progress.is_some() {
+            self.delete_progress()?;
+        }
+        // Last, so until it lands the old parity names an older generation
+        if self.parity {
+            self.write_parity(&layout, secret)?;
+        }
+        if cacheable(slot, typed) {
+            self.remember_total(total, generation);
+        }
+        self.warn_if_many_parts(total, secret.len());
+        Ok(())
+    }
 
+    /// Write part 1, reading it back if `verify` or
+    /// [`EntryBuilder::verify_writes`] asks to.
+    fn write_part1(
+        &self,
+        layout: &Layout,
+        secret: &[u8],
+        buf: &mut Vec<u8>,
+        verify: bool,
+    ) -> Result<()> {
+        self.write_part(1, layout, secret, buf)?;
+        if (self.verify_writes || verify) && self.read_part(1)? != *buf {
+            return Err(Error::CorruptedSecret("write verification failed".into()));
+        }
         Ok(())
     }
 
+    /// Work out how `secret` will be split, and the header of the
+    /// credential it replaces.
+    ///
+    /// Reads part 1's header once so cleanup needs no second lookup. With
+    /// `reuse_chunk_size`, the chunk size part 1 records is kept if it
+    /// still fits.
+    pub(crate) fn plan(
+        &self,
+        secret: &[u8],
+        expires: Option<u64>,
+        reuse_chunk_size: bool,
+    ) -> Result<(Layout, Option<Header>)> {
+        let old = self.stored_header()?;
+        let layout = self.plan_over(secret, expires, reuse_chunk_size, old)?;
+        Ok((layout, old))
+    }
+
+    /// Like [`Entry::plan`], given the header of the credential replaced.
+    fn plan_over(
+        &self,
+        secret: &[u8],
+        expires: Option<u64>,
+        reuse_chunk_size: bool,
+        old: Option<Header>,
+    ) -> Result<Layout> {
+        let chunk_size = old
+            .filter(|_| reuse_chunk_size)
+            .and_then(|header| header.chunk_size)
+            .filter(|&size| size > 0 && size <= self.chunk_limit(expires.is_some()))
+            .unwrap_or_else(|| self.chunk_limit(expires.is_some()));
+        self.layout_at(secret, expires, chunk_size, old)
+    }
+
+    /// Split `secret` into parts of `chunk_size` bytes, replacing the
+    /// credential whose header is `old`.
+    fn layout_at(
+        &self,
+        secret: &[u8],
+        expires: Option<u64>,
+        chunk_size: usize,
+        old: Option<Header>,
+    ) -> Result<Layout> {
+        let ranges: Vec<_> = match self.chunking {
+            Chunking::Exact => chunk_ranges(secret.len(), chunk_size).collect(),
+            Chunking::TextAware => text_ranges(secret, chunk_size),
+        };
+        let total = ranges.len();
+        if total > self.max_parts {
+            return Err(Error::InvalidArgument(format!(
+                "secret needs {} parts, which exceeds maximum parts ({}); \
+                 consider compressing it before storing",
+                total, self.max_parts
+            )));
+        }
+        self.check_part_width(total)?;
+        if self.commit_marker == CommitMarker::Manifest
+            && manifest_len(total, self.max_entry_size) > self.max_entry_size
+        {
+            return Err(Error::InvalidArgument(format!(
+                "a manifest for {} parts does not fit one entry",
+                total
+            )));
+        }
+
+        let links = if self.hash_chain {
+            chain::links(ranges.iter().map(|range| &secret[range.clone()]))
+        } else {
+            Vec::new()
+        };
+        let layout = Layout {
+            total,
+            chunk_size,
+            ranges,
+            links,
+            expires,
+            slot: 0,
+            typed: false,
+            generation: Some(next_generation(old.and_then(|old| old.generation))),
+        };
+        Ok(layout)
+    }
+
+    /// Report a write over the configured part threshold, if any.
+    pub(crate) fn warn_if_many_parts(&self, total: usize, len: usize) {
+        match self.warn_parts_threshold {
+            Some(threshold) if total > threshold => {}
+            _ => return,
+        }
+        match &self.on_many_parts {
+            Some(PartsWarning(callback)) => callback(total, len),
+            None => log::warn!(
+                "{}/{} is stored in {} keyring entries ({} bytes); consider compressing it",
+                self.service,
+                self.user,
+                total,
+                len
+            ),
+        }
+    }
+
     /// Retrieve binary data from the credential store.
     ///
     /// Automatically reassembles data that was split across multiple entries.
+    ///
+    /// If part 1 has no header separator at all, as when it was stored by
+    /// plain `keyring`, [`Error::NotOurFormat`] is returned instead of
+    /// [`Error::CorruptedSecret`].
+    ///
+    /// Parts are numbered from 1. If part 1 is missing but a part 0 is
+    /// stored, as a writer numbering from 0 would leave, that is reported
+    /// as [`Error::CorruptedSecret`] rather than as a missing credential.
+    /// Finding that out costs a missing credential's read one more backend
+    /// lookup.
+    ///
+    /// With [`CommitMarker::Sentinel`], the marker is read first and every
+    /// part must agree with the total it records.
     pub fn get_secret(&self) -> Result<Vec<u8>> {
-        // Read part 1 to get total count
-        let entry1 = self.part_entry(1)?;
-        let data1 = entry1.get_secret().map_err(Error::from)?;
-        let (part, total, payload1) = decode_part(&data1)?;
-
-        if part != 1 {
-            return Err(Error::CorruptedSecret(format!(
-                "expected part 1, got {}",
-                part
-            )));
+        self.read_all(None)
+    }
+
+    /// Like [`Entry::get_secret`], stopping with [`Error::Cancelled`] once
+    /// `cancel` is set.
+    ///
+    /// The flag is checked before each part is read. Reads change nothing,
+    /// so there is nothing to clean up.
+    pub fn get_secret_cancellable(&self, cancel: &AtomicBool) -> Result<Vec<u8>> {
+        self.read_all(Some(cancel))
+    }
+
+    /// Store a secret like [`Entry::set_secret`], stopping with
+    /// [`Error::Cancelled`] once `cancel` is set.
+    ///
+    /// The parts are first staged as by [`Entry::prepare`], checking the
+    /// flag before each one; a cancelled write deletes what it staged and
+    /// leaves the stored credential untouched. Once every part is staged
+    /// the flag is checked one last time and the write is committed, which
+    /// is not interrupted, since stopping half way through replacing the
+    /// parts would leave neither the old secret nor the new one.
+    pub fn set_secret_cancellable(
+        &self,
+        secret: impl AsRef<[u8]>,
+        cancel: &AtomicBool,
+    ) -> Result<()> {
+        let prepared = self.stage(secret.as_ref(), Some(cancel))?;
+        check_cancelled(Some(cancel))?;
+        prepared.commit()
+    }
+
+    /// The body of [`Entry::get_secret`], checking `cancel` between parts.
+    fn read_all(&self, cancel: Option<&AtomicBool>) -> Result<Vec<u8>> {
+        let mut failed_after = None;
+        let result = self.read_all_once(cancel, &mut failed_after);
+        let (Err(e), Some(first)) = (&result, failed_after) else {
+            return result;
+        };
+        // A replace_secret between part 1 and a later part leaves the later
+        // part missing or mismatched; read the new secret instead
+        match self.stored_header() {
+            Ok(Some(current)) if current != first => {
+                log::trace!(
+                    "{}/{} changed during read ({}), retrying",
+                    self.service,
+                    self.user,
+                    e
+                );
+                self.read_all_once(cancel, &mut None)
+            }
+            _ => result,
         }
+    }
 
-        if total == 1 {
-            return Ok(payload1);
+    /// Read the whole secret once. If a part after part 1 fails to read,
+    /// part 1's header is left in `failed_after`, so the caller can tell
+    /// whether the secret changed meanwhile.
+    fn read_all_once(
+        &self,
+        cancel: Option<&AtomicBool>,
+        failed_after: &mut Option<Header>,
+    ) -> Result<Vec<u8>> {
+        if self.parity {
+            return self.read_with_parity(cancel, failed_after);
         }
+        check_cancelled(cancel)?;
+        let mut first = self.read_first_part()?;
+        let total = first.total;
+        log::trace!(
+            "reading {}/{} from {} parts",
+            self.service,
+            self.user,
+            total
+        );
 
-        // Read remaining parts
-        let mut result = payload1;
+        // Read remaining parts, copying each payload straight from its buffer
         for i in 2..=total {
-            let entry = self.part_entry(i)?;
-            let data = entry.get_secret().map_err(Error::from)?;
-            let (part, part_total, payload) = decode_part(&data)?;
-
-            if part != i {
-                return Err(Error::CorruptedSecret(format!(
-                    "expected part {}, got {}",
-                    i, part
-                )));
+            check_cancelled(cancel)?;
+            if let Err(e) = self.read_next_part(i, &mut first) {
+                *failed_after = Some(first.header);
+                return Err(e);
             }
-            if part_total != total {
-                return Err(Error::CorruptedSecret(format!(
-                    "inconsistent total: expected {}, got {}",
-                    total, part_total
-                )));
+        }
+
+        Ok(first.payload)
+    }
+
+    /// Retrieve the first `n` bytes of the secret.
+    ///
+    /// Parts are read in order only until `n` bytes are collected, so a
+    /// small `n` on a large secret reads just part 1. Part 1 is validated
+    /// exactly as by [`Entry::get_secret`], and each later part read is
+    /// checked against it. A hash chain can only be closed by reading the
+    /// last part, so a peek that stops early does not detect a substituted
+    /// later part. Returns the whole secret if it is shorter than `n`.
+    pub fn peek(&self, n: usize) -> Result<Vec<u8>> {
+        let mut first = self.read_first_part()?;
+        for i in 2..=first.total {
+            if first.payload.len() >= n {
+                break;
+            }
+            self.read_next_part(i, &mut first)?;
+        }
+        first.payload.truncate(n);
+        Ok(first.payload)
+    }
+
+    /// Retrieve the secret as the payloads of its parts, in order.
+    ///
+    /// Each part is validated exactly as by [`Entry::get_secret`], but the
+    /// payloads are returned separately rather than concatenated, which
+    /// saves a copy for callers that process the secret in blocks anyway.
+    /// Every part holds the chunk size part 1 records except the last,
+    /// which holds the remainder; under [`Chunking::TextAware`] parts can
+    /// end earlier. An empty secret is a single empty part.
+    pub fn get_parts(&self) -> Result<Vec<Vec<u8>>> {
+        let mut reading = self.read_first_part()?;
+        let mut parts = Vec::with_capacity(reading.total);
+        parts.push(std::mem::take(&mut reading.payload));
+        for i in 2..=reading.total {
+            self.read_next_part(i, &mut reading)?;
+            parts.push(std::mem::take(&mut reading.payload));
+        }
+        Ok(parts)
+    }
+
+    /// Read and validate part 1, returning what later parts are read with.
+    ///
+    /// With [`CommitMarker::Sentinel`], the total comes from the marker.
+    pub(crate) fn read_first_part(&self) -> Result<Reading> {
+        let mut manifest = None;
+        let committed = match self.commit_marker {
+            CommitMarker::Part1 => None,
+            CommitMarker::Sentinel => Some(self.read_marker()?),
+            CommitMarker::Manifest => {
+                manifest = self.read_manifest()?;
+                manifest.as_ref().map(Vec::len)
             }
+        };
+
+        // Read part 1 to get total count
+        let mut data1 = match self.read_part(1) {
+            Err(Error::Keyring(keyring::Error::NoEntry)) => return Err(self.missing_part1()),
+            result => result?,
+        };
+        if !data1.contains(&b'|') {
+            // Every header ends in the separator, so this is foreign data
+            return Err(Error::NotOurFormat);
+        }
+        if let Some(manifest) = &manifest {
+            manifest::check_part(manifest, 1, &data1)?;
+        }
+        let (header1, payload1) = decode_part(&data1)?;
+        let total = committed.unwrap_or(header1.total);
+        let header_len = data1.len() - payload1.len();
+
+        check_header(&header1, 1, total)?;
+        if let Some(expires) = header1.expires {
+            self.purge_if_expired(expires)?;
+        }
+        let mut chain = ChainCheck::default();
+        chain.verify(&header1, 1, total, payload1)?;
+        if cacheable(header1.slot, header1.typed) {
+            self.remember_total(total, header1.generation);
+        }
+
+        // Strip the header in place so part 1's buffer becomes the result
+        data1.drain(..header_len);
+        Ok(Reading {
+            payload: data1,
+            header: header1,
+            total,
+            chain,
+            manifest,
+        })
+    }
+
+    /// The error for a read that found no part 1.
+    ///
+    /// Parts are numbered from 1, so a stored part 0 can only come from a
+    /// writer numbering from 0; that is reported rather than passed off as
+    /// a missing credential. A failure to probe part 0 other than it
+    /// being missing is returned as it is.
+    fn missing_part1(&self) -> Error {
+        match self.backend_get(&self.part_user(0)) {
+            Ok(_) => Error::CorruptedSecret(
+                "part 0 is stored but part 1 is not; parts are numbered from 1".into(),
+            ),
+            Err(e) => Error::from(e),
+        }
+    }
 
-            result.extend_from_slice(&payload);
+    /// Read part `part` of the secret being read, check it and append its
+    /// payload.
+    pub(crate) fn read_next_part(&self, part: usize, reading: &mut Reading) -> Result<()> {
+        let total = reading.total;
+        let data = self.read_part_in(part, reading.header.slot)?;
+        if let Some(manifest) = &reading.manifest {
+            manifest::check_part(manifest, part, &data)?;
         }
+        let (header, payload) = decode_part_expecting(&data, part, total)?;
+        check_generation(&header, reading.header.generation)?;
+        reading.chain.verify(&header, part, total, payload)?;
+        reading.payload.extend_from_slice(payload);
+        Ok(())
+    }
 
-        Ok(result)
+    /// Compare `candidate` with the stored secret in constant time.
+    ///
+    /// The comparison takes the same time however many leading bytes match,
+    /// so it is safe for checking user-supplied tokens; only the length of
+    /// the stored secret can be inferred from timing. Returns `Ok(false)` if
+    /// no credential is stored.
+    pub fn secret_eq(&self, candidate: &[u8]) -> Result<bool> {
+        match self.get_secret() {
+            Ok(secret) => Ok(constant_time_eq(&secret, candidate)),
+            Err(Error::Keyring(keyring::Error::NoEntry)) => Ok(false),
+            Err(e) => Err(e),
+        }
     }
 
     /// Delete the credential from the store.
     ///
     /// This is idempotent - calling it when no credential exists returns Ok(()).
     /// Deletes parts from the end backwards for safe resumption if interrupted.
+    ///
+    /// With [`EntryBuilder::cache_total`] enabled, a cached total is trusted
+    /// and part 1 is not read first.
+    ///
+    /// With [`CommitMarker::Sentinel`] or [`CommitMarker::Manifest`], the
+    /// marker is deleted first. The progress entry of an unfinished
+    /// [`Entry::set_secret_resumable`] is deleted last, unless the total was
+    /// cached.
     pub fn delete_credential(&self) -> Result<()> {
-        // Try to read part 1 to get total
-        let total = match self.read_part_total(1) {
-            Ok(total) => total,
-            Err(Error::Keyring(keyring::Error::NoEntry)) => return Ok(()), // Already clean
+        // Only credentials using the primary names and no type are cached
+        let (total, slot, typed, cached) = match self.cached_total() {
+            Some(total) => (total, 0, false, true),
+            None => self
+                .stored_header()?
+                .map_or((0, 0, false, false), |header| {
+                    (header.total, header.slot, header.typed, false)
+                }),
+        };
+        self.invalidate_cache();
+        log::trace!(
+            "deleting {}/{} from {} parts",
+            self.service,
+            self.user,
+            total
+        );
+        if self.commit_marker != CommitMarker::Part1 {
+            self.delete_marker()?;
+        }
+        self.drop_parity()?;
+        self.delete_parts_in(1..=total, slot)?;
+        if typed {
+            self.delete_content_type()?;
+        }
+        // As in a write, a cached total is trusted to have no leftovers
+        if !cached {
+            self.delete_progress()?;
+        }
+        Ok(())
+    }
+
+    /// Delete the credential like [`Entry::delete_credential`], even if
+    /// part 1 cannot be decoded.
+    ///
+    /// When part 1 is unreadable its total is unknown, so parts `2, 3, ...`
+    /// are deleted under both the primary and the alternate names until one
+    /// is missing, as by [`Entry::delete_parts_from`], and then part 1
+    /// itself. The commit marker, content type and progress entries go
+    /// first. Parts after a gap are not found; [`Entry::repair`] reports
+    /// those. Errors from the backend itself are still returned.
+    pub fn delete_force(&self) -> Result<()> {
+        match self.delete_credential() {
+            Err(e @ Error::Keyring(_)) => Err(e),
+            Err(e) => {
+                log::debug!("part 1 is unreadable ({}), deleting by scanning", e);
+                self.delete_unreadable()
+            }
+            Ok(()) => Ok(()),
+        }
+    }
+
+    /// Delete every part up to the first gap in each slot, part 1 last.
+    fn delete_unreadable(&self) -> Result<()> {
+        self.invalidate_cache();
+        self.delete_marker()?;
+        self.delete_content_type()?;
+        self.drop_parity()?;
+        self.delete_progress()?;
+        for slot in [0, 1] {
+            for part in 2..=MAX_PARTS {
+                match self.backend_delete(&self.slot_user(part, slot)) {
+                    Ok(()) => continue,
+                    Err(keyring::Error::NoEntry) => break,
+                    Err(e) => return Err(Error::from(e)),
+                }
+            }
+        }
+        match self.backend_delete(&self.part_user(1)) {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    /// The header format version the stored credential was written with.
+    ///
+    /// Returns 0 for the legacy unversioned `{part}/{total}|` header and 1
+    /// for the versioned `v1/...` header, so a credential that predates an
+    /// upgrade can be told apart. Only part 1 is read. Returns
+    /// [`Error::NotOurFormat`] if part 1 matches no known format, and
+    /// [`keyring::Error::NoEntry`] if nothing is stored.
+    pub fn format_version(&self) -> Result<u8> {
+        let data = self.read_part(1)?;
+        match decode_part(&data) {
+            Ok((header, _)) => Ok(header.version),
+            Err(_) => Err(Error::NotOurFormat),
+        }
+    }
+
+    /// The number of parts the stored secret is split into, or 0 if nothing
+    /// is stored.
+    ///
+    /// With [`EntryBuilder::cache_total`] enabled, the last total this entry
+    /// read or wrote is returned without contacting the backend.
+    pub fn part_count(&self) -> Result<usize> {
+        if let Some(total) = self.cached_total() {
+            return Ok(total);
+        }
+        match self.stored_header()? {
+            Some(header) => {
+                if cacheable(header.slot, header.typed) {
+                    self.remember_total(header.total, header.generation);
+                }
+                Ok(header.total)
+            }
+            None => Ok(0),
+        }
+    }
+
+    /// The number of bytes the credential occupies in the backend.
+    ///
+    /// This sums the raw stored length of every part, headers included, plus
+    /// the entries stored alongside them: the commit marker under
+    /// [`CommitMarker::Sentinel`] or [`CommitMarker::Manifest`], the content
+    /// type if part 1 is flagged with one, and the parity part if parity
+    /// is enabled. It is therefore larger than the secret itself. Entries
+    /// missing from the layout part 1 describes are not counted. Returns 0
+    /// if nothing is stored.
+    pub fn storage_footprint(&self) -> Result<usize> {
+        let part1 = match self.read_part(1) {
+            Ok(data) => data,
+            Err(Error::Keyring(keyring::Error::NoEntry)) => return Ok(0),
             Err(e) => return Err(e),
         };
+        let (header, _) = decode_part(&part1)?;
 
-        // Delete from back to front for safe resumption
-        for i in (1..=total).rev() {
-            let entry = self.part_entry(i)?;
-            match entry.delete_credential() {
-                Ok(()) => continue,
-                Err(keyring::Error::NoEntry) => continue, // Already deleted
+        let mut footprint = part1.len();
+        let mut users: Vec<String> = (2..=header.total)
+            .map(|i| self.slot_user(i, header.slot))
+            .collect();
+        if self.commit_marker != CommitMarker::Part1 {
+            users.push(self.marker_user());
+        }
+        if header.typed {
+            users.push(self.type_user());
+        }
+        if self.parity && header.total > 1 {
+            users.push(self.parity_user());
+        }
+        for user in users {
+            match self.backend_get(&user) {
+                Ok(data) => footprint += data.len(),
+                Err(keyring::Error::NoEntry) => continue,
                 Err(e) => return Err(Error::from(e)),
             }
         }
+        Ok(footprint)
+    }
 
-        Ok(())
+    /// The number of writes the stored credential has seen, from part 1's
+    /// generation.
+    ///
+    /// Every write stamps its parts with a generation one more than the
+    /// one it replaces, so an application that notes the count after each
+    /// of its own writes can tell that another writer changed the
+    /// credential in between: the count no longer matches. This is tamper
+    /// evidence, not protection, since anyone who can write the store can
+    /// write any count. The count is `u32` on disk and wraps from
+    /// [`u32::MAX`] back to 1, so compare counts for equality rather than
+    /// order. A credential written before generations were recorded counts
+    /// 0 until its next write.
+    ///
+    /// Part 1 is always read from the backend, even with
+    /// [`EntryBuilder::cache_total`] enabled. Returns
+    /// [`keyring::Error::NoEntry`] if nothing is stored.
+    pub fn write_count(&self) -> Result<u64> {
+        match self.stored_header()? {
+            Some(header) => Ok(header.generation.map_or(0, u64::from)),
+            None => Err(Error::Keyring(keyring::Error::NoEntry)),
+        }
+    }
+
+    /// Whether a credential is stored.
+    ///
+    /// Only part 1's header is read, so this does not check that every part
+    /// is present.
+    pub fn exists(&self) -> Result<bool> {
+        Ok(self.part_count()? > 0)
+    }
+
+    /// Whether the stored secret is split across more than one part.
+    ///
+    /// Only part 1's header is read. Returns `Ok(false)` if nothing is
+    /// stored, matching [`Entry::part_count`] reporting 0.
+    pub fn is_chunked(&self) -> Result<bool> {
+        Ok(self.part_count()? > 1)
+    }
+
+    /// Forget the cached part count, so the next operation re-reads part 1.
+    ///
+    /// Call this after the credential may have been changed by anything
+    /// other than this entry and its clones. Does nothing unless
+    /// [`EntryBuilder::cache_total`] is enabled.
+    pub fn invalidate_cache(&self) {
+        if let Some(cache) = &self.total_cache {
+            cache.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Delete parts `start`, `start + 1`, ... until one is missing.
+    ///
+    /// Returns how many parts were deleted. Part 1 is never read, so this is
+    /// safe to use when its total may be stale, e.g. to clean up surplus or
+    /// orphaned parts. The scan stops after part [`MAX_PARTS`](crate::MAX_PARTS) at the latest.
+    ///
+    /// Parts are deleted front to back, so if this is interrupted, parts
+    /// after the first gap are only found again by calling it with a later
+    /// `start`.
+    pub fn delete_parts_from(&self, start: usize) -> Result<usize> {
+        if start == 0 {
+            return Err(Error::InvalidArgument("parts are numbered from 1".into()));
+        }
+        self.invalidate_cache();
+
+        let mut deleted = 0;
+        for part in start..=MAX_PARTS {
+            match self.backend_delete(&self.part_user(part)) {
+                Ok(()) => deleted += 1,
+                Err(keyring::Error::NoEntry) => break,
+                Err(e) => return Err(Error::from(e)),
+            }
+        }
+        Ok(deleted)
+    }
+
+    /// Move the stored secret to `new_service`, keeping the same user.
+    ///
+    /// The secret is read and validated, written to the new service with
+    /// [`Entry::set_secret`], and read back. The original parts are deleted
+    /// only once the copy matches, so any failure before that point leaves
+    /// the original intact. The copy is chunked the way a fresh write to the
+    /// new service would be, which may differ from the original layout.
+    ///
+    /// Any secret already stored for the user under `new_service` is
+    /// overwritten.
+    pub fn move_service(&self, new_service: &str) -> Result<()> {
+        if new_service == self.service {
+            return Err(Error::InvalidArgument(
+                "new service is the same as the current one".into(),
+            ));
+        }
+        let target = self.with_service(new_service)?;
+
+        let secret = self.get_secret()?;
+        target.set_secret(&secret)?;
+        if target.get_secret()? != secret {
+            return Err(Error::CorruptedSecret(
+                "copy in the new service does not match the original".into(),
+            ));
+        }
+
+        self.delete_credential()
+    }
+
+    /// Move the stored secret from one backend to another.
+    ///
+    /// This entry's service, user and configuration are used on both sides;
+    /// its own backend is not touched. The secret is read from `from`,
+    /// written to `to` with [`Entry::set_secret`] and read back, and only
+    /// once the copy matches is it deleted from `from`. Any failure before
+    /// that point leaves the source intact. As with
+    /// [`Entry::move_service`], the copy is chunked the way a fresh write
+    /// would be, and a secret already stored in `to` is overwritten.
+    ///
+    /// [`KeyringBackend::new`](crate::KeyringBackend::new) gives the
+    /// platform keyring entries use by default. Any other store, such as a
+    /// file a secret was kept in before, is migrated by implementing
+    /// [`Backend`](crate::Backend) for it: three methods reading, writing
+    /// and deleting one value by service and part name, reporting a
+    /// missing one as [`keyring::Error::NoEntry`].
+    pub fn migrate_backend(&self, from: Box<dyn Backend>, to: Box<dyn Backend>) -> Result<()> {
+        let mut source = self.with_service(&self.service)?;
+        source.backend = Arc::from(from);
+        let mut target = self.with_service(&self.service)?;
+        target.backend = Arc::from(to);
+
+        let secret = source.get_secret()?;
+        target.set_secret(&secret)?;
+        if target.get_secret()? != secret {
+            return Err(Error::CorruptedSecret(
+                "copy in the new backend does not match the original".into(),
+            ));
+        }
+
+        source.delete_credential()
+    }
+
+    /// An entry for the same user and backend in another service.
+    fn with_service(&self, service: &str) -> Result<Self> {
+        validate_service(service)?;
+        let entry = Self {
+            service: service.to_string(),
+            user: self.user.clone(),
+            encoded_user: self.encoded_user.clone(),
+            backend: Arc::clone(&self.backend),
+            total_cache: self
+                .total_cache
+                .as_ref()
+                .map(|_| Arc::new(AtomicU64::new(0))),
+            commit_marker: self.commit_marker,
+            write_order: self.write_order,
+            chunking: self.chunking,
+            hash_chain: self.hash_chain,
+            verify_writes: self.verify_writes,
+            clock: Arc::clone(&self.clock),
+            warn_parts_threshold: self.warn_parts_threshold,
+            on_many_parts: self.on_many_parts.clone(),
+            max_entry_size: self.max_entry_size,
+            part_width: self.part_width,
+            max_parts: self.max_parts,
+            label: self.label.clone(),
+            comment: self.comment.clone(),
+            parity: self.parity,
+        };
+        entry.check_names()?;
+        Ok(entry)
+    }
+
+    /// Check that every name this entry stores under fits the platform.
+    ///
+    /// The longest is a staged part with the highest part number, which is
+    /// measured together with the service, since stores such as the Windows
+    /// Credential Manager combine the two into one target name.
+    fn check_names(&self) -> Result<()> {
+        check_name_len(&format!("{}.{}", self.staged_user(MAX_PARTS), self.service))
+    }
+
+    /// The backend user name holding the given part number, counting from 1.
+    pub(crate) fn part_user(&self, part: usize) -> String {
+        let width = self.part_width.unwrap_or(0);
+        format!("{}.{:0width$}", self.encoded_user, part)
+    }
+
+    /// Check that `total` parts can be named at the configured part width.
+    pub(crate) fn check_part_width(&self, total: usize) -> Result<()> {
+        match self.part_width {
+            Some(width) if total.to_string().len() > width => Err(Error::InvalidArgument(format!(
+                "{} parts cannot be numbered in {} digits; raise the part width",
+                total, width
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// The backend user name holding the given part in the given slot.
+    ///
+    /// Part 1 always uses the primary name, since it says which slot the
+    /// other parts are in.
+    pub(crate) fn slot_user(&self, part: usize, slot: u8) -> String {
+        match (part, slot) {
+            (1, _) | (_, 0) => self.part_user(part),
+            // Part names otherwise end in digits, so the suffix never collides
+            _ => format!("{}b", self.part_user(part)),
+        }
+    }
+
+    /// Confirm the backend responds, without touching a real credential.
+    fn probe(&self) -> Result<()> {
+        // Like the marker, `#` keeps this out of the part namespace
+        match self.backend_get(&format!("{}#probe", self.encoded_user)) {
+            Ok(_) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    /// The backend user name holding the commit marker.
+    pub(crate) fn marker_user(&self) -> String {
+        // Part users always end in `.{digits}`, so neither name collides
+        match self.commit_marker {
+            CommitMarker::Manifest => format!("{}#manifest", self.encoded_user),
+            _ => format!("{}#complete", self.encoded_user),
+        }
+    }
+
+    /// The backend user name holding the content type.
+    fn type_user(&self) -> String {
+        format!("{}#type", self.encoded_user)
+    }
+
+    /// Delete the content type, if one is stored.
+    pub(crate) fn delete_content_type(&self) -> Result<()> {
+        match self.backend_delete(&self.type_user()) {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    /// Slots for each part's manifest entry under [`CommitMarker::Manifest`],
+    /// and nothing otherwise, so other writes skip hashing the parts.
+    pub(crate) fn manifest_for(&self, total: usize) -> Vec<ManifestPart> {
+        match self.commit_marker {
+            CommitMarker::Manifest => vec![ManifestPart::of(&[]); total],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Write the commit marker for a completed write of `total` parts,
+    /// whose manifest entries are in `manifest`.
+    pub(crate) fn write_marker(&self, total: usize, manifest: &[ManifestPart]) -> Result<()> {
+        let data = match self.commit_marker {
+            CommitMarker::Part1 => return Ok(()),
+            CommitMarker::Sentinel => encode_marker(total),
+            CommitMarker::Manifest => encode_manifest(manifest),
+        };
+        self.backend_set(&self.marker_user(), &data)
+            .map_err(Error::from)
+    }
+
+    /// Read the manifest, or `None` if the credential was written without
+    /// one.
+    fn read_manifest(&self) -> Result<Option<Vec<ManifestPart>>> {
+        match self.backend_get(&self.marker_user()) {
+            Ok(data) => decode_manifest(&data).map(Some),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    /// Read the total recorded by the commit marker.
+    ///
+    /// A missing marker is [`keyring::Error::NoEntry`] if no part 1 is
+    /// stored either, and corruption otherwise.
+    fn read_marker(&self) -> Result<usize> {
+        match self.backend_get(&self.marker_user()) {
+            Ok(data) => decode_marker(&data),
+            Err(keyring::Error::NoEntry) => match self.stored_header()? {
+                None => Err(Error::Keyring(keyring::Error::NoEntry)),
+                Some(_) => Err(Error::CorruptedSecret(
+                    "missing commit marker; the last write did not complete".into(),
+                )),
+            },
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    /// Delete the commit marker, if one is stored.
+    pub(crate) fn delete_marker(&self) -> Result<()> {
+        match self.backend_delete(&self.marker_user()) {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    // Every backend call goes through these three so each one is traced.
+    // Only names, sizes and errors are logged, never secret bytes.
+
+    pub(crate) fn backend_get(&self, user: &str) -> keyring::Result<Vec<u8>> {
+        let result = self.backend.get_secret(&self.service, user);
+        match &result {
+            Ok(data) => log::trace!("get {}/{}: {} bytes", self.service, user, data.len()),
+            Err(e) => log::trace!("get {}/{}: {}", self.service, user, e),
+        }
+        result
+    }
+
+    pub(crate) fn backend_set(&self, user: &str, data: &[u8]) -> keyring::Result<()> {
+        let result = self.backend.set_secret(&self.service, user, data);
+        match &result {
+            Ok(()) => log::trace!("set {}/{}: {} bytes", self.service, user, data.len()),
+            Err(e) => log::trace!("set {}/{}: {}", self.service, user, e),
+        }
+        result
+    }
+
+    /// Wait for the backend to persist the parts written so far, before
+    /// part 1 commits them.
+    pub(crate) fn barrier(&self) -> Result<()> {
+        let result = self.backend.flush();
+        if let Err(e) = &result {
+            log::trace!("flush {}: {}", self.service, e);
+        }
+        result.map_err(Error::from)
+    }
+
+    pub(crate) fn backend_delete(&self, user: &str) -> keyring::Result<()> {
+        let result = self.backend.delete_credential(&self.service, user);
+        match &result {
+            Ok(()) => log::trace!("delete {}/{}: ok", self.service, user),
+            Err(e) => log::trace!("delete {}/{}: {}", self.service, user, e),
+        }
+        result
+    }
+
+    /// Read the raw stored bytes of a part.
+    pub(crate) fn read_part(&self, part: usize) -> Result<Vec<u8>> {
+        self.read_part_in(part, 0)
+    }
+
+    /// Read the raw stored bytes of a part in the given slot.
+    pub(crate) fn read_part_in(&self, part: usize, slot: u8) -> Result<Vec<u8>> {
+        self.backend_get(&self.slot_user(part, slot))
+            .map_err(Error::from)
+    }
+
+    /// Encode and write a single part of `secret`, using `buf` as scratch.
+    fn write_part(
+        &self,
+        part: usize,
+        layout: &Layout,
+        secret: &[u8],
+        buf: &mut Vec<u8>,
+    ) -> Result<()> {
+        encode_layout_part(part, layout, secret, buf)?;
+        let user = self.slot_user(part, layout.slot);
+        self.backend_set(&user, buf)?;
+        self.describe_part(&user, part, layout.total);
+        Ok(())
+    }
+
+    /// Tag a part just written with the configured label and comment.
+    ///
+    /// The attributes only help browsing, so a failure is logged instead
+    /// of failing the write.
+    pub(crate) fn describe_part(&self, user: &str, part: usize, total: usize) {
+        if self.label.is_none() && self.comment.is_none() {
+            return;
+        }
+        let label = self
+            .label
+            .as_ref()
+            .map(|label| format!("{} (part {} of {})", label, part, total));
+        let mut attributes = HashMap::new();
+        if let Some(label) = &label {
+            attributes.insert("label", label.as_str());
+        }
+        if let Some(comment) = &self.comment {
+            attributes.insert("comment", comment.as_str());
+        }
+        if let Err(e) = self
+            .backend
+            .update_attributes(&self.service, user, &attributes)
+        {
+            log::warn!("failed to tag {}/{}: {}", self.service, user, e);
+        }
+    }
+
+    /// The largest chunk size this entry writes with, leaving room for an
+    /// expiry on part 1 if `expiring`.
+    pub(crate) fn chunk_limit(&self, expiring: bool) -> usize {
+        chunk_limit(self.max_entry_size, self.hash_chain, expiring)
+    }
+
+    /// The current time according to the entry's clock.
+    pub(crate) fn now(&self) -> std::time::SystemTime {
+        self.clock.now()
+    }
+
+    /// Delete the credential and return [`Error::Expired`] if `expires`, in
+    /// seconds since the Unix epoch, has passed.
+    fn purge_if_expired(&self, expires: u64) -> Result<()> {
+        let now = self
+            .now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs());
+        if now < expires {
+            return Ok(());
+        }
+        log::trace!("{}/{} expired, deleting it", self.service, self.user);
+        self.delete_credential()?;
+        Err(Error::Expired)
+    }
+
+    /// The cached total, if caching is enabled and a total is known.
+    fn cached_total(&self) -> Option<usize> {
+        self.cached().map(|(total, _)| total)
+    }
+
+    /// The cached total and the generation it was written with.
+    fn cached(&self) -> Option<(usize, Option<u32>)> {
+        let cache = self.total_cache.as_ref()?;
+        let packed = cache.load(Ordering::Relaxed);
+        let total = (packed & u64::from(u32::MAX)) as usize;
+        let generation = (packed >> 32) as u32;
+        (total != 0).then_some((total, (generation != 0).then_some(generation)))
+    }
+
+    /// Record the total last read or written, and its generation, if
+    /// caching is enabled.
+    pub(crate) fn remember_total(&self, total: usize, generation: Option<u32>) {
+        if let Some(cache) = &self.total_cache {
+            let packed = u64::from(generation.unwrap_or(0)) << 32 | total as u64;
+            cache.store(packed, Ordering::Relaxed);
+        }
+    }
+
+    /// Read a part and extract just its header.
+    fn read_header(&self, part: usize) -> Result<Header> {
+        let data = self.read_part(part)?;
+        let (header, _) = decode_part(&data)?;
+        Ok(header)
+    }
+
+    /// Read part 1's header, or `None` if no credential is stored.
+    pub(crate) fn stored_header(&self) -> Result<Option<Header>> {
+        match self.read_header(1) {
+            Ok(header) => Ok(Some(header)),
+            Err(Error::Keyring(keyring::Error::NoEntry)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Delete the given parts from back to front for safe resumption.
+    ///
+    /// Parts that are already missing are skipped.
+    pub(crate) fn delete_parts(&self, parts: RangeInclusive<usize>) -> Result<()> {
+        self.delete_parts_in(parts, 0)
+    }
+
+    /// Like [`Entry::delete_parts`], for the parts in the given slot.
+    pub(crate) fn delete_parts_in(&self, parts: RangeInclusive<usize>, slot: u8) -> Result<()> {
+        for i in parts.rev() {
+            match self.backend_delete(&self.slot_user(i, slot)) {
+                Ok(()) => continue,
+                Err(keyring::Error::NoEntry) => continue, // Already deleted
+                Err(e) => return Err(Error::from(e)),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How a secret is split up for one write.
+pub(crate) struct Layout {
+    pub(crate) total: usize,
+    pub(crate) chunk_size: usize,
+    /// The bytes of the secret each part holds, indexed from part 1.
+    pub(crate) ranges: Vec<Range<usize>>,
+    /// Every part's hash chain link, or empty if the secret is not chained.
+    links: Vec<[u8; DIGEST_LEN]>,
+    /// The expiry recorded on part 1, in seconds since the Unix epoch.
+    expires: Option<u64>,
+    /// Which names parts `2..=total` are written under.
+    pub(crate) slot: u8,
+    /// Whether part 1 is flagged as having a content type.
+    typed: bool,
+    /// The generation every part is stamped with.
+    pub(crate) generation: Option<u32>,
+}
+
+/// The generation to stamp on a write over one stamped `old`, starting at 1
+/// and wrapping back to it after [`u32::MAX`].
+fn next_generation(old: Option<u32>) -> u32 {
+    match old {
+        Some(old) if old < u32::MAX => old + 1,
+        _ => 1,
+    }
+}
+
+/// Whether a credential's total can be cached: only those whose parts use
+/// the primary names and that have no content type to delete alongside.
+fn cacheable(slot: u8, typed: bool) -> bool {
+    slot == 0 && !typed
+}
+
+/// How [`Entry::store`] writes a secret.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum WriteMode {
+    /// Overwrite parts in place, as [`Entry::set_secret`] does.
+    Update,
+    /// Write to the unused slot, as [`Entry::replace_secret`] describes.
+    Replace,
+    /// Overwrite in place at the full chunk size, ignoring the one part 1
+    /// records.
+    Compact,
+    /// Overwrite in place, recording progress as
+    /// [`Entry::set_secret_resumable`] describes.
+    Resumable,
+    /// Write at the given chunk size, reading every part back, as
+    /// [`Entry::reshape`] describes.
+    Reshape(usize),
+}
+
+/// A secret being read part by part, after part 1 was validated.
+pub(crate) struct Reading {
+    /// The payloads read so far.
+    pub(crate) payload: Vec<u8>,
+    /// Part 1's header.
+    pub(crate) header: Header,
+    /// The total every part must agree with.
+    pub(crate) total: usize,
+    pub(crate) chain: ChainCheck,
+    /// The parts the manifest lists, under [`CommitMarker::Manifest`].
+    pub(crate) manifest: Option<Vec<ManifestPart>>,
+}
+
+/// Record the stored bytes of `part` in a manifest from
+/// [`Entry::manifest_for`], if it has entries.
+pub(crate) fn record(manifest: &mut [ManifestPart], part: usize, data: &[u8]) {
+    if let Some(entry) = manifest.get_mut(part - 1) {
+        *entry = ManifestPart::of(data);
+    }
+}
+
+impl TryFrom<&str> for Entry {
+    type Error = Error;
+
+    /// See [`Entry::for_user`].
+    fn try_from(user: &str) -> Result<Self> {
+        Self::for_user(user)
+    }
+}
+
+impl TryFrom<String> for Entry {
+    type Error = Error;
+
+    /// See [`Entry::for_user`].
+    fn try_from(user: String) -> Result<Self> {
+        Self::for_user(&user)
+    }
+}
+
+/// Return [`Error::Cancelled`] if `cancel` is given and set.
+pub(crate) fn check_cancelled(cancel: Option<&AtomicBool>) -> Result<()> {
+    match cancel {
+        Some(cancel) if cancel.load(Ordering::Relaxed) => Err(Error::Cancelled),
+        _ => Ok(()),
+    }
+}
+
+/// Encode part `part` of `secret` as laid out by `layout` into `buf`.
+pub(crate) fn encode_layout_part(
+    part: usize,
+    layout: &Layout,
+    secret: &[u8],
+    buf: &mut Vec<u8>,
+) -> Result<()> {
+    let Layout {
+        total, chunk_size, ..
+    } = *layout;
+    // plan sizes the ranges from `secret`, so every part's range is in
+    // bounds; check anyway rather than panic if that ever stops holding
+    let range = part
+        .checked_sub(1)
+        .and_then(|i| layout.ranges.get(i))
+        .cloned();
+    let chunk_data = range.and_then(|range| secret.get(range)).ok_or_else(|| {
+        Error::InvalidArgument(format!(
+            "part {} of {} is out of range for a {}-byte secret",
+            part,
+            total,
+            secret.len()
+        ))
+    })?;
+
+    let mut header = Header::new(part, total);
+    if part == 1 {
+        // Part 1 records the geometry so later updates can reuse it
+        header.chunk_size = Some(chunk_size);
+        header.expires = layout.expires;
+        header.slot = layout.slot;
+        header.typed = layout.typed;
+    }
+    header.chain = layout.links.get(part - 1).copied();
+    header.generation = layout.generation;
+    encode_part_into(&header, chunk_data, buf);
+    Ok(())
+}
+
+/// Compare two byte strings without short-circuiting on the first difference.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let diff = a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y));
+    // Keep the optimizer from turning the fold back into an early exit
+    std::hint::black_box(diff) == 0
+}
+
+/// The checks [`crate::validate_service`] documents.
+pub(crate) fn validate_service(service: &str) -> Result<()> {
+    if service.is_empty() {
+        return Err(Error::EmptyField(Field::Service));
+    }
+    Ok(())
+}
+
+/// Fail with a clear error if `name` is too long for the platform's store.
+fn check_name_len(name: &str) -> Result<()> {
+    match Platform::current().max_name_len() {
+        Some(limit) if name.chars().count() > limit => Err(Error::InvalidArgument(format!(
+            "target name too long: {} characters, but the platform allows {}",
+            name.chars().count(),
+            limit
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// The checks [`crate::validate_user`] documents.
+pub(crate) fn validate_user(user: &str) -> Result<()> {
+    if user.is_empty() {
+        return Err(Error::EmptyField(Field::User));
+    }
+    Ok(())
+}
+
+/// Percent-encode the `.` separator and `%` itself in a base user name,
+/// so `{user}.{part}` names never collide between different users.
+fn encode_user(user: &str) -> String {
+    let mut encoded = String::with_capacity(user.len());
+    for c in user.chars() {
+        match c {
+            '%' => encoded.push_str("%25"),
+            '.' => encoded.push_str("%2E"),
+            c => encoded.push(c),
+        }
+    }
+    encoded
+}
+
+/// Encode a namespace like a user, and its `:` separator too.
+fn encode_namespace(namespace: &str) -> String {
+    // `%` is already encoded, so the `%3A` this adds is unambiguous
+    encode_user(namespace).replace(':', "%3A")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::SpyBackend;
+    use crate::chunk::{chunks_needed, max_chunk_size};
+    use crate::format::{encode_part, CURRENT_VERSION};
+    use crate::test_util::{part_exists, spy_entry, take_ops, write_raw, FaultyBackend, SERVICE};
+    use std::sync::Mutex;
+
+    /// The inverse of [`encode_user`].
+    fn decode_user(encoded: &str) -> String {
+        encoded.replace("%2E", ".").replace("%25", "%")
+    }
+
+    #[test]
+    fn test_shrinking_overwrite_removes_stale_parts() {
+        let (entry, spy) = spy_entry("shrink");
+        let chunk_size = max_chunk_size();
+
+        let large = vec![0xAA; chunk_size * 5];
+        entry.set_secret(&large).unwrap();
+        for part in 1..=5 {
+            assert!(part_exists(&spy, &entry, part), "part {part} should exist");
+        }
+
+        let small = vec![0x55; chunk_size * 2];
+        entry.set_secret(&small).unwrap();
+        assert!(part_exists(&spy, &entry, 1));
+        assert!(part_exists(&spy, &entry, 2));
+        for part in 3..=5 {
+            assert!(
+                !part_exists(&spy, &entry, part),
+                "part {part} should be gone"
+            );
+        }
+        assert_eq!(entry.get_secret().unwrap(), small);
+
+        entry.delete_credential().unwrap();
+        assert!(!part_exists(&spy, &entry, 1));
+    }
+
+    #[test]
+    fn test_small_overwrite_makes_minimal_calls() {
+        let (entry, spy) = spy_entry("small-overwrite");
+
+        entry.set_password("first").unwrap();
+        assert_eq!(
+            take_ops(&spy),
+            [
+                ("get", "small-overwrite.1".into()),
+                ("delete", "small-overwrite#progress".into()),
+                ("set", "small-overwrite.1".into())
+            ]
+        );
+
+        entry.set_password("second").unwrap();
+        assert_eq!(
+            take_ops(&spy),
+            [
+                ("get", "small-overwrite.1".into()),
+                ("delete", "small-overwrite#progress".into()),
+                ("set", "small-overwrite.1".into())
+            ]
+        );
+        assert_eq!(entry.get_password().unwrap(), "second");
+    }
+
+    #[test]
+    fn test_small_overwrite_of_large_secret_removes_surplus() {
+        let (entry, spy) = spy_entry("small-after-large");
+
+        entry.set_secret(vec![1; max_chunk_size() * 3]).unwrap();
+        entry.set_secret(b"tiny").unwrap();
+        for part in 2..=3 {
+            assert!(
+                !part_exists(&spy, &entry, part),
+                "part {part} should be gone"
+            );
+        }
+        assert_eq!(entry.get_secret().unwrap(), b"tiny");
+    }
+
+    #[test]
+    fn test_overwrite_grow_shrink_and_same_size() {
+        let user = "resize";
+        let (entry, spy) = spy_entry(user);
+        let chunk_size = max_chunk_size();
+        let op = |name, part: usize| (name, format!("{user}.{part}"));
+
+        entry.set_secret(vec![1; chunk_size * 2]).unwrap();
+        take_ops(&spy);
+
+        // Grow: new parts are written high to low, part 1 last
+        let grown = vec![2; chunk_size * 4];
+        entry.set_secret(&grown).unwrap();
+        assert_eq!(
+            take_ops(&spy),
+            [
+                op("get", 1),
+                ("delete", format!("{user}#progress")),
+                op("set", 4),
+                op("set", 3),
+                op("set", 2),
+                op("set", 1)
+            ]
+        );
+        assert_eq!(entry.get_secret().unwrap(), grown);
+        take_ops(&spy);
+
+        // Same size: every part is overwritten, nothing is deleted
+        let same = vec![3; chunk_size * 4];
+        entry.set_secret(&same).unwrap();
+        assert_eq!(
+            take_ops(&spy),
+            [
+                op("get", 1),
+                ("delete", format!("{user}#progress")),
+                op("set", 4),
+                op("set", 3),
+                op("set", 2),
+                op("set", 1)
+            ]
+        );
+        assert_eq!(entry.get_secret().unwrap(), same);
+        take_ops(&spy);
+
+        // Shrink: surplus parts are deleted before part 1 commits
+        let shrunk = vec![4; chunk_size + 1];
+        entry.set_secret(&shrunk).unwrap();
+        assert_eq!(
+            take_ops(&spy),
+            [
+                op("get", 1),
+                ("delete", format!("{user}#progress")),
+                op("set", 2),
+                op("delete", 4),
+                op("delete", 3),
+                op("set", 1)
+            ]
+        );
+        assert_eq!(entry.get_secret().unwrap(), shrunk);
+        assert!(!part_exists(&spy, &entry, 3));
+        assert!(!part_exists(&spy, &entry, 4));
+    }
+
+    #[test]
+    fn test_update_reuses_recorded_chunk_size() {
+        let (entry, _spy) = spy_entry("geometry");
+
+        // A credential written with a smaller chunk size than the default
+        let header = Header {
+            chunk_size: Some(100),
+            ..Header::new(1, 1)
+        };
+        write_raw(&entry, 1, &encode_part(&header, b"old"));
+
+        let secret = vec![7; 250];
+        entry.set_secret(&secret).unwrap();
+        let part1 = entry.read_part(1).unwrap();
+        let (header, payload) = decode_part(&part1).unwrap();
+        assert_eq!(header.total, 3);
+        assert_eq!(header.chunk_size, Some(100));
+        assert_eq!(payload.len(), 100);
+        assert_eq!(entry.get_secret().unwrap(), secret);
+    }
+
+    #[test]
+    fn test_read_legacy_credential() {
+        let (entry, spy) = spy_entry("legacy");
+        write_raw(&entry, 1, b"1/2|hello ");
+        write_raw(&entry, 2, b"2/2|world");
+
+        assert_eq!(entry.get_secret().unwrap(), b"hello world");
+
+        // An update rewrites the credential in the current format
+        entry.set_secret(b"updated").unwrap();
+        let (header, _) = decode_part(&entry.read_part(1).unwrap()).unwrap();
+        assert_eq!(header.version, crate::format::CURRENT_VERSION);
+        assert!(!part_exists(&spy, &entry, 2));
+    }
+
+    #[test]
+    fn test_reader_handles_credentials_from_either_format() {
+        let (legacy, _spy) = spy_entry("rolling-old");
+        write_raw(&legacy, 1, b"1/2|written by ");
+        write_raw(&legacy, 2, b"2/2|an old client");
+        let (current, _spy) = spy_entry("rolling-new");
+        current.set_secret(b"written by a new client").unwrap();
+
+        assert_eq!(legacy.get_password().unwrap(), "written by an old client");
+        assert_eq!(current.get_password().unwrap(), "written by a new client");
+    }
+
+    #[test]
+    fn test_get_secret_reports_inconsistent_total() {
+        let (entry, _spy) = spy_entry("inconsistent-total");
+        write_raw(&entry, 1, b"v1/1/3|a");
+        write_raw(&entry, 2, b"v1/2/4|b");
+        write_raw(&entry, 3, b"v1/3/3|c");
+
+        let err = entry.get_secret().unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InconsistentTotal {
+                part: 2,
+                expected: 3,
+                found: 4
+            }
+        ));
+    }
+
+    #[test]
+    fn test_set_secret_rejects_too_many_parts() {
+        let user = "too-many-parts";
+        let (entry, spy) = spy_entry(user);
+
+        // A recorded chunk size of 1 byte makes the part cap easy to reach
+        let header = Header {
+            chunk_size: Some(1),
+            ..Header::new(1, 1)
+        };
+        write_raw(&entry, 1, &encode_part(&header, b"x"));
+        take_ops(&spy);
+
+        let err = entry.set_secret(vec![0; MAX_PARTS + 1]).unwrap_err();
+        assert!(
+            matches!(err, Error::InvalidArgument(ref msg) if msg.contains("exceeds maximum parts"))
+        );
+        assert_eq!(take_ops(&spy), [("get", format!("{user}.1"))]);
+
+        entry.set_secret(vec![0; MAX_PARTS]).unwrap();
+        assert!(part_exists(&spy, &entry, MAX_PARTS));
+    }
+
+    #[test]
+    fn test_max_parts_caps_writes_below_the_format() {
+        let spy = SpyBackend::new();
+        let entry = Entry::builder(SERVICE)
+            .max_parts(2)
+            .build("max-parts")
+            .unwrap()
+            .with_backend(Box::new(spy.clone()));
+        assert_eq!(entry.max_secret_size(), max_chunk_size() * 2);
+        entry.set_secret(vec![1; max_chunk_size() * 2]).unwrap();
+        let err = entry
+            .set_secret(vec![2; max_chunk_size() * 2 + 1])
+            .unwrap_err();
+        assert!(
+            matches!(err, Error::InvalidArgument(ref msg) if msg.contains("maximum parts (2)"))
+        );
+        assert!(!part_exists(&spy, &entry, 3));
+
+        for parts in [0, MAX_PARTS + 1] {
+            assert!(Entry::builder(SERVICE)
+                .max_parts(parts)
+                .build("max-parts")
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn test_delete_is_idempotent() {
+        let (entry, spy) = spy_entry("delete");
+
+        entry.delete_credential().unwrap();
+        entry.set_secret(vec![9; max_chunk_size() * 2]).unwrap();
+        entry.delete_credential().unwrap();
+        assert!(!part_exists(&spy, &entry, 1));
+        assert!(!part_exists(&spy, &entry, 2));
+        entry.delete_credential().unwrap();
+    }
+
+    #[test]
+    fn test_delete_parts_from_stops_at_first_gap() {
+        let (entry, spy) = spy_entry("orphans");
+        for part in [2, 3, 4, 6] {
+            write_raw(&entry, part, b"orphan");
+        }
+
+        assert_eq!(entry.delete_parts_from(2).unwrap(), 3);
+        for part in 2..=4 {
+            assert!(!part_exists(&spy, &entry, part));
+        }
+        assert!(part_exists(&spy, &entry, 6));
+        assert!(!take_ops(&spy).iter().any(|(kind, _)| *kind == "get"));
+
+        assert_eq!(entry.delete_parts_from(6).unwrap(), 1);
+        assert_eq!(entry.delete_parts_from(6).unwrap(), 0);
+        assert!(entry.delete_parts_from(0).is_err());
+    }
+
+    #[test]
+    fn test_new_bytes_requires_utf8() {
+        let entry = Entry::new_bytes(b"service", "us\u{e9}r".as_bytes()).unwrap();
+        assert_eq!(entry.part_user(1), "us\u{e9}r.1");
+
+        assert!(matches!(
+            Entry::new_bytes(b"service", b"\xff\xfe"),
+            Err(Error::NotUtf8(Field::User))
+        ));
+        assert!(matches!(
+            Entry::new_bytes(b"\xc3", b"user"),
+            Err(Error::NotUtf8(Field::Service))
+        ));
+        assert!(matches!(
+            Entry::new_bytes(b"service", b""),
+            Err(Error::EmptyField(Field::User))
+        ));
+        assert!(matches!(
+            Entry::new("", "user"),
+            Err(Error::EmptyField(Field::Service))
+        ));
+    }
+
+    #[test]
+    fn test_for_user_uses_default_service() {
+        let entry = Entry::for_user("alice").unwrap();
+        assert_eq!(entry.service, crate::DEFAULT_SERVICE);
+        assert_eq!(entry.user, "alice");
+        assert_eq!(Entry::try_from("bob").unwrap().user, "bob");
+        assert_eq!(
+            Entry::try_from(String::from("carol")).unwrap().user,
+            "carol"
+        );
+        assert!(matches!(
+            Entry::for_user(""),
+            Err(Error::EmptyField(Field::User))
+        ));
+        assert!(matches!(
+            Entry::try_from(""),
+            Err(Error::EmptyField(Field::User))
+        ));
+    }
+
+    #[test]
+    fn test_user_encoding_round_trips() {
+        for user in [
+            "alice",
+            "a.b",
+            "a%2Eb",
+            "100%",
+            "%%..",
+            "\u{e9}l\u{e8}ve.\u{1f511}",
+        ] {
+            let encoded = encode_user(user);
+            assert!(!encoded.contains('.'), "{encoded:?} contains a separator");
+            assert_eq!(decode_user(&encoded), user);
+        }
+    }
+
+    #[test]
+    fn test_dotted_users_do_not_collide() {
+        let spy = SpyBackend::new();
+        let entry = |user| {
+            Entry::new(SERVICE, user)
+                .unwrap()
+                .with_backend(Box::new(spy.clone()))
+        };
+        // Users that look like other users' part names, or like their encoding
+        let users = ["a", "a.1", "a.1.1", "a.b", "a%2Eb", "\u{e9}.\u{e9}"];
+        for user in users {
+            entry(user).set_password(user).unwrap();
+        }
+        for user in users {
+            assert_eq!(entry(user).get_password().unwrap(), user);
+        }
+        assert_eq!(entry("a.b").part_user(1), "a%2Eb.1");
+        assert_eq!(entry("a%2Eb").part_user(1), "a%252Eb.1");
+    }
+
+    #[test]
+    fn test_part_one_is_always_the_final_write() {
+        // Crash safety relies on part 1 committing the write, whatever was stored before
+        let chunk_size = max_chunk_size();
+        let sizes = [
+            0,
+            1,
+            chunk_size,
+            chunk_size + 1,
+            chunk_size * 3,
+            chunk_size * 5 - 1,
+        ];
+        for previous in [None, Some(1), Some(chunk_size * 2), Some(chunk_size * 4)] {
+            for size in sizes {
+                let (entry, spy) = spy_entry("commit-order");
+                if let Some(previous) = previous {
+                    entry.set_secret(vec![1; previous]).unwrap();
+                }
+                take_ops(&spy);
+
+                entry.set_secret(vec![2; size]).unwrap();
+                let ops = take_ops(&spy);
+                let part1 = ("set", entry.part_user(1));
+                assert_eq!(ops.last(), Some(&part1), "{previous:?} -> {size}: {ops:?}");
+                assert_eq!(ops.iter().filter(|op| **op == part1).count(), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_part_count() {
+        let (entry, _spy) = spy_entry("part-count");
+        assert_eq!(entry.part_count().unwrap(), 0);
+        entry.set_secret(vec![0; max_chunk_size() + 1]).unwrap();
+        assert_eq!(entry.part_count().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_storage_footprint_includes_headers() {
+        let (entry, _spy) = spy_entry("footprint");
+        assert_eq!(entry.storage_footprint().unwrap(), 0);
+
+        let secret = vec![0; max_chunk_size() + 10];
+        entry.set_secret(&secret).unwrap();
+        let parts = entry.read_part(1).unwrap().len() + entry.read_part(2).unwrap().len();
+        assert_eq!(entry.storage_footprint().unwrap(), parts);
+        assert!(parts > secret.len());
+
+        let (mut entry, spy) = spy_entry("footprint-extras");
+        entry.parity = true;
+        entry.set_secret_with_type(&secret, "text/plain").unwrap();
+        let parity = spy.get_secret(SERVICE, &entry.parity_user()).unwrap();
+        let parts = entry.read_part(1).unwrap().len() + entry.read_part(2).unwrap().len();
+        assert_eq!(
+            entry.storage_footprint().unwrap(),
+            parts + "text/plain".len() + parity.len()
+        );
+    }
+
+    #[test]
+    fn test_exists() {
+        let (entry, _spy) = spy_entry("exists");
+        assert!(!entry.exists().unwrap());
+        entry.set_secret(b"x").unwrap();
+        assert!(entry.exists().unwrap());
+        entry.delete_credential().unwrap();
+        assert!(!entry.exists().unwrap());
+    }
+
+    #[test]
+    fn test_is_chunked() {
+        let (entry, spy) = spy_entry("is-chunked");
+        assert!(!entry.is_chunked().unwrap());
+        entry.set_secret(b"small").unwrap();
+        assert!(!entry.is_chunked().unwrap());
+        entry.set_secret(vec![0; max_chunk_size() + 1]).unwrap();
+        take_ops(&spy);
+        assert!(entry.is_chunked().unwrap());
+        assert_eq!(take_ops(&spy), [("get", entry.part_user(1))]);
+    }
+
+    #[test]
+    fn test_cached_total_skips_part_one_reads() {
+        let user = "cached-total";
+        let spy = SpyBackend::new();
+        let entry = Entry::builder(SERVICE)
+            .cache_total(true)
+            .build(user)
+            .unwrap()
+            .with_backend(Box::new(spy.clone()));
+        entry.set_secret(vec![0; max_chunk_size() * 2]).unwrap();
+        take_ops(&spy);
+
+        entry.get_secret().unwrap();
+        take_ops(&spy);
+        assert_eq!(entry.part_count().unwrap(), 2);
+        entry.delete_credential().unwrap();
+        assert_eq!(
+            take_ops(&spy),
+            [
+                ("delete", format!("{user}.2")),
+                ("delete", format!("{user}.1"))
+            ]
+        );
+
+        // Deleting forgets the total, so the next count asks the backend
+        assert_eq!(entry.part_count().unwrap(), 0);
+        assert_eq!(take_ops(&spy), [("get", format!("{user}.1"))]);
+    }
+
+    #[test]
+    fn test_small_secrets_cost_what_raw_keyring_does() {
+        let user = "single-part";
+        let spy = SpyBackend::new();
+        let entry = Entry::builder(SERVICE)
+            .cache_total(true)
+            .build(user)
+            .unwrap()
+            .with_backend(Box::new(spy.clone()));
+        entry.set_secret(b"first").unwrap();
+        take_ops(&spy);
+
+        entry.set_secret(b"second").unwrap();
+        entry.get_secret().unwrap();
+        assert_eq!(
+            take_ops(&spy),
+            [("set", format!("{user}.1")), ("get", format!("{user}.1"))]
+        );
+
+        // Growing past one part still reads the old header first
+        entry.set_secret(vec![1; max_chunk_size() + 1]).unwrap();
+        assert_eq!(take_ops(&spy)[0], ("get", format!("{user}.1")));
+        entry.set_secret(b"small again").unwrap();
+        assert!(!part_exists(&spy, &entry, 2));
+    }
+
+    #[test]
+    fn test_invalidate_cache_rereads_part_one() {
+        let user = "invalidate-cache";
+        let spy = SpyBackend::new();
+        let entry = Entry::builder(SERVICE)
+            .cache_total(true)
+            .build(user)
+            .unwrap()
+            .with_backend(Box::new(spy.clone()));
+        entry.set_secret(b"small").unwrap();
+
+        // Another writer grows the credential behind the cache's back
+        let other = Entry::new(SERVICE, user)
+            .unwrap()
+            .with_backend(Box::new(spy.clone()));
+        other.set_secret(vec![0; max_chunk_size() * 3]).unwrap();
+        assert_eq!(entry.part_count().unwrap(), 1);
+
+        entry.invalidate_cache();
+        assert_eq!(entry.part_count().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_move_service_relocates_every_part() {
+        let (entry, spy) = spy_entry("move-service");
+        let secret: Vec<u8> = (0..max_chunk_size() * 2 + 5).map(|i| i as u8).collect();
+        entry.set_secret(&secret).unwrap();
+
+        entry.move_service("new-svc").unwrap();
+        for part in 1..=3 {
+            assert!(!part_exists(&spy, &entry, part));
+        }
+        let moved = Entry::new("new-svc", "move-service")
+            .unwrap()
+            .with_backend(Box::new(spy.clone()));
+        assert_eq!(moved.get_secret().unwrap(), secret);
+    }
+
+    #[test]
+    fn test_move_service_keeps_original_on_failure() {
+        let (entry, spy) = spy_entry("move-broken");
+        write_raw(&entry, 1, b"v1/1/2|a");
+        take_ops(&spy);
+
+        // Part 2 is missing, so nothing may be written or deleted
+        assert!(entry.move_service("new-svc").is_err());
+        assert!(!take_ops(&spy).iter().any(|(kind, _)| *kind != "get"));
+        assert!(part_exists(&spy, &entry, 1));
+        assert!(entry.move_service(SERVICE).is_err());
+    }
+
+    #[test]
+    fn test_migrate_backend_moves_secret() {
+        let (entry, _spy) = spy_entry("migrate-backend");
+        let (from, to) = (SpyBackend::new(), SpyBackend::new());
+        let source = entry.clone().with_backend(Box::new(from.clone()));
+        let secret = vec![7; max_chunk_size() + 3];
+        source.set_secret(&secret).unwrap();
+
+        entry
+            .migrate_backend(Box::new(from.clone()), Box::new(to.clone()))
+            .unwrap();
+        assert!(!source.exists().unwrap());
+        let target = entry.clone().with_backend(Box::new(to));
+        assert_eq!(target.get_secret().unwrap(), secret);
+        assert!(matches!(
+            entry.migrate_backend(Box::new(from), Box::new(SpyBackend::new())),
+            Err(Error::Keyring(keyring::Error::NoEntry))
+        ));
+    }
+
+    #[test]
+    fn test_migrate_backend_keeps_source_on_failure() {
+        let (entry, _spy) = spy_entry("migrate-broken");
+        let from = SpyBackend::new();
+        let source = entry.clone().with_backend(Box::new(from.clone()));
+        source
+            .set_password("longer than the thirty-two bytes kept")
+            .unwrap();
+
+        let to = FaultyBackend::truncating(SpyBackend::new(), 32);
+        assert!(matches!(
+            entry.migrate_backend(Box::new(from), Box::new(to)),
+            Err(Error::CorruptedSecret(_))
+        ));
+        assert_eq!(
+            source.get_password().unwrap(),
+            "longer than the thirty-two bytes kept"
+        );
+    }
+
+    #[test]
+    fn test_set_secret_from_chunks_matches_set_secret() {
+        let (entry, _spy) = spy_entry("from-chunks");
+        let secret: Vec<u8> = (0..max_chunk_size() * 2 + 9).map(|i| i as u8).collect();
+        entry.set_secret_from_chunks(secret.chunks(1000)).unwrap();
+        assert_eq!(entry.part_count().unwrap(), 3);
+        assert_eq!(entry.get_secret().unwrap(), secret);
+
+        entry.set_secret_from_chunks([]).unwrap();
+        assert_eq!(entry.get_secret().unwrap(), b"");
+
+        // An endless stream is cut off rather than gathered
+        let endless = std::iter::repeat(&[0u8; 4096][..]);
+        assert!(matches!(
+            entry.set_secret_from_chunks(endless),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert_eq!(entry.get_secret().unwrap(), b"");
+    }
+
+    #[test]
+    fn test_overlong_names_are_rejected_up_front() {
+        let Some(limit) = Platform::current().max_name_len() else {
+            assert!(Entry::new(SERVICE, &"u".repeat(40_000)).is_ok());
+            return;
+        };
+        // The user, the longest suffix (".9999#staged") and the service
+        let fits = limit - SERVICE.len() - ".9999#staged.".len();
+        assert!(Entry::new(SERVICE, &"u".repeat(fits)).is_ok());
+        let err = Entry::new(SERVICE, &"u".repeat(fits + 1)).unwrap_err();
+        assert!(
+            matches!(err, Error::InvalidArgument(ref msg) if msg.starts_with("target name too long"))
+        );
+        assert!(Entry::new_with_target(&"t".repeat(limit + 1), SERVICE, "user").is_err());
+    }
+
+    #[test]
+    fn test_namespaces_isolate_users() {
+        let spy = SpyBackend::new();
+        let build = |namespace: &str| {
+            Entry::builder(SERVICE)
+                .namespace(namespace)
+                .build("alice")
+                .unwrap()
+                .with_backend(Box::new(spy.clone()))
+        };
+        build("tenant42").set_secret(b"42").unwrap();
+        build("tenant7").set_secret(b"7").unwrap();
+        build("a:b.c").set_secret(b"escaped").unwrap();
+        assert!(spy.contains(SERVICE, "tenant42:alice.1"));
+        assert!(spy.contains(SERVICE, "a%3Ab%2Ec:alice.1"));
+        assert_eq!(build("tenant42").get_secret().unwrap(), b"42");
+        assert_eq!(build("tenant7").get_secret().unwrap(), b"7");
+
+        build("tenant42").delete_credential().unwrap();
+        assert_eq!(build("tenant7").get_secret().unwrap(), b"7");
+        assert!(Entry::builder(SERVICE)
+            .namespace("")
+            .build("alice")
+            .is_err());
+    }
+
+    #[test]
+    fn test_get_password_with_encoding() {
+        let (entry, _spy) = spy_entry("encoding");
+        let utf16: Vec<u8> = "pässword"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        entry.set_secret(&utf16).unwrap();
+        assert!(matches!(entry.get_password(), Err(Error::BadEncoding)));
+        assert_eq!(
+            entry.get_password_with_encoding(Encoding::Utf16Le).unwrap(),
+            "pässword"
+        );
+
+        entry.set_secret([b'p', 0xe4, b's', b's']).unwrap();
+        assert_eq!(
+            entry.get_password_with_encoding(Encoding::Latin1).unwrap(),
+            "päss"
+        );
+        assert!(matches!(
+            entry.get_password_with_encoding(Encoding::Utf8),
+            Err(Error::BadEncoding)
+        ));
+    }
+
+    #[test]
+    fn test_delete_force_scans_past_corrupt_part_one() {
+        let (entry, spy) = spy_entry("delete-force");
+        entry.set_secret(vec![1; max_chunk_size() * 2 + 1]).unwrap();
+        write_raw(&entry, 1, b"v1/garbage");
+        assert!(matches!(
+            entry.delete_credential(),
+            Err(Error::CorruptedSecret(_))
+        ));
+
+        entry.delete_force().unwrap();
+        for part in 1..=3 {
+            assert!(!part_exists(&spy, &entry, part));
+        }
+        entry.delete_force().unwrap();
+    }
+
+    #[test]
+    fn test_secret_eq() {
+        let (entry, _spy) = spy_entry("secret-eq");
+        assert!(!entry.secret_eq(b"token").unwrap());
+
+        entry.set_secret(b"token").unwrap();
+        assert!(entry.secret_eq(b"token").unwrap());
+        assert!(!entry.secret_eq(b"tokem").unwrap());
+        assert!(!entry.secret_eq(b"token2").unwrap());
+        assert!(!entry.secret_eq(b"").unwrap());
+    }
+
+    #[test]
+    fn test_write_part_out_of_range_is_an_error() {
+        let (entry, spy) = spy_entry("out-of-range");
+        let layout = Layout {
+            total: 4,
+            chunk_size: 4,
+            ranges: chunk_ranges(16, 4).collect(),
+            links: Vec::new(),
+            expires: None,
+            slot: 0,
+            typed: false,
+            generation: None,
+        };
+        let err = entry
+            .write_part(4, &layout, b"too short", &mut Vec::new())
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidArgument(_)));
+        assert!(take_ops(&spy).is_empty());
+    }
+
+    fn sentinel_entry(user: &str) -> (Entry, SpyBackend) {
+        let spy = SpyBackend::new();
+        let entry = Entry::builder(SERVICE)
+            .commit_marker(CommitMarker::Sentinel)
+            .build(user)
+            .unwrap()
+            .with_backend(Box::new(spy.clone()));
+        (entry, spy)
+    }
+
+    #[test]
+    fn test_sentinel_marker_is_written_last() {
+        let user = "sentinel-order";
+        let (entry, spy) = sentinel_entry(user);
+        entry.set_secret(vec![7; max_chunk_size() + 1]).unwrap();
+        assert_eq!(
+            take_ops(&spy),
+            [
+                ("get", format!("{user}.1")),
+                ("delete", format!("{user}#complete")),
+                ("delete", format!("{user}#progress")),
+                ("set", format!("{user}.2")),
+                ("set", format!("{user}.1")),
+                ("set", format!("{user}#complete")),
+            ]
+        );
+        assert_eq!(entry.get_secret().unwrap(), vec![7; max_chunk_size() + 1]);
+
+        entry.delete_credential().unwrap();
+        assert!(!spy.contains(SERVICE, &format!("{user}#complete")));
+        assert!(matches!(
+            entry.get_secret(),
+            Err(Error::Keyring(keyring::Error::NoEntry))
+        ));
+    }
+
+    #[test]
+    fn test_forward_write_order_commits_with_the_marker() {
+        let user = "forward-order";
+        let spy = SpyBackend::new();
+        let entry = Entry::builder(SERVICE)
+            .commit_marker(CommitMarker::Sentinel)
+            .write_order(WriteOrder::Forward)
+            .build(user)
+            .unwrap()
+            .with_backend(Box::new(spy.clone()));
+        let secret = vec![7; max_chunk_size() * 2 + 1];
+        entry.set_secret(&secret).unwrap();
+        let sets: Vec<_> = take_ops(&spy)
+            .into_iter()
+            .filter(|(kind, _)| *kind == "set")
+            .map(|(_, name)| name)
+            .collect();
+        assert_eq!(
+            sets,
+            [
+                format!("{user}.1"),
+                format!("{user}.2"),
+                format!("{user}.3"),
+                format!("{user}#complete"),
+            ]
+        );
+        assert_eq!(entry.get_secret().unwrap(), secret);
+
+        assert!(matches!(
+            Entry::builder(SERVICE)
+                .write_order(WriteOrder::Forward)
+                .build(user),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_sentinel_detects_interrupted_write() {
+        let (entry, _spy) = sentinel_entry("sentinel-interrupted");
+        // Every part is present, but the marker was never written
+        write_raw(&entry, 1, b"v1/1/2|a");
+        write_raw(&entry, 2, b"v1/2/2|b");
+        assert!(matches!(entry.get_secret(), Err(Error::CorruptedSecret(_))));
+
+        // A marker disagreeing with the parts is caught as well
+        let user = entry.marker_user();
+        entry.backend.set_secret(SERVICE, &user, b"v1/3").unwrap();
+        assert!(matches!(
+            entry.get_secret(),
+            Err(Error::InconsistentTotal {
+                part: 1,
+                expected: 3,
+                found: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn test_max_secret_size_is_exact() {
+        let (entry, spy) = spy_entry("max-secret-size");
+        let mut secret = vec![0; entry.max_secret_size() + 1];
+        assert!(matches!(
+            entry.set_secret(&secret),
+            Err(Error::InvalidArgument(_))
+        ));
+
+        secret.pop();
+        entry.set_secret(&secret).unwrap();
+        assert!(part_exists(&spy, &entry, MAX_PARTS));
+    }
+
+    #[test]
+    fn test_fits_in_one_chunk_matches_the_write() {
+        let (entry, spy) = spy_entry("fits");
+        let limit = max_chunk_size();
+        assert!(crate::fits_in_one_chunk(limit));
+        assert!(!crate::fits_in_one_chunk(limit + 1));
+
+        for len in [limit, limit + 1] {
+            entry.delete_credential().unwrap();
+            entry.set_secret(vec![0; len]).unwrap();
+            assert_eq!(entry.fits_in_one_chunk(len), !part_exists(&spy, &entry, 2));
+        }
+
+        let small = Entry::builder(SERVICE)
+            .max_entry_size(100)
+            .build("fits-small")
+            .unwrap();
+        assert!(small.fits_in_one_chunk(small.chunk_limit(false)));
+        assert!(!small.fits_in_one_chunk(100));
+    }
+
+    /// Collects every log record so tests can inspect what was traced.
+    struct CaptureLogger(std::sync::Mutex<Vec<String>>);
+
+    impl log::Log for CaptureLogger {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            let mut records = self.0.lock().unwrap_or_else(|e| e.into_inner());
+            records.push(record.args().to_string());
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[test]
+    fn test_backend_calls_are_traced_without_secrets() {
+        static LOGGER: CaptureLogger = CaptureLogger(std::sync::Mutex::new(Vec::new()));
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+
+        let (entry, _spy) = spy_entry("traced");
+        let secret = "hunter2-".repeat(max_chunk_size() / 4);
+        entry.set_password(&secret).unwrap();
+        entry.get_password().unwrap();
+        entry.delete_credential().unwrap();
+
+        let records = LOGGER.0.lock().unwrap().clone();
+        let ours: Vec<_> = records.iter().filter(|r| r.contains("traced")).collect();
+        assert!(ours
+            .iter()
+            .any(|r| r.starts_with("set ") && r.contains("traced.2")));
+        assert!(ours
+            .iter()
+            .any(|r| r.starts_with("get ") && r.contains("traced.1")));
+        assert!(ours.iter().any(|r| r.starts_with("delete ")));
+        assert!(!records.iter().any(|r| r.contains("hunter2")));
+    }
+
+    #[test]
+    fn test_hash_chain_detects_swapped_payloads() {
+        let spy = SpyBackend::new();
+        let entry = Entry::builder(SERVICE)
+            .hash_chain(true)
+            .build("hash-chain")
+            .unwrap()
+            .with_backend(Box::new(spy.clone()));
+        let chunk_size = chunk_limit(max_raw_size(), true, false);
+        let secret: Vec<u8> = [vec![1; chunk_size], vec![2; chunk_size], vec![3; 10]].concat();
+        entry.set_secret(&secret).unwrap();
+        assert_eq!(entry.get_secret().unwrap(), secret);
+
+        // Swap the payloads of parts 2 and 3, keeping each header in place
+        let part2 = entry.read_part(2).unwrap();
+        let part3 = entry.read_part(3).unwrap();
+        let (header2, payload2) = decode_part(&part2).unwrap();
+        let (header3, payload3) = decode_part(&part3).unwrap();
+        write_raw(&entry, 2, &encode_part(&header2, payload3));
+        write_raw(&entry, 3, &encode_part(&header3, payload2));
+
+        let err = entry.get_secret().unwrap_err();
+        assert!(matches!(err, Error::CorruptedSecret(ref msg) if msg.contains("part 3")));
+        // Readers verify the chain even when they do not write one
+        let plain = Entry::new(SERVICE, "hash-chain")
+            .unwrap()
+            .with_backend(Box::new(spy.clone()));
+        assert!(plain.get_secret().is_err());
+    }
+
+    #[test]
+    fn test_set_secret_accepts_byte_views() {
+        let (entry, _spy) = spy_entry("as-ref");
+        let slice: &[u8] = b"slice";
+        entry.set_secret(slice).unwrap();
+        assert_eq!(entry.get_secret().unwrap(), b"slice");
+        entry.set_secret(b"array").unwrap();
+        entry.set_secret([1u8, 2, 3]).unwrap();
+        assert_eq!(entry.get_secret().unwrap(), [1, 2, 3]);
+        entry.set_secret(vec![4u8]).unwrap();
+        entry.set_secret("str").unwrap();
+        entry.set_secret(String::from("string")).unwrap();
+        assert_eq!(entry.get_password().unwrap(), "string");
+    }
+
+    /// A backend that notes how many operations the spy had seen at each
+    /// flush.
+    #[derive(Debug)]
+    struct FlushingBackend(SpyBackend, Arc<Mutex<Vec<usize>>>);
+
+    impl Backend for FlushingBackend {
+        fn get_secret(&self, service: &str, user: &str) -> keyring::Result<Vec<u8>> {
+            self.0.get_secret(service, user)
+        }
+
+        fn set_secret(&self, service: &str, user: &str, secret: &[u8]) -> keyring::Result<()> {
+            self.0.set_secret(service, user, secret)
+        }
+
+        fn delete_credential(&self, service: &str, user: &str) -> keyring::Result<()> {
+            self.0.delete_credential(service, user)
+        }
+
+        fn flush(&self) -> keyring::Result<()> {
+            self.1.lock().unwrap().push(self.0.operations().len());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_flush_comes_right_before_part_one() {
+        let spy = SpyBackend::new();
+        let flushes = Arc::new(Mutex::new(Vec::new()));
+        let entry = Entry::new(SERVICE, "flush")
+            .unwrap()
+            .with_backend(Box::new(FlushingBackend(spy.clone(), flushes.clone())));
+        entry
+            .set_secret(vec![1; crate::max_chunk_size() * 2 + 1])
+            .unwrap();
+
+        let flushes = flushes.lock().unwrap();
+        assert_eq!(flushes.len(), 1);
+        let ops = take_ops(&spy);
+        assert_eq!(ops[flushes[0]], ("set", entry.part_user(1)));
+        assert!(ops[..flushes[0]].contains(&("set", entry.part_user(3))));
+    }
+
+    #[test]
+    fn test_verify_writes_detects_mismatch() {
+        let build = |verify| {
+            Entry::builder(SERVICE)
+                .verify_writes(verify)
+                .build("verify-writes")
+                .unwrap()
+                .with_backend(Box::new(FaultyBackend::truncating(SpyBackend::new(), 32)))
+        };
+        let secret = "longer than the thirty-two bytes kept";
+
+        assert!(build(false).set_password(secret).is_ok());
+        let err = build(true).set_password(secret).unwrap_err();
+        assert!(
+            matches!(err, Error::CorruptedSecret(ref msg) if msg == "write verification failed")
+        );
+
+        let (entry, spy) = spy_entry("verify-ok");
+        let entry = Entry {
+            verify_writes: true,
+            ..entry
+        };
+        entry.set_password("short").unwrap();
+        assert_eq!(take_ops(&spy).last(), Some(&("get", entry.part_user(1))));
+    }
+
+    #[test]
+    fn test_max_entry_size_overrides_platform_limit() {
+        let spy = SpyBackend::new();
+        let entry = Entry::builder(SERVICE)
+            .max_entry_size(1024)
+            .build("entry-size")
+            .unwrap()
+            .with_backend(Box::new(spy.clone()));
+        assert_eq!(entry.max_secret_size(), (1024 - 41) * MAX_PARTS);
+
+        entry.set_secret(vec![7; 2900]).unwrap();
+        assert_eq!(entry.part_count().unwrap(), 3);
+        for op in spy.operations() {
+            if let crate::backend::Operation::Set { secret, .. } = op {
+                assert!(secret.len() <= 1024);
+            }
+        }
+        assert_eq!(entry.get_secret().unwrap(), vec![7; 2900]);
+
+        let err = Entry::builder(SERVICE)
+            .max_entry_size(40)
+            .build("entry-size")
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidArgument(_)));
+    }
+
+    #[test]
+    fn test_part_width_pads_every_name() {
+        let spy = SpyBackend::new();
+        let build = |width| {
+            Entry::builder(SERVICE)
+                .max_entry_size(64)
+                .part_width(width)
+                .build("part-width")
+                .unwrap()
+                .with_backend(Box::new(spy.clone()))
+        };
+        let entry = build(3);
+        let chunk = entry.chunk_limit(false);
+        let secret = vec![3; chunk * 11];
+        entry.set_secret(&secret).unwrap();
+        assert!(spy.contains(SERVICE, "part-width.001"));
+        assert!(spy.contains(SERVICE, "part-width.011"));
+        assert!(!spy.contains(SERVICE, "part-width.1"));
+        assert_eq!(entry.get_secret().unwrap(), secret);
+
+        // Ten parts or more cannot be numbered in one digit
+        let narrow = build(1);
+        assert!(matches!(
+            narrow.set_secret(&secret),
+            Err(Error::InvalidArgument(_))
+        ));
+        narrow.set_secret(&secret[..chunk * 9]).unwrap();
+        assert!(spy.contains(SERVICE, "part-width.9"));
+
+        entry.delete_credential().unwrap();
+        assert!(!spy.contains(SERVICE, "part-width.001"));
+        assert!(spy.contains(SERVICE, "part-width.1"));
+    }
+
+    #[test]
+    fn test_label_and_comment_tag_every_part() {
+        let spy = SpyBackend::new();
+        let entry = Entry::builder(SERVICE)
+            .label("My App")
+            .comment("managed by my-app")
+            .build("labelled")
+            .unwrap()
+            .with_backend(Box::new(spy.clone()));
+        entry.set_secret(vec![1; max_chunk_size() + 1]).unwrap();
+
+        let tags: Vec<_> = spy
+            .take_operations()
+            .into_iter()
+            .filter_map(|op| match op {
+                crate::backend::Operation::UpdateAttributes {
+                    user, attributes, ..
+                } => Some((user, attributes)),
+                _ => None,
+            })
+            .collect();
+        let tag = |part, label: &str| {
+            let attributes = [("comment", "managed by my-app"), ("label", label)];
+            let attributes = attributes.map(|(k, v)| (k.to_string(), v.to_string()));
+            (entry.part_user(part), attributes.to_vec())
+        };
+        assert_eq!(
+            tags,
+            [
+                tag(2, "My App (part 2 of 2)"),
+                tag(1, "My App (part 1 of 2)")
+            ]
+        );
+
+        // Untagged entries make no attribute calls
+        let (plain, spy) = spy_entry("unlabelled");
+        plain.set_secret(b"secret").unwrap();
+        assert!(take_ops(&spy).iter().all(|(kind, _)| *kind != "attributes"));
+    }
+
+    #[test]
+    fn test_every_write_stamps_the_next_generation() {
+        let (entry, _spy) = spy_entry("generation");
+        let secret = vec![5; crate::max_chunk_size() + 1];
+        entry.set_secret(&secret).unwrap();
+        entry.set_secret(&secret).unwrap();
+        for part in 1..=2 {
+            let data = entry.read_part(part).unwrap();
+            assert_eq!(decode_part(&data).unwrap().0.generation, Some(2));
+        }
+        assert_eq!(next_generation(None), 1);
+        assert_eq!(next_generation(Some(u32::MAX)), 1);
+    }
+
+    #[test]
+    fn test_write_count_sees_other_writers() {
+        let spy = SpyBackend::new();
+        let build = || {
+            Entry::builder(SERVICE)
+                .cache_total(true)
+                .build("write-count")
+                .unwrap()
+                .with_backend(Box::new(spy.clone()))
+        };
+        let (ours, theirs) = (build(), build());
+        assert!(matches!(
+            ours.write_count(),
+            Err(Error::Keyring(keyring::Error::NoEntry))
+        ));
+        ours.set_password("one").unwrap();
+        ours.set_password("two").unwrap();
+        let seen = ours.write_count().unwrap();
+        assert_eq!(seen, 2);
+
+        theirs.set_password("out of band").unwrap();
+        assert_ne!(ours.write_count().unwrap(), seen);
+
+        write_raw(&ours, 1, b"v1/1/1|unstamped");
+        assert_eq!(ours.write_count().unwrap(), 0);
+        write_raw(&ours, 1, b"v1/1/1/g=4294967295|last");
+        ours.invalidate_cache();
+        ours.set_password("wrapped").unwrap();
+        assert_eq!(ours.write_count().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_read_across_a_write_is_a_concurrent_modification() {
+        let (entry, _spy) = spy_entry("generation-torn");
+        let chunk = crate::max_chunk_size();
+        entry.set_secret(vec![1; chunk + 1]).unwrap();
+
+        // A write lands between a reader's part 1 and part 2
+        let mut parts = entry.parts();
+        parts.next().unwrap().unwrap();
+        entry.set_secret(vec![2; chunk + 1]).unwrap();
+        assert!(matches!(
+            parts.next(),
+            Some(Err(Error::ConcurrentModification { part: 2 }))
+        ));
+
+        // A write that has replaced part 2 but not yet part 1
+        let newer = entry.read_part(2).unwrap();
+        entry.set_secret(vec![3; chunk + 1]).unwrap();
+        entry.set_secret(vec![2; chunk + 1]).unwrap();
+        write_raw(&entry, 2, &newer);
+        let err = entry.get_secret().unwrap_err();
+        assert!(matches!(err, Error::ConcurrentModification { part: 2 }));
+        assert!(err.is_transient());
+    }
+
+    #[test]
+    fn test_stray_part_zero_is_reported() {
+        let (entry, spy) = spy_entry("part-zero");
+        assert!(matches!(
+            entry.get_secret(),
+            Err(Error::Keyring(keyring::Error::NoEntry))
+        ));
+        write_raw(&entry, 0, b"v1/0/1|zero-indexed");
+        assert!(
+            matches!(entry.get_secret(), Err(Error::CorruptedSecret(msg)) if msg.contains("part 0"))
+        );
+
+        write_raw(&entry, 1, b"v1/0/1|zero-indexed");
+        assert!(matches!(entry.get_secret(), Err(Error::CorruptedSecret(_))));
+        entry.delete_force().unwrap();
+        entry.set_secret(b"fine").unwrap();
+        assert_eq!(entry.get_secret().unwrap(), b"fine");
+        assert!(spy.contains(SERVICE, &entry.part_user(0)));
+    }
+
+    /// A backend that has no part 1 but fails every other lookup.
+    #[derive(Debug)]
+    struct LockedAfterPart1;
+
+    impl Backend for LockedAfterPart1 {
+        fn get_secret(&self, _service: &str, user: &str) -> keyring::Result<Vec<u8>> {
+            match user.ends_with(".1") {
+                true => Err(keyring::Error::NoEntry),
+                false => Err(keyring::Error::NoStorageAccess("locked".into())),
+            }
+        }
+
+        fn set_secret(&self, _service: &str, _user: &str, _secret: &[u8]) -> keyring::Result<()> {
+            Err(keyring::Error::NoStorageAccess("locked".into()))
+        }
+
+        fn delete_credential(&self, _service: &str, _user: &str) -> keyring::Result<()> {
+            Err(keyring::Error::NoStorageAccess("locked".into()))
+        }
+    }
+
+    #[test]
+    fn test_failed_part_zero_probe_is_not_a_missing_credential() {
+        let entry = Entry::new(SERVICE, "part-zero-locked")
+            .unwrap()
+            .with_backend(Box::new(LockedAfterPart1));
+        assert!(matches!(
+            entry.get_secret(),
+            Err(Error::Keyring(keyring::Error::NoStorageAccess(_)))
+        ));
+    }
+
+    #[test]
+    fn test_foreign_part_one_is_not_our_format() {
+        let (entry, _spy) = spy_entry("foreign");
+        write_raw(&entry, 1, b"plain keyring password");
+        assert!(matches!(entry.get_secret(), Err(Error::NotOurFormat)));
+
+        write_raw(&entry, 1, b"v1/x|damaged");
+        assert!(matches!(entry.get_secret(), Err(Error::CorruptedSecret(_))));
+    }
+
+    #[test]
+    fn test_cancelled_read_stops_between_parts() {
+        let (entry, spy) = spy_entry("cancel-read");
+        entry.set_secret(vec![1; max_chunk_size() * 3]).unwrap();
+        take_ops(&spy);
+
+        let cancel = AtomicBool::new(true);
+        assert!(matches!(
+            entry.get_secret_cancellable(&cancel),
+            Err(Error::Cancelled)
+        ));
+        assert!(take_ops(&spy).is_empty());
+
+        cancel.store(false, Ordering::Relaxed);
+        assert_eq!(
+            entry.get_secret_cancellable(&cancel).unwrap().len(),
+            max_chunk_size() * 3
+        );
+    }
+
+    #[test]
+    fn test_cancelled_write_leaves_credential_untouched() {
+        let (entry, spy) = spy_entry("cancel-write");
+        entry.set_secret(b"old").unwrap();
+        take_ops(&spy);
+
+        let cancel = AtomicBool::new(true);
+        let err = entry
+            .set_secret_cancellable(vec![2; max_chunk_size() * 3], &cancel)
+            .unwrap_err();
+        assert!(matches!(err, Error::Cancelled));
+        assert!(!take_ops(&spy).iter().any(|(kind, _)| *kind == "set"));
+        assert_eq!(entry.get_secret().unwrap(), b"old");
+
+        cancel.store(false, Ordering::Relaxed);
+        entry.set_secret_cancellable(b"new", &cancel).unwrap();
+        assert_eq!(entry.get_secret().unwrap(), b"new");
+    }
+
+    #[test]
+    fn test_peek_reads_only_the_parts_it_needs() {
+        let (entry, spy) = spy_entry("peek");
+        let chunk = max_chunk_size();
+        let secret: Vec<u8> = (0..chunk * 3).map(|i| i as u8).collect();
+        entry.set_secret(&secret).unwrap();
+        take_ops(&spy);
+
+        assert_eq!(entry.peek(4).unwrap(), secret[..4]);
+        assert_eq!(take_ops(&spy), [("get", entry.part_user(1))]);
+
+        assert_eq!(entry.peek(chunk + 1).unwrap(), secret[..chunk + 1]);
+        assert_eq!(take_ops(&spy).len(), 2);
+        assert_eq!(entry.peek(usize::MAX).unwrap(), secret);
+
+        write_raw(&entry, 1, b"v1/1/2/c=4|data");
+        write_raw(&entry, 2, b"v1/2/3|more");
+        assert!(matches!(
+            entry.peek(10),
+            Err(Error::InconsistentTotal { part: 2, .. })
+        ));
+        write_raw(&entry, 1, b"v1/2/2|data");
+        assert!(matches!(entry.peek(1), Err(Error::CorruptedSecret(_))));
+    }
+
+    #[test]
+    fn test_get_parts_keeps_part_boundaries() {
+        let (entry, _spy) = spy_entry("get-parts");
+        let chunk = max_chunk_size();
+        let secret: Vec<u8> = (0..chunk * 2 + 7).map(|i| i as u8).collect();
+        entry.set_secret(&secret).unwrap();
+        let parts = entry.get_parts().unwrap();
+        let lens: Vec<_> = parts.iter().map(Vec::len).collect();
+        assert_eq!(lens, [chunk, chunk, 7]);
+        assert_eq!(parts.concat(), secret);
+
+        entry.set_secret(b"").unwrap();
+        assert_eq!(entry.get_parts().unwrap(), [b""]);
+
+        write_raw(&entry, 1, b"v1/1/2/c=4|data");
+        write_raw(&entry, 2, b"v1/2/3|more");
+        assert!(matches!(
+            entry.get_parts(),
+            Err(Error::InconsistentTotal { part: 2, .. })
+        ));
+
+        // A corrupted total must not size an allocation or a loop
+        write_raw(&entry, 1, b"v1/1/18446744073709551615|x");
+        assert!(matches!(entry.get_parts(), Err(Error::CorruptedSecret(_))));
+        assert!(matches!(
+            entry.storage_footprint(),
+            Err(Error::CorruptedSecret(_))
+        ));
+        assert_eq!(entry.repair().unwrap().total, Some(3));
+    }
+
+    /// A backend whose keyring cannot be reached.
+    #[derive(Debug)]
+    struct UnreachableBackend;
+
+    impl Backend for UnreachableBackend {
+        fn get_secret(&self, _service: &str, _user: &str) -> keyring::Result<Vec<u8>> {
+            Err(keyring::Error::NoStorageAccess("locked".into()))
+        }
+
+        fn set_secret(&self, _service: &str, _user: &str, _secret: &[u8]) -> keyring::Result<()> {
+            Err(keyring::Error::NoStorageAccess("locked".into()))
+        }
+
+        fn delete_credential(&self, _service: &str, _user: &str) -> keyring::Result<()> {
+            Err(keyring::Error::NoStorageAccess("locked".into()))
+        }
+    }
+
+    #[test]
+    fn test_probe_reads_only_a_reserved_name() {
+        let (entry, spy) = spy_entry("probe");
+        entry.probe().unwrap();
+        let ops = take_ops(&spy);
+        assert_eq!(ops, [("get", "probe#probe".to_string())]);
+
+        let entry = entry.with_backend(Box::new(UnreachableBackend));
+        assert!(matches!(
+            entry.probe(),
+            Err(Error::Keyring(keyring::Error::NoStorageAccess(_)))
+        ));
+    }
+
+    #[test]
+    fn test_builder_clock_is_used() {
+        let clock = crate::clock::ManualClock::new(std::time::UNIX_EPOCH);
+        let entry = Entry::builder(SERVICE)
+            .clock(Box::new(clock.clone()))
+            .build("clock")
+            .unwrap();
+        assert_eq!(entry.now(), std::time::UNIX_EPOCH);
+        clock.advance(std::time::Duration::from_secs(60));
+        assert_eq!(
+            entry.now(),
+            std::time::UNIX_EPOCH + std::time::Duration::from_secs(60)
+        );
+    }
+
+    #[test]
+    fn test_expired_secret_is_purged_on_read() {
+        let clock = crate::clock::ManualClock::new(UNIX_EPOCH + Duration::from_secs(1000));
+        let spy = SpyBackend::new();
+        let entry = Entry::builder(SERVICE)
+            .clock(Box::new(clock.clone()))
+            .build("ttl")
+            .unwrap()
+            .with_backend(Box::new(spy.clone()));
+
+        let secret = vec![5; max_chunk_size() * 2];
+        entry
+            .set_secret_with_ttl(&secret, Duration::from_millis(1500))
+            .unwrap();
+        let (header, _) = decode_part(&entry.read_part(1).unwrap()).unwrap();
+        assert_eq!(header.expires, Some(1002));
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(entry.get_secret().unwrap(), secret);
+
+        clock.advance(Duration::from_secs(1));
+        assert!(matches!(entry.get_secret(), Err(Error::Expired)));
+        for part in 1..=3 {
+            assert!(!part_exists(&spy, &entry, part));
+        }
+
+        // A plain write clears the expiry
+        entry.set_secret_with_ttl(b"short", Duration::ZERO).unwrap();
+        entry.set_secret(b"forever").unwrap();
+        clock.advance(Duration::from_secs(1_000_000));
+        assert_eq!(entry.get_secret().unwrap(), b"forever");
+    }
+
+    #[test]
+    fn test_many_parts_callback_fires_once_per_write() {
+        let calls = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = Arc::clone(&calls);
+        let entry = Entry::builder(SERVICE)
+            .warn_parts_threshold(2)
+            .on_many_parts(move |parts, len| seen.lock().unwrap().push((parts, len)))
+            .build("many-parts")
+            .unwrap()
+            .with_backend(Box::new(SpyBackend::new()));
+
+        entry.set_secret(vec![0; max_chunk_size() * 2]).unwrap();
+        assert!(calls.lock().unwrap().is_empty());
+
+        let len = max_chunk_size() * 2 + 1;
+        entry.set_secret(vec![0; len]).unwrap();
+        assert_eq!(*calls.lock().unwrap(), [(3, len)]);
+    }
+
+    #[test]
+    fn test_replace_secret_alternates_slots() {
+        let (entry, spy) = spy_entry("replace-slots");
+        let old = vec![1; max_chunk_size() * 2 + 1];
+        entry.set_secret(&old).unwrap();
+
+        let new = vec![2; max_chunk_size() * 2];
+        entry.replace_secret(&new).unwrap();
+        assert_eq!(entry.stored_header().unwrap().unwrap().slot, 1);
+        assert!(spy.contains(SERVICE, &entry.slot_user(2, 1)));
+        for part in 2..=3 {
+            assert!(!part_exists(&spy, &entry, part));
+        }
+        assert_eq!(entry.get_secret().unwrap(), new);
+        assert!(entry.storage_footprint().unwrap() > new.len());
+
+        // Replacing again flips back; a plain write also returns to slot 0
+        entry.replace_secret(&old).unwrap();
+        assert_eq!(entry.stored_header().unwrap().unwrap().slot, 0);
+        entry.replace_secret(&new).unwrap();
+        entry.set_secret(&old).unwrap();
+        assert_eq!(entry.stored_header().unwrap().unwrap().slot, 0);
+        assert!(!spy.contains(SERVICE, &entry.slot_user(2, 1)));
+        assert_eq!(entry.get_secret().unwrap(), old);
+
+        entry.replace_secret(&new).unwrap();
+        entry.delete_credential().unwrap();
+        assert!(!spy.contains(SERVICE, &entry.slot_user(2, 1)));
+        assert!(!entry.exists().unwrap());
+    }
+
+    #[test]
+    fn test_interrupted_replace_keeps_old_secret() {
+        let (entry, spy) = spy_entry("replace-interrupted");
+        let old = vec![1; max_chunk_size() * 2];
+        entry.set_secret(&old).unwrap();
+
+        // The new parts are in place but part 1 was never replaced
+        for part in 2..=3 {
+            spy.set_secret(SERVICE, &entry.slot_user(part, 1), b"v1/2/3|new")
+                .unwrap();
+        }
+        assert_eq!(entry.get_secret().unwrap(), old);
+    }
+
+    /// A backend that runs a hook the first time part 2 is read.
+    struct RacingBackend {
+        spy: SpyBackend,
+        part2: String,
+        hook: std::sync::Mutex<Option<Box<dyn FnOnce() + Send>>>,
+    }
+
+    impl std::fmt::Debug for RacingBackend {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("RacingBackend").finish_non_exhaustive()
+        }
+    }
+
+    impl Backend for RacingBackend {
+        fn get_secret(&self, service: &str, user: &str) -> keyring::Result<Vec<u8>> {
+            if user == self.part2 {
+                if let Some(hook) = self.hook.lock().unwrap().take() {
+                    hook();
+                }
+            }
+            self.spy.get_secret(service, user)
+        }
+
+        fn set_secret(&self, service: &str, user: &str, secret: &[u8]) -> keyring::Result<()> {
+            self.spy.set_secret(service, user, secret)
+        }
+
+        fn delete_credential(&self, service: &str, user: &str) -> keyring::Result<()> {
+            self.spy.delete_credential(service, user)
+        }
+    }
+
+    #[test]
+    fn test_read_racing_a_replace_returns_the_new_secret() {
+        let (writer, spy) = spy_entry("replace-race");
+        writer.set_secret(vec![1; max_chunk_size() * 3]).unwrap();
+
+        let new = vec![2; max_chunk_size() * 2 + 1];
+        let replacement = new.clone();
+        let hook_writer = writer.clone();
+        let reader = Entry::new(SERVICE, "replace-race")
+            .unwrap()
+            .with_backend(Box::new(RacingBackend {
+                spy,
+                part2: writer.part_user(2),
+                hook: std::sync::Mutex::new(Some(Box::new(move || {
+                    hook_writer.replace_secret(&replacement).unwrap();
+                }))),
+            }));
+        assert_eq!(reader.get_secret().unwrap(), new);
+    }
+
+    #[test]
+    fn test_format_version_reads_part_one() {
+        let (entry, _spy) = spy_entry("format-version");
+        assert!(matches!(
+            entry.format_version(),
+            Err(Error::Keyring(keyring::Error::NoEntry))
+        ));
+        write_raw(&entry, 1, b"1/1|legacy");
+        assert_eq!(entry.format_version().unwrap(), 0);
+        entry.set_secret(b"current").unwrap();
+        assert_eq!(entry.format_version().unwrap(), CURRENT_VERSION);
+        for foreign in [&b"plain keyring secret"[..], b"v9/1/1|future"] {
+            write_raw(&entry, 1, foreign);
+            assert!(matches!(entry.format_version(), Err(Error::NotOurFormat)));
+        }
+    }
+
+    #[test]
+    fn test_text_aware_chunking_ends_parts_on_lines() {
+        let entry = Entry::builder(SERVICE)
+            .chunking(Chunking::TextAware)
+            .max_entry_size(256)
+            .build("text-aware")
+            .unwrap()
+            .with_backend(Box::new(SpyBackend::new()));
+        let pem: String = (0..40)
+            .map(|i| format!("line {i:02} of the bundle\n"))
+            .collect();
+        entry.set_password(&pem).unwrap();
+        assert_eq!(entry.get_password().unwrap(), pem);
+
+        let parts: Vec<_> = entry.parts().map(|part| part.unwrap().1).collect();
+        assert!(parts.len() > 1);
+        for payload in &parts {
+            assert_eq!(payload.last(), Some(&b'\n'));
+            assert!(payload.len() <= entry.chunk_limit(false));
+        }
+    }
+
+    /// Store and read back one secret of `len` random bytes, then check
+    /// that deleting it leaves no part behind.
+    fn check_round_trip(rng: &mut fastrand::Rng, len: usize, chunking: Chunking) {
+        let spy = SpyBackend::new();
+        let entry = Entry::builder(SERVICE)
+            .chunking(chunking)
+            .max_entry_size(64)
+            .build("round-trip")
+            .unwrap()
+            .with_backend(Box::new(spy.clone()));
+        let secret: Vec<u8> = (0..len).map(|_| rng.u8(..)).collect();
+
+        entry.set_secret(&secret).unwrap();
+        assert_eq!(entry.get_secret().unwrap(), secret, "{len} bytes");
+        let total = entry.part_count().unwrap();
+        if chunking == Chunking::Exact {
+            assert_eq!(
+                total,
+                chunks_needed(len, entry.chunk_limit(false)),
+                "{len} bytes"
+            );
+        }
+
+        entry.delete_credential().unwrap();
+        for part in 1..=total + 1 {
+            assert!(!part_exists(&spy, &entry, part), "{len} bytes, part {part}");
+        }
+    }
+
+    #[test]
+    fn test_round_trip_random_sizes() {
+        // Seeded so a failure reproduces; the message names the size
+        let mut rng = fastrand::Rng::with_seed(0x006b_6579_7269_6e67);
+        let chunk = chunk_limit(64, false, false);
+        for _ in 0..200 {
+            let len = rng.usize(0..=chunk * 5);
+            check_round_trip(&mut rng, len, Chunking::Exact);
+        }
+        for _ in 0..100 {
+            let len = (rng.usize(1..=5) * chunk).saturating_add_signed(rng.isize(-2..=2));
+            check_round_trip(&mut rng, len, Chunking::Exact);
+        }
+        for _ in 0..50 {
+            let len = rng.usize(0..=chunk * 5);
+            check_round_trip(&mut rng, len, Chunking::TextAware);
+        }
+    }
+
+    #[test]
+    fn test_content_type_is_stored_and_cleared() {
+        let (entry, spy) = spy_entry("content-type");
+        assert!(matches!(
+            entry.content_type(),
+            Err(Error::Keyring(keyring::Error::NoEntry))
+        ));
+        entry.set_secret(b"untyped").unwrap();
+        assert_eq!(entry.content_type().unwrap(), None);
+
+        let json = vec![b'1'; max_chunk_size() + 1];
+        entry
+            .set_secret_with_type(&json, "application/json")
+            .unwrap();
+        assert_eq!(
+            entry.content_type().unwrap().as_deref(),
+            Some("application/json")
+        );
+        assert_eq!(entry.get_secret().unwrap(), json);
+
+        entry.set_secret(b"plain").unwrap();
+        assert_eq!(entry.content_type().unwrap(), None);
+        assert!(!spy.contains(SERVICE, "content-type#type"));
+
+        entry.set_secret_with_type(b"x", "text/plain").unwrap();
+        entry.delete_credential().unwrap();
+        assert!(!spy.contains(SERVICE, "content-type#type"));
+
+        for bad in ["", "text/plain\n", "t\u{e9}xt"] {
+            assert!(matches!(
+                entry.set_secret_with_type(b"x", bad),
+                Err(Error::InvalidArgument(_))
+            ));
+        }
+        assert!(!entry.exists().unwrap());
+    }
+
+    #[test]
+    fn test_get_password_trimmed_strips_only_a_leading_bom() {
+        let (entry, _spy) = spy_entry("bom");
+        entry.set_password("\u{feff}token\u{feff}").unwrap();
+        assert_eq!(entry.get_password_trimmed().unwrap(), "token\u{feff}");
+        assert_eq!(entry.get_password().unwrap(), "\u{feff}token\u{feff}");
+        assert_eq!(&entry.get_secret().unwrap()[..3], b"\xef\xbb\xbf");
+
+        entry.set_password("plain").unwrap();
+        assert_eq!(entry.get_password_trimmed().unwrap(), "plain");
+    }
+
+    fn manifest_entry(user: &str, spy: &SpyBackend) -> Entry {
+        Entry::builder(SERVICE)
+            .commit_marker(CommitMarker::Manifest)
+            .build(user)
+            .unwrap()
+            .with_backend(Box::new(spy.clone()))
+    }
+
+    #[test]
+    fn test_manifest_is_written_last_and_checked() {
+        let user = "manifest-order";
+        let spy = SpyBackend::new();
+        let entry = manifest_entry(user, &spy);
+        let secret = vec![7; max_chunk_size() * 2 + 1];
+        entry.set_secret(&secret).unwrap();
+        let ops = take_ops(&spy);
+        assert_eq!(ops[1], ("delete", format!("{user}#manifest")));
+        assert_eq!(*ops.last().unwrap(), ("set", format!("{user}#manifest")));
+        assert_eq!(entry.get_secret().unwrap(), secret);
+
+        // A well-formed part that was not the one written is caught
+        write_raw(&entry, 2, b"v1/2/3|tampered");
+        assert!(matches!(
+            entry.get_secret(),
+            Err(Error::CorruptedSecret(msg)) if msg.contains("manifest")
+        ));
+
+        // So is a part 1 whose total lost track of the last part
+        entry.set_secret(&secret).unwrap();
+        let stale = encode_part(
+            &Header {
+                chunk_size: Some(max_chunk_size()),
+                ..Header::new(1, 2)
+            },
+            &secret[..max_chunk_size()],
+        );
+        write_raw(&entry, 1, &stale);
+        assert!(entry.get_secret().is_err());
+
+        entry.delete_credential().unwrap();
+        assert!(!spy.contains(SERVICE, &format!("{user}#manifest")));
+        assert!(!part_exists(&spy, &entry, 2));
+    }
+
+    #[test]
+    fn test_manifest_entry_reads_credentials_without_one() {
+        let (plain, spy) = spy_entry("manifest-legacy");
+        plain.set_secret(vec![1; max_chunk_size() + 1]).unwrap();
+        let entry = manifest_entry("manifest-legacy", &spy);
+        assert_eq!(entry.get_secret().unwrap(), vec![1; max_chunk_size() + 1]);
     }
 
-    /// Create a keyring entry for the given part number.
-    fn part_entry(&self, part: usize) -> Result<keyring::Entry> {
-        let part_user = format!("{}.{}", self.user, part);
-        keyring::Entry::new(&self.service, &part_user).map_err(Error::from)
+    #[test]
+    fn test_manifest_must_fit_one_entry() {
+        let spy = SpyBackend::new();
+        let entry = Entry::builder(SERVICE)
+            .commit_marker(CommitMarker::Manifest)
+            .max_entry_size(512)
+            .build("manifest-too-big")
+            .unwrap()
+            .with_backend(Box::new(spy.clone()));
+        entry.set_secret(vec![0; 1000]).unwrap();
+        assert!(matches!(
+            entry.set_secret(vec![0; 5000]),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert_eq!(entry.get_secret().unwrap(), vec![0; 1000]);
     }
 
-    /// Read part 1 and extract just the total count.
-    fn read_part_total(&self, part: usize) -> Result<usize> {
-        let entry = self.part_entry(part)?;
-        let data = entry.get_secret().map_err(Error::from)?;
-        let (_, total, _) = decode_part(&data)?;
-        Ok(total)
+    #[test]
+    fn test_replace_secret_rejects_sentinel_marker() {
+        let entry = Entry::builder(SERVICE)
+            .commit_marker(CommitMarker::Sentinel)
+            .build("replace-sentinel")
+            .unwrap()
+            .with_backend(Box::new(SpyBackend::new()));
+        assert!(matches!(
+            entry.replace_secret(b"x"),
+            Err(Error::InvalidArgument(_))
+        ));
     }
 }
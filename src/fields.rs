@@ -0,0 +1,140 @@
+use std::collections::HashSet;
+
+use crate::entry::Entry;
+use crate::{Error, Result};
+
+/// Marks a secret stored by [`Entry::set_fields`].
+const FIELDS_PREFIX: &[u8] = b"f1|";
+
+/// Bytes in each length prefix.
+const LEN_BYTES: usize = 4;
+
+impl Entry {
+    /// Store several named fields, such as a username, password and token,
+    /// as one secret.
+    ///
+    /// The fields are framed into a single secret, stored as by
+    /// [`Entry::set_secret`], so they are chunked and committed together.
+    /// The framing is `f1|`, then for each field in order its name's length
+    /// as a 4-byte big-endian integer, the name in UTF-8, the value's
+    /// length the same way and the value. Values may hold any bytes.
+    ///
+    /// Returns [`Error::InvalidArgument`] without writing anything if a
+    /// name is empty or given twice.
+    pub fn set_fields(&self, fields: &[(&str, &[u8])]) -> Result<()> {
+        self.set_secret(encode_fields(fields)?)
+    }
+
+    /// Retrieve the value of one field stored by [`Entry::set_fields`], or
+    /// `None` if the secret has no field by that name.
+    ///
+    /// The whole secret is read; returns [`Error::CorruptedSecret`] if it
+    /// was not stored by [`Entry::set_fields`].
+    pub fn get_field(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .get_fields()?
+            .into_iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value))
+    }
+
+    /// Retrieve every field stored by [`Entry::set_fields`], in the order
+    /// they were given.
+    pub fn get_fields(&self) -> Result<Vec<(String, Vec<u8>)>> {
+        decode_fields(&self.get_secret()?)
+    }
+}
+
+fn encode_fields(fields: &[(&str, &[u8])]) -> Result<Vec<u8>> {
+    let mut names = HashSet::new();
+    let mut out = FIELDS_PREFIX.to_vec();
+    for (name, value) in fields {
+        if name.is_empty() {
+            return Err(Error::InvalidArgument("field name cannot be empty".into()));
+        }
+        if !names.insert(*name) {
+            return Err(Error::InvalidArgument(format!(
+                "field {:?} is given more than once",
+                name
+            )));
+        }
+        for bytes in [name.as_bytes(), value] {
+            // Secrets are limited to MAX_PARTS entries, far below u32::MAX
+            out.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+            out.extend_from_slice(bytes);
+        }
+    }
+    Ok(out)
+}
+
+fn decode_fields(secret: &[u8]) -> Result<Vec<(String, Vec<u8>)>> {
+    let malformed = || Error::CorruptedSecret("secret was not stored as fields".into());
+    let mut rest = secret.strip_prefix(FIELDS_PREFIX).ok_or_else(malformed)?;
+    let mut fields = Vec::new();
+    while !rest.is_empty() {
+        let name = take_framed(&mut rest).ok_or_else(malformed)?;
+        let name = String::from_utf8(name.to_vec()).map_err(|_| malformed())?;
+        let value = take_framed(&mut rest).ok_or_else(malformed)?;
+        fields.push((name, value.to_vec()));
+    }
+    Ok(fields)
+}
+
+/// Split one length-prefixed frame off the front of `rest`.
+fn take_framed<'a>(rest: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let len = rest.get(..LEN_BYTES)?;
+    let len = u32::from_be_bytes(len.try_into().ok()?) as usize;
+    let bytes = rest.get(LEN_BYTES..LEN_BYTES.checked_add(len)?)?;
+    *rest = &rest[LEN_BYTES + len..];
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{spy_entry, take_ops};
+
+    #[test]
+    fn test_fields_round_trip_across_parts() {
+        let (entry, _spy) = spy_entry("fields");
+        let token = vec![0xff; crate::max_chunk_size()];
+        entry
+            .set_fields(&[("username", b"alice"), ("token", &token), ("empty", b"")])
+            .unwrap();
+
+        assert_eq!(entry.get_field("token").unwrap(), Some(token));
+        assert_eq!(entry.get_field("empty").unwrap(), Some(Vec::new()));
+        assert_eq!(entry.get_field("password").unwrap(), None);
+        let names: Vec<_> = entry
+            .get_fields()
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, ["username", "token", "empty"]);
+        assert_eq!(&entry.get_secret().unwrap()[..15], b"f1|\0\0\0\x08username");
+    }
+
+    #[test]
+    fn test_fields_reject_bad_names_and_foreign_secrets() {
+        let (entry, spy) = spy_entry("fields-invalid");
+        for fields in [&[("", &b"x"[..])][..], &[("a", b"1"), ("a", b"2")]] {
+            assert!(matches!(
+                entry.set_fields(fields),
+                Err(Error::InvalidArgument(_))
+            ));
+        }
+        assert!(take_ops(&spy).is_empty());
+
+        entry.set_secret(b"plain secret").unwrap();
+        assert!(matches!(
+            entry.get_field("a"),
+            Err(Error::CorruptedSecret(_))
+        ));
+        entry.set_secret(b"f1|\0\0\0\x05ab").unwrap();
+        assert!(matches!(
+            entry.get_field("a"),
+            Err(Error::CorruptedSecret(_))
+        ));
+    }
+}
@@ -1,19 +1,109 @@
+use crate::chunk::MAX_PARTS;
+use crate::sha256::DIGEST_LEN;
 use crate::Error;
 
+/// The header version written by [`encode_part`].
+pub const CURRENT_VERSION: u8 = 1;
+
+/// Metadata stored in front of every part's payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    /// Format version; 0 is the legacy unversioned `{part}/{total}|` header.
+    pub version: u8,
+    /// 1-indexed part number.
+    pub part: usize,
+    /// Total number of parts in the secret.
+    pub total: usize,
+    /// Payload size of every part but the last, recorded on part 1.
+    pub chunk_size: Option<usize>,
+    /// Hash chain link: on part `k > 1`, the running hash of the payloads of
+    /// parts `1..k`; on part 1, the running hash of every payload.
+    pub chain: Option<[u8; DIGEST_LEN]>,
+    /// When the secret expires, in seconds since the Unix epoch, recorded
+    /// on part 1.
+    pub expires: Option<u64>,
+    /// Which set of names parts `2..=total` are stored under, recorded on
+    /// part 1: 0 for `{user}.{part}`, 1 for the alternate `{user}.{part}b`.
+    pub slot: u8,
+    /// Whether a content type is stored alongside, recorded on part 1.
+    pub typed: bool,
+    /// The write that stored this part, the same on every part of one
+    /// write. Counts up from 1 and wraps back to 1 after `u32::MAX`.
+    pub generation: Option<u32>,
+}
+
+impl Header {
+    /// A header for the given part written with the current version.
+    pub fn new(part: usize, total: usize) -> Self {
+        Self {
+            version: CURRENT_VERSION,
+            part,
+            total,
+            chunk_size: None,
+            chain: None,
+            expires: None,
+            slot: 0,
+            typed: false,
+            generation: None,
+        }
+    }
+}
+
 /// Encode a chunk with its part metadata.
-/// Format: "{part}/{total}|{payload}"
-pub fn encode_part(part: usize, total: usize, data: &[u8]) -> Vec<u8> {
-    let header = format!("{}/{}", part, total);
-    let mut result = Vec::with_capacity(header.len() + 1 + data.len());
-    result.extend_from_slice(header.as_bytes());
-    result.push(b'|');
-    result.extend_from_slice(data);
+/// Format: "v1/{part}/{total}[/c={chunk_size}][/x={expiry}][/s={slot}][/t=1][/g={generation}][/h={hex chain link}]|{payload}"
+///
+/// The header is always written with [`CURRENT_VERSION`].
+pub fn encode_part(header: &Header, data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::new();
+    encode_part_into(header, data, &mut result);
     result
 }
 
-/// Decode a chunk, extracting part number, total parts, and payload.
-/// Returns (part, total, payload).
-pub fn decode_part(data: &[u8]) -> Result<(usize, usize, Vec<u8>), Error> {
+/// Encode a chunk like [`encode_part`], replacing the contents of `out`.
+///
+/// Reusing `out` across parts avoids allocating a buffer per part. The
+/// total must not exceed [`MAX_PARTS`], which callers check first.
+pub fn encode_part_into(header: &Header, data: &[u8], out: &mut Vec<u8>) {
+    use std::io::Write;
+
+    debug_assert!(header.total <= MAX_PARTS, "total exceeds MAX_PARTS");
+    out.clear();
+    // Writing into a Vec cannot fail
+    let _ = write!(out, "v{}/{}/{}", CURRENT_VERSION, header.part, header.total);
+    if let Some(chunk_size) = header.chunk_size {
+        let _ = write!(out, "/c={}", chunk_size);
+    }
+    if let Some(expires) = header.expires {
+        let _ = write!(out, "/x={}", expires);
+    }
+    if header.slot != 0 {
+        let _ = write!(out, "/s={}", header.slot);
+    }
+    if header.typed {
+        out.extend_from_slice(b"/t=1");
+    }
+    if let Some(generation) = header.generation {
+        let _ = write!(out, "/g={}", generation);
+    }
+    if let Some(chain) = header.chain {
+        out.extend_from_slice(b"/h=");
+        for byte in chain {
+            let _ = write!(out, "{:02x}", byte);
+        }
+    }
+    out.push(b'|');
+    out.extend_from_slice(data);
+}
+
+/// Decode a chunk, extracting its header and a view of its payload.
+///
+/// Both the current versioned header and the legacy `{part}/{total}|`
+/// header are accepted, so credentials written before and after an upgrade
+/// read through the same call. The format is sniffed from the first byte: a
+/// leading `v` marks a versioned header, anything else is parsed as legacy.
+/// The payload borrows from `data`, so callers copy it only if they need to
+/// own it.
+pub fn decode_part(data: &[u8]) -> Result<(Header, &[u8]), Error> {
     // Find the '|' separator
     let separator_pos = data
         .iter()
@@ -23,28 +113,231 @@ pub fn decode_part(data: &[u8]) -> Result<(usize, usize, Vec<u8>), Error> {
     let header = std::str::from_utf8(&data[..separator_pos])
         .map_err(|_| Error::CorruptedSecret("invalid header encoding".into()))?;
 
-    // Parse "part/total"
-    let slash_pos = header
-        .find('/')
-        .ok_or(Error::CorruptedSecret("missing slash in header".into()))?;
+    // Every header is a '/'-separated field list whose meaning depends on
+    // the version, so new fields never need a new delimiter search
+    let fields: Vec<&str> = header.split('/').collect();
+    let header = match fields[0].strip_prefix('v') {
+        Some(version) => {
+            let version: u8 = version
+                .parse()
+                .map_err(|_| Error::CorruptedSecret("invalid format version".into()))?;
+            match version {
+                CURRENT_VERSION => parse_v1(&fields[1..])?,
+                version => {
+                    return Err(Error::CorruptedSecret(format!(
+                        "unsupported format version {}",
+                        version
+                    )))
+                }
+            }
+        }
+        None => parse_legacy(&fields)?,
+    };
 
-    let part: usize = header[..slash_pos]
+    if header.part == 0 {
+        return Err(Error::CorruptedSecret(
+            "invalid part 0: parts are numbered from 1".into(),
+        ));
+    }
+    if header.part > header.total {
+        return Err(Error::CorruptedSecret(format!(
+            "invalid part {}/{}",
+            header.part, header.total
+        )));
+    }
+
+    Ok((header, &data[separator_pos + 1..]))
+}
+
+/// Encode the commit marker recording a completed write of `total` parts.
+/// Format: "v1/{total}"
+pub fn encode_marker(total: usize) -> Vec<u8> {
+    format!("v{}/{}", CURRENT_VERSION, total).into_bytes()
+}
+
+/// Decode a commit marker, returning the total it records.
+pub fn decode_marker(data: &[u8]) -> Result<usize, Error> {
+    let marker = std::str::from_utf8(data)
+        .map_err(|_| Error::CorruptedSecret("invalid commit marker encoding".into()))?;
+    let total = marker
+        .strip_prefix(&format!("v{}/", CURRENT_VERSION))
+        .ok_or(Error::CorruptedSecret("invalid commit marker".into()))?;
+    match parse_total(total)? {
+        0 => Err(Error::CorruptedSecret("invalid commit marker".into())),
+        total => Ok(total),
+    }
+}
+
+/// Decode a chunk that must be part `part` of `total`.
+///
+/// For reads after part 1, where the caller already knows which part it
+/// asked for and how many there should be. The header is still returned for
+/// the metadata beyond part and total, such as the hash chain link.
+pub fn decode_part_expecting(
+    data: &[u8],
+    part: usize,
+    total: usize,
+) -> Result<(Header, &[u8]), Error> {
+    let (header, payload) = decode_part(data)?;
+    check_header(&header, part, total)?;
+    Ok((header, payload))
+}
+
+/// Check that a decoded header is part `part` of `total`.
+pub fn check_header(header: &Header, part: usize, total: usize) -> Result<(), Error> {
+    if header.part != part {
+        return Err(Error::CorruptedSecret(format!(
+            "expected part {}, got {}",
+            part, header.part
+        )));
+    }
+    if header.total != total {
+        return Err(Error::InconsistentTotal {
+            part,
+            expected: total,
+            found: header.total,
+        });
+    }
+    Ok(())
+}
+
+/// Check that a part after part 1 was stamped with part 1's `generation`.
+///
+/// A mismatch means the read straddled a write that replaced some parts
+/// but had not yet committed part 1.
+pub fn check_generation(header: &Header, generation: Option<u32>) -> Result<(), Error> {
+    if header.generation != generation {
+        return Err(Error::ConcurrentModification { part: header.part });
+    }
+    Ok(())
+}
+
+/// Parse the fields of a legacy "part/total" header.
+fn parse_legacy(fields: &[&str]) -> Result<Header, Error> {
+    let [part, total] = fields else {
+        return Err(Error::CorruptedSecret(if fields.len() < 2 {
+            "missing slash in header".into()
+        } else {
+            "too many fields in legacy header".into()
+        }));
+    };
+
+    Ok(Header {
+        version: 0,
+        part: parse_part(part)?,
+        total: parse_total(total)?,
+        chunk_size: None,
+        chain: None,
+        expires: None,
+        slot: 0,
+        typed: false,
+        generation: None,
+    })
+}
+
+/// Parse the fields of a version 1 header after the version itself:
+/// "{part}/{total}" followed by optional "{key}={value}" fields.
+fn parse_v1(fields: &[&str]) -> Result<Header, Error> {
+    let (part, total, extra) = match fields {
+        [] => return Err(Error::CorruptedSecret("missing part number".into())),
+        [_] => return Err(Error::CorruptedSecret("missing slash in header".into())),
+        [part, total, extra @ ..] => (part, total, extra),
+    };
+
+    let mut header = Header {
+        version: 1,
+        part: parse_part(part)?,
+        total: parse_total(total)?,
+        chunk_size: None,
+        chain: None,
+        expires: None,
+        slot: 0,
+        typed: false,
+        generation: None,
+    };
+
+    for field in extra {
+        let (key, value) = field.split_once('=').ok_or(Error::CorruptedSecret(format!(
+            "invalid header field {:?}",
+            field
+        )))?;
+        match key {
+            "c" => {
+                let chunk_size = value
+                    .parse()
+                    .map_err(|_| Error::CorruptedSecret("invalid chunk size".into()))?;
+                header.chunk_size = Some(chunk_size);
+            }
+            "x" => {
+                let expires = value
+                    .parse()
+                    .map_err(|_| Error::CorruptedSecret("invalid expiry".into()))?;
+                header.expires = Some(expires);
+            }
+            "s" => {
+                header.slot = match value {
+                    "0" => 0,
+                    "1" => 1,
+                    _ => return Err(Error::CorruptedSecret("invalid slot".into())),
+                };
+            }
+            "t" => {
+                header.typed = match value {
+                    "1" => true,
+                    _ => return Err(Error::CorruptedSecret("invalid content type flag".into())),
+                };
+            }
+            "g" => {
+                let generation = value
+                    .parse()
+                    .map_err(|_| Error::CorruptedSecret("invalid generation".into()))?;
+                header.generation = Some(generation);
+            }
+            "h" => header.chain = Some(parse_digest(value)?),
+            // Fields added by newer writers are optional metadata
+            _ => continue,
+        }
+    }
+
+    Ok(header)
+}
+
+fn parse_digest(field: &str) -> Result<[u8; DIGEST_LEN], Error> {
+    parse_hex_digest(field).ok_or(Error::CorruptedSecret("invalid hash chain link".into()))
+}
+
+/// Parse a digest written as 64 lowercase or uppercase hex digits.
+pub(crate) fn parse_hex_digest(field: &str) -> Option<[u8; DIGEST_LEN]> {
+    if field.len() != DIGEST_LEN * 2 {
+        return None;
+    }
+    let mut digest = [0u8; DIGEST_LEN];
+    for (byte, pair) in digest.iter_mut().zip(field.as_bytes().chunks_exact(2)) {
+        let pair = std::str::from_utf8(pair).ok()?;
+        *byte = u8::from_str_radix(pair, 16).ok()?;
+    }
+    Some(digest)
+}
+
+fn parse_part(field: &str) -> Result<usize, Error> {
+    field
         .parse()
-        .map_err(|_| Error::CorruptedSecret("invalid part number".into()))?;
+        .map_err(|_| Error::CorruptedSecret("invalid part number".into()))
+}
 
-    let total: usize = header[slash_pos + 1..]
+/// Parse a total, which no writer makes larger than [`MAX_PARTS`]; a larger
+/// one is corrupted, and must not size allocations or loops.
+fn parse_total(field: &str) -> Result<usize, Error> {
+    let total = field
         .parse()
         .map_err(|_| Error::CorruptedSecret("invalid total number".into()))?;
-
-    if part == 0 || part > total {
+    if total > MAX_PARTS {
         return Err(Error::CorruptedSecret(format!(
-            "invalid part {}/{}",
-            part, total
+            "total {} exceeds maximum parts ({})",
+            total, MAX_PARTS
         )));
     }
-
-    let payload = data[separator_pos + 1..].to_vec();
-    Ok((part, total, payload))
+    Ok(total)
 }
 
 #[cfg(test)]
@@ -54,35 +347,220 @@ mod tests {
     #[test]
     fn test_encode_decode_roundtrip() {
         let payload = b"hello world";
-        let encoded = encode_part(2, 5, payload);
-        let (part, total, decoded) = decode_part(&encoded).unwrap();
+        let encoded = encode_part(&Header::new(2, 5), payload);
+        let (header, decoded) = decode_part(&encoded).unwrap();
 
-        assert_eq!(part, 2);
-        assert_eq!(total, 5);
+        assert_eq!(header.version, CURRENT_VERSION);
+        assert_eq!(header.part, 2);
+        assert_eq!(header.total, 5);
+        assert_eq!(header.chunk_size, None);
         assert_eq!(decoded, payload);
     }
 
+    #[test]
+    fn test_decode_rejects_total_above_max_parts() {
+        let huge = format!("v1/1/{}|x", usize::MAX);
+        let over = format!("1/{}|x", MAX_PARTS + 1);
+        for data in [huge.as_bytes(), over.as_bytes(), b"v1/18446744073709551615"] {
+            let err = match data.contains(&b'|') {
+                true => decode_part(data).unwrap_err(),
+                false => decode_marker(data).unwrap_err(),
+            };
+            assert!(
+                matches!(err, Error::CorruptedSecret(ref msg) if msg.contains("maximum parts"))
+            );
+        }
+        assert!(decode_part(format!("v1/1/{}|x", MAX_PARTS).as_bytes()).is_ok());
+    }
+
     #[test]
     fn test_encode_format() {
-        let encoded = encode_part(1, 3, b"data");
-        assert_eq!(&encoded, b"1/3|data");
+        let encoded = encode_part(&Header::new(1, 3), b"data");
+        assert_eq!(&encoded, b"v1/1/3|data");
+    }
+
+    #[test]
+    fn test_encode_into_replaces_buffer() {
+        let mut buf = b"stale contents".to_vec();
+        encode_part_into(&Header::new(2, 3), b"data", &mut buf);
+        assert_eq!(buf, encode_part(&Header::new(2, 3), b"data"));
+    }
+
+    #[test]
+    fn test_encode_chunk_size() {
+        let header = Header {
+            chunk_size: Some(4096),
+            ..Header::new(1, 3)
+        };
+        let encoded = encode_part(&header, b"data");
+        assert_eq!(&encoded, b"v1/1/3/c=4096|data");
+
+        let (decoded, payload) = decode_part(&encoded).unwrap();
+        assert_eq!(decoded, header);
+        assert_eq!(payload, b"data");
+    }
+
+    #[test]
+    fn test_encode_expiry() {
+        let header = Header {
+            chunk_size: Some(10),
+            expires: Some(1_700_000_000),
+            ..Header::new(1, 1)
+        };
+        let encoded = encode_part(&header, b"data");
+        assert_eq!(&encoded, b"v1/1/1/c=10/x=1700000000|data");
+        assert_eq!(decode_part(&encoded).unwrap().0, header);
+        assert!(decode_part(b"v1/1/1/x=soon|data").is_err());
+    }
+
+    #[test]
+    fn test_encode_slot() {
+        let header = Header {
+            slot: 1,
+            ..Header::new(1, 2)
+        };
+        let encoded = encode_part(&header, b"data");
+        assert_eq!(&encoded, b"v1/1/2/s=1|data");
+        assert_eq!(decode_part(&encoded).unwrap().0, header);
+        assert!(decode_part(b"v1/1/2/s=2|data").is_err());
+    }
+
+    #[test]
+    fn test_encode_content_type_flag() {
+        let header = Header {
+            slot: 1,
+            typed: true,
+            ..Header::new(1, 2)
+        };
+        let encoded = encode_part(&header, b"data");
+        assert_eq!(&encoded, b"v1/1/2/s=1/t=1|data");
+        assert_eq!(decode_part(&encoded).unwrap().0, header);
+        assert!(decode_part(b"v1/1/2/t=yes|data").is_err());
+    }
+
+    #[test]
+    fn test_encode_generation() {
+        let header = Header {
+            generation: Some(u32::MAX),
+            ..Header::new(2, 3)
+        };
+        let encoded = encode_part(&header, b"data");
+        assert_eq!(&encoded, b"v1/2/3/g=4294967295|data");
+        assert_eq!(decode_part(&encoded).unwrap().0, header);
+        assert!(decode_part(b"v1/2/3/g=4294967296|data").is_err());
+    }
+
+    #[test]
+    fn test_encode_chain_link() {
+        let header = Header {
+            chain: Some([0xab; DIGEST_LEN]),
+            ..Header::new(2, 3)
+        };
+        let encoded = encode_part(&header, b"data");
+        let expected = format!("v1/2/3/h={}|data", "ab".repeat(DIGEST_LEN));
+        assert_eq!(encoded, expected.as_bytes());
+
+        let (decoded, payload) = decode_part(&encoded).unwrap();
+        assert_eq!(decoded, header);
+        assert_eq!(payload, b"data");
+        assert!(decode_part(b"v1/2/3/h=abc|data").is_err());
+    }
+
+    #[test]
+    fn test_decode_borrows_payload() {
+        let encoded = encode_part(&Header::new(1, 1), b"data");
+        let (_, payload) = decode_part(&encoded).unwrap();
+        assert_eq!(payload.as_ptr_range().end, encoded.as_ptr_range().end);
+    }
+
+    #[test]
+    fn test_decode_part_expecting() {
+        let encoded = encode_part(&Header::new(2, 3), b"data");
+        let (_, payload) = decode_part_expecting(&encoded, 2, 3).unwrap();
+        assert_eq!(payload, b"data");
+        assert!(matches!(
+            decode_part_expecting(&encoded, 3, 3),
+            Err(Error::CorruptedSecret(_))
+        ));
+        assert!(matches!(
+            decode_part_expecting(&encoded, 2, 4),
+            Err(Error::InconsistentTotal {
+                part: 2,
+                expected: 4,
+                found: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn test_decode_legacy_header() {
+        let (header, payload) = decode_part(b"2/3|data").unwrap();
+
+        assert_eq!(header.version, 0);
+        assert_eq!(header.part, 2);
+        assert_eq!(header.total, 3);
+        assert_eq!(header.chunk_size, None);
+        assert_eq!(payload, b"data");
+    }
+
+    #[test]
+    fn test_decode_field_count_is_checked_per_version() {
+        assert!(decode_part(b"1/2/3|data").is_err());
+        assert!(decode_part(b"1|data").is_err());
+        assert!(decode_part(b"v1/1|data").is_err());
+        assert!(decode_part(b"v0/1/1|data").is_err());
+        let (header, payload) = decode_part(b"v1/1/1/c=4/a=1/b=2/x=5|da/ta").unwrap();
+        assert_eq!(header.chunk_size, Some(4));
+        assert_eq!(header.expires, Some(5));
+        assert_eq!(payload, b"da/ta");
+    }
+
+    #[test]
+    fn test_decode_sniffs_both_formats() {
+        let legacy = b"2/3|same payload".to_vec();
+        let current = encode_part(&Header::new(2, 3), b"same payload");
+        for data in [&legacy, &current] {
+            let (header, payload) = decode_part(data).unwrap();
+            assert_eq!((header.part, header.total), (2, 3));
+            assert_eq!(payload, b"same payload");
+        }
+        assert_eq!(decode_part(&legacy).unwrap().0.version, 0);
+        assert_eq!(decode_part(&current).unwrap().0.version, CURRENT_VERSION);
+
+        // A legacy payload starting with 'v' is still told apart by its header
+        let (header, payload) = decode_part(b"1/1|v1/1/1|data").unwrap();
+        assert_eq!(header.version, 0);
+        assert_eq!(payload, b"v1/1/1|data");
+    }
+
+    #[test]
+    fn test_decode_ignores_unknown_fields() {
+        let (header, payload) = decode_part(b"v1/1/2/c=10/z=future|data").unwrap();
+        assert_eq!(header.chunk_size, Some(10));
+        assert_eq!(payload, b"data");
+    }
+
+    #[test]
+    fn test_decode_unsupported_version() {
+        let result = decode_part(b"v9/1/1|data");
+        assert!(result.is_err());
     }
 
     #[test]
     fn test_decode_empty_payload() {
-        let encoded = encode_part(1, 1, b"");
-        let (part, total, payload) = decode_part(&encoded).unwrap();
+        let encoded = encode_part(&Header::new(1, 1), b"");
+        let (header, payload) = decode_part(&encoded).unwrap();
 
-        assert_eq!(part, 1);
-        assert_eq!(total, 1);
+        assert_eq!(header.part, 1);
+        assert_eq!(header.total, 1);
         assert!(payload.is_empty());
     }
 
     #[test]
     fn test_decode_binary_payload() {
         let binary_data: Vec<u8> = (0..=255).collect();
-        let encoded = encode_part(1, 1, &binary_data);
-        let (_, _, decoded) = decode_part(&encoded).unwrap();
+        let encoded = encode_part(&Header::new(1, 1), &binary_data);
+        let (_, decoded) = decode_part(&encoded).unwrap();
 
         assert_eq!(decoded, binary_data);
     }
@@ -97,11 +575,24 @@ mod tests {
     fn test_decode_invalid_part_zero() {
         let result = decode_part(b"0/3|data");
         assert!(result.is_err());
+        let result = decode_part(b"v1/0/3|data");
+        assert!(result.is_err());
     }
 
     #[test]
     fn test_decode_invalid_part_exceeds_total() {
         let result = decode_part(b"5/3|data");
         assert!(result.is_err());
+        let result = decode_part(b"v1/5/3|data");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_marker_roundtrip() {
+        assert_eq!(&encode_marker(3), b"v1/3");
+        assert_eq!(decode_marker(&encode_marker(3)).unwrap(), 3);
+        assert!(decode_marker(b"v1/0").is_err());
+        assert!(decode_marker(b"v9/3").is_err());
+        assert!(decode_marker(b"3").is_err());
     }
 }
@@ -0,0 +1,88 @@
+use crate::entry::Entry;
+use crate::{Error, Result};
+
+impl Entry {
+    /// Store a secret given as a hex string.
+    ///
+    /// Digits may be upper or lower case, and a leading `0x` or `0X` is
+    /// skipped. The decoded bytes are stored as by [`Entry::set_secret`].
+    ///
+    /// Returns [`Error::InvalidArgument`] without writing anything if the
+    /// string has an odd number of digits or anything but hex digits. The
+    /// message gives the position of the first bad character, never the
+    /// input itself.
+    pub fn set_hex(&self, hex: &str) -> Result<()> {
+        self.set_secret(decode_hex(hex)?)
+    }
+
+    /// Retrieve the secret as a lowercase hex string, without a prefix.
+    pub fn get_hex(&self) -> Result<String> {
+        Ok(encode_hex(&self.get_secret()?))
+    }
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for &byte in bytes {
+        hex.push(char::from(DIGITS[usize::from(byte >> 4)]));
+        hex.push(char::from(DIGITS[usize::from(byte & 0xf)]));
+    }
+    hex
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>> {
+    let digits = hex
+        .strip_prefix("0x")
+        .or_else(|| hex.strip_prefix("0X"))
+        .unwrap_or(hex)
+        .as_bytes();
+    let pairs = digits.chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return Err(Error::InvalidArgument(
+            "hex secret has an odd number of digits".into(),
+        ));
+    }
+    let offset = hex.len() - digits.len();
+    let digit = |i: usize| {
+        char::from(digits[i]).to_digit(16).ok_or_else(|| {
+            Error::InvalidArgument(format!(
+                "hex secret has a non-hex character at byte {}",
+                offset + i
+            ))
+        })
+    };
+    (0..pairs.len())
+        .map(|i| Ok((digit(2 * i)? << 4 | digit(2 * i + 1)?) as u8))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{spy_entry, take_ops};
+
+    #[test]
+    fn test_hex_round_trip_accepts_prefix_and_case() {
+        let (entry, _spy) = spy_entry("hex");
+        entry.set_hex("0xDEADbeef00").unwrap();
+        assert_eq!(entry.get_secret().unwrap(), [0xde, 0xad, 0xbe, 0xef, 0]);
+        assert_eq!(entry.get_hex().unwrap(), "deadbeef00");
+
+        entry.set_hex("").unwrap();
+        assert_eq!(entry.get_hex().unwrap(), "");
+    }
+
+    #[test]
+    fn test_invalid_hex_is_rejected_without_writing() {
+        let (entry, spy) = spy_entry("hex-invalid");
+        for bad in ["abc", "0xzz", "12 4", "é1"] {
+            assert!(matches!(entry.set_hex(bad), Err(Error::InvalidArgument(_))));
+        }
+        assert!(take_ops(&spy).is_empty());
+        assert_eq!(
+            decode_hex("0x12zz").unwrap_err().to_string(),
+            "invalid argument: hex secret has a non-hex character at byte 4"
+        );
+    }
+}
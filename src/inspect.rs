@@ -0,0 +1,120 @@
+use crate::chunk::MAX_PARTS;
+use crate::entry::Entry;
+use crate::format::decode_part;
+use crate::{Error, Result};
+
+/// What [`Entry::inspect`] found at one part index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartInfo {
+    /// The part index, from 1.
+    pub part: usize,
+    /// Whether anything is stored at this index. A part that is not stored
+    /// but falls within a total some part claims is a gap.
+    pub present: bool,
+    /// The stored size in bytes, header included, or 0 if not stored.
+    pub size: usize,
+    /// The payload size, if the header decodes.
+    pub payload_len: Option<usize>,
+    /// The total the header claims, if it decodes.
+    pub total: Option<usize>,
+}
+
+impl Entry {
+    /// List the stored parts and their sizes, including gaps.
+    ///
+    /// Parts are probed from 1 up to the highest total any decodable part
+    /// claims, and past it until one is missing, so parts left beyond the
+    /// total show up too. Part 2 is always probed, so a lost part 1 is
+    /// reported as a gap rather than as an empty credential. Nothing is
+    /// validated or changed: this is for diagnostics, and works on
+    /// credentials [`Entry::get_secret`] rejects. Later parts are read from
+    /// the slot a decodable part 1 names. Returns an empty list if nothing
+    /// is stored.
+    pub fn inspect(&self) -> Result<Vec<PartInfo>> {
+        let mut found = Vec::new();
+        let mut highest_claimed = 1;
+        let mut slot = 0;
+        for part in 1..=MAX_PARTS {
+            let data = match self.read_part_in(part, slot) {
+                Ok(data) => data,
+                Err(Error::Keyring(keyring::Error::NoEntry)) if part > highest_claimed => break,
+                Err(Error::Keyring(keyring::Error::NoEntry)) => {
+                    found.push(PartInfo {
+                        part,
+                        present: false,
+                        size: 0,
+                        payload_len: None,
+                        total: None,
+                    });
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            let decoded = decode_part(&data).ok();
+            if let Some((header, _)) = &decoded {
+                highest_claimed = highest_claimed.max(header.total);
+                if part == 1 {
+                    slot = header.slot;
+                }
+            }
+            found.push(PartInfo {
+                part,
+                present: true,
+                size: data.len(),
+                payload_len: decoded.as_ref().map(|(_, payload)| payload.len()),
+                total: decoded.map(|(header, _)| header.total),
+            });
+        }
+        // A missing part 1 with nothing after it is no credential at all
+        if found.iter().all(|info| !info.present) {
+            found.clear();
+        }
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{spy_entry, write_raw};
+
+    #[test]
+    fn test_inspect_reports_sizes_and_gaps() {
+        let (entry, _spy) = spy_entry("inspect");
+        assert!(entry.inspect().unwrap().is_empty());
+
+        let chunk = crate::max_chunk_size();
+        entry.set_secret(vec![1; chunk * 2 + 3]).unwrap();
+        entry.backend_delete(&entry.part_user(2)).unwrap();
+        write_raw(&entry, 4, b"orphan");
+
+        let found = entry.inspect().unwrap();
+        let summary: Vec<_> = found
+            .iter()
+            .map(|info| (info.part, info.present, info.payload_len, info.total))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (1, true, Some(chunk), Some(3)),
+                (2, false, None, None),
+                (3, true, Some(3), Some(3)),
+                (4, true, None, None),
+            ]
+        );
+        assert_eq!(found[3].size, 6);
+        assert_eq!(found[0].size, entry.read_part(1).unwrap().len());
+    }
+
+    #[test]
+    fn test_inspect_sees_past_a_lost_part_one() {
+        let (entry, _spy) = spy_entry("inspect-lost");
+        entry
+            .set_secret(vec![1; crate::max_chunk_size() + 1])
+            .unwrap();
+        entry.backend_delete(&entry.part_user(1)).unwrap();
+        let found = entry.inspect().unwrap();
+        assert_eq!(found.len(), 2);
+        assert!(!found[0].present);
+        assert_eq!(found[1].total, Some(2));
+    }
+}
@@ -24,21 +24,98 @@
 //! }
 //! ```
 //!
+//! ## Configuration
+//!
+//! [`Entry::builder`] configures entries that share a service, such as the
+//! keyring target every part is created with. With the `parity`
+//! feature, `EntryBuilder::parity` stores an XOR parity part alongside each
+//! secret, from which [`Entry::get_secret`] rebuilds a single lost part.
+//!
+//! ## Testing
+//!
+//! The `testing` feature exposes `Entry::with_backend` and an in-memory
+//! `SpyBackend` that records every backend call, for asserting how many
+//! keyring operations an application makes. It also exposes
+//! `EntryBuilder::clock` with a `ManualClock`, for controlling the time seen
+//! by anything that stores timestamps. `testing::TestEntry` creates an
+//! entry under random names that is deleted on drop, for integration tests
+//! against the real keyring.
+//!
 //! ## Storage Format
 //!
 //! Secrets are stored with a naming convention of `{user}.{part}` where part is
-//! 1-indexed. Each part contains a header `{part}/{total}|` followed by the payload.
+//! 1-indexed. Any `.` or `%` in the user is percent-encoded (`%2E`, `%25`) so
+//! the part suffix is unambiguous. Each part contains a versioned header
+//! `v1/{part}/{total}|` followed by the payload. Part 1 also records the chunk
+//! size used to split the secret.
+//!
+//! For example, a secret split into 3 parts of 8182 bytes for user "alice":
+//! - `alice.1` → `v1/1/3/c=8182|<chunk1>`
+//! - `alice.2` → `v1/2/3|<chunk2>`
+//! - `alice.3` → `v1/3/3|<chunk3>`
 //!
-//! For example, a secret split into 3 parts for user "alice":
-//! - `alice.1` → `1/3|<chunk1>`
-//! - `alice.2` → `2/3|<chunk2>`
-//! - `alice.3` → `3/3|<chunk3>`
+//! Entries built with [`EntryBuilder::hash_chain`] add a `/h=` field holding
+//! a running SHA-256 over the payloads, so reordered or substituted parts
+//! are detected on read.
+//!
+//! A secret stored with [`Entry::replace_secret`] keeps parts 2 onwards
+//! under the alternate names `{user}.{part}b`, and part 1 records this as
+//! `/s=1`; the next replace switches back to the plain names.
+//!
+//! A content type given to [`Entry::set_secret_with_type`] is kept in a
+//! `{user}#type` entry, and part 1 is flagged with `/t=1`.
+//!
+//! Credentials written with the older unversioned `{part}/{total}|` header
+//! are still read, and are rewritten in the current format on update.
 
+mod backend;
+mod builder;
+mod caching;
+mod chain;
 mod chunk;
+mod clock;
+mod compat;
+mod diff;
+mod encoding;
 mod entry;
+mod fields;
 mod format;
+mod hex;
+mod inspect;
+mod manifest;
+mod mirror;
+mod parity;
+mod parts;
+mod prepared;
+mod raw;
+mod read_only;
+mod repair;
+mod resume;
+mod self_test;
+mod sha256;
+#[cfg(test)]
+mod test_util;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
+pub use backend::{Backend, BackendInfo, KeyringBackend};
+#[cfg(feature = "testing")]
+pub use backend::{Operation, SpyBackend};
+pub use builder::{Chunking, CommitMarker, EntryBuilder, WriteOrder};
+pub use caching::CachingEntry;
+pub use chunk::{Platform, MAX_PARTS};
+#[cfg(feature = "testing")]
+pub use clock::{Clock, ManualClock};
+pub use diff::LayoutDiff;
+pub use encoding::Encoding;
 pub use entry::Entry;
+pub use inspect::PartInfo;
+pub use mirror::{MirrorEntry, MirrorReport};
+pub use parts::PartIter;
+pub use prepared::PreparedWrite;
+pub use read_only::ReadOnlyEntry;
+pub use repair::{CompactReport, RepairAction, RepairProblem, RepairReport};
+pub use self_test::SelfTestReport;
 
 use thiserror::Error;
 
@@ -53,6 +130,36 @@ pub enum Error {
     #[error("corrupted secret: {0}")]
     CorruptedSecret(String),
 
+    /// A later part disagrees with part 1 about how many parts there are.
+    ///
+    /// `expected` is the total recorded on part 1 and `found` is the total
+    /// recorded on `part`.
+    #[error("inconsistent total: part 1 says {expected}, part {part} says {found}")]
+    InconsistentTotal {
+        part: usize,
+        expected: usize,
+        found: usize,
+    },
+
+    /// Part 1 holds data with no header at all, such as a credential
+    /// written directly with `keyring`, rather than a damaged one of ours.
+    #[error("credential was not written by keyring-cursed")]
+    NotOurFormat,
+
+    /// A later part was written by a different write than part 1, so the
+    /// read overlapped a write in progress. Retrying once the write
+    /// finishes reads a consistent secret.
+    #[error("part {part} was written by a different write than part 1")]
+    ConcurrentModification { part: usize },
+
+    /// The operation was stopped by its cancellation flag.
+    #[error("operation cancelled")]
+    Cancelled,
+
+    /// The credential's expiry has passed, so it was deleted.
+    #[error("credential has expired")]
+    Expired,
+
     /// The retrieved data is not valid UTF-8 (when using get_password).
     #[error("secret is not valid UTF-8")]
     BadEncoding,
@@ -60,14 +167,312 @@ pub enum Error {
     /// Invalid argument provided.
     #[error("invalid argument: {0}")]
     InvalidArgument(String),
+
+    /// One of several users passed to [`Entry::many`] or
+    /// [`EntryBuilder::build_many`] was rejected.
+    #[error("user at index {index} is invalid: {source}")]
+    InvalidUser {
+        /// The position of the rejected user in the list.
+        index: usize,
+        /// Why it was rejected.
+        #[source]
+        source: Box<Error>,
+    },
+
+    /// A required identifier was empty.
+    #[error("{0} cannot be empty")]
+    EmptyField(Field),
+
+    /// An identifier was not valid UTF-8, which every backend requires.
+    #[error("{0} is not valid UTF-8, which the backend requires")]
+    NotUtf8(Field),
+}
+
+/// The identifier an [`Error::EmptyField`] or [`Error::NotUtf8`] is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Field {
+    /// The service name.
+    Service,
+    /// The user name.
+    User,
+}
+
+impl std::fmt::Display for Field {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Field::Service => "service",
+            Field::User => "user",
+        })
+    }
+}
+
+impl Error {
+    /// The status code reported by the platform credential store, if any.
+    ///
+    /// Available for keychain failures on Apple platforms, where it is the
+    /// `OSStatus` of the failed call. Other platforms and errors return `None`.
+    pub fn platform_code(&self) -> Option<i64> {
+        let inner = match self {
+            Error::Keyring(keyring::Error::PlatformFailure(inner))
+            | Error::Keyring(keyring::Error::NoStorageAccess(inner)) => inner,
+            _ => return None,
+        };
+        platform::code(inner.as_ref())
+    }
+
+    /// Whether retrying the operation may succeed.
+    ///
+    /// [`Error::ConcurrentModification`] counts, as do platform failures
+    /// whose [`platform_code`](Error::platform_code) is known to be
+    /// temporary, such as a keychain that is locked until the device is
+    /// unlocked. Every other error is permanent.
+    pub fn is_transient(&self) -> bool {
+        if matches!(self, Error::ConcurrentModification { .. }) {
+            return true;
+        }
+        self.platform_code()
+            .is_some_and(|code| platform::TRANSIENT_CODES.contains(&code))
+    }
+
+    /// A process exit code for this error's category, for CLIs built on
+    /// the crate.
+    ///
+    /// The codes are stable across releases, so scripts can branch on `$?`:
+    ///
+    /// | Code | Category | Errors |
+    /// |------|----------|--------|
+    /// | 1 | store failure | other [`Error::Keyring`] errors |
+    /// | 2 | invalid input | [`Error::InvalidArgument`], [`Error::InvalidUser`], [`Error::EmptyField`], [`Error::NotUtf8`] |
+    /// | 4 | not found | [`keyring::Error::NoEntry`], [`Error::Expired`] |
+    /// | 5 | corrupted | [`Error::CorruptedSecret`], [`Error::InconsistentTotal`], [`Error::NotOurFormat`], [`Error::BadEncoding`] |
+    /// | 6 | retry | any error [`is_transient`](Error::is_transient) reports |
+    /// | 7 | cancelled | [`Error::Cancelled`] |
+    ///
+    /// 0 is left for success and 3 is unused.
+    pub fn exit_code(&self) -> i32 {
+        if self.is_transient() {
+            return 6;
+        }
+        match self {
+            Error::Keyring(keyring::Error::NoEntry) | Error::Expired => 4,
+            Error::Keyring(_) => 1,
+            Error::InvalidArgument(_)
+            | Error::InvalidUser { .. }
+            | Error::EmptyField(_)
+            | Error::NotUtf8(_) => 2,
+            Error::CorruptedSecret(_)
+            | Error::InconsistentTotal { .. }
+            | Error::NotOurFormat
+            | Error::BadEncoding => 5,
+            Error::ConcurrentModification { .. } => 6,
+            Error::Cancelled => 7,
+        }
+    }
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+mod platform {
+    /// errSecInteractionNotAllowed: the keychain is locked and cannot prompt.
+    pub(crate) const TRANSIENT_CODES: &[i64] = &[-25308];
+
+    pub(crate) fn code(err: &(dyn std::error::Error + Send + Sync + 'static)) -> Option<i64> {
+        err.downcast_ref::<security_framework::base::Error>()
+            .map(|err| i64::from(err.code()))
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "ios")))]
+mod platform {
+    pub(crate) const TRANSIENT_CODES: &[i64] = &[];
+
+    pub(crate) fn code(_err: &(dyn std::error::Error + Send + Sync + 'static)) -> Option<i64> {
+        None
+    }
 }
 
 /// A Result type alias using our Error type.
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// The service used by [`Entry::for_user`] and the `TryFrom` conversions
+/// when no service is given.
+pub const DEFAULT_SERVICE: &str = "keyring-cursed";
+
 /// Returns the maximum payload size per chunk for the current platform.
 ///
 /// This can be useful for estimating how many parts a secret will be split into.
 pub fn max_chunk_size() -> usize {
     chunk::max_chunk_size()
 }
+
+/// Returns the maximum payload size per chunk on the given platform.
+///
+/// Useful for planning secrets that will be stored on another platform.
+pub fn max_chunk_size_for(platform: Platform) -> usize {
+    chunk::max_chunk_size_for(platform)
+}
+
+/// Returns whether a secret of `len` bytes fits in a single keyring entry
+/// on the current platform, at the [`max_chunk_size`].
+///
+/// Entries built with [`EntryBuilder::max_entry_size`] can have a smaller
+/// limit; [`Entry::fits_in_one_chunk`] accounts for it.
+pub fn fits_in_one_chunk(len: usize) -> bool {
+    len <= chunk::max_chunk_size()
+}
+
+/// Returns how many keyring entries a secret of `secret_len` bytes will
+/// occupy on the given platform, header overhead included.
+pub fn estimate_parts(secret_len: usize, platform: Platform) -> usize {
+    chunk::estimate_parts(secret_len, platform)
+}
+
+/// Check that `service` would be accepted by [`Entry::new`].
+///
+/// The same check entries run on construction, for validating input
+/// before building one. The only requirement is that the service is not
+/// empty, which fails with [`Error::EmptyField`].
+pub fn validate_service(service: &str) -> Result<()> {
+    entry::validate_service(service)
+}
+
+/// Check that `user` would be accepted by [`Entry::new`].
+///
+/// The same check entries run on construction, for validating input as
+/// it is typed. The user must not be empty, which fails with
+/// [`Error::EmptyField`]. Any other string is accepted: the `.` separator
+/// and `%` are percent-encoded in part names rather than rejected. The
+/// platform's limit on the length of the stored name depends on the
+/// service too, so [`Entry::new`] checks it once both are known.
+pub fn validate_user(user: &str) -> Result<()> {
+    entry::validate_user(user)
+}
+
+/// Returns how many parts `data_len` bytes take in chunks of `chunk_size`.
+///
+/// Empty data still takes one part. This is the count writes use with
+/// [`Chunking::Exact`]; it is not capped at [`MAX_PARTS`].
+///
+/// # Panics
+///
+/// If `chunk_size` is 0.
+pub fn chunks_needed(data_len: usize, chunk_size: usize) -> usize {
+    chunk::chunks_needed(data_len, chunk_size)
+}
+
+/// Returns the byte range of the secret that part `part` of `total` holds
+/// with [`Chunking::Exact`], numbering parts from 1.
+///
+/// # Panics
+///
+/// If `part` is 0 or above `total`, or `total` is not
+/// [`chunks_needed`]`(data_len, chunk_size)`.
+pub fn part_range(
+    part: usize,
+    total: usize,
+    data_len: usize,
+    chunk_size: usize,
+) -> std::ops::Range<usize> {
+    chunk::part_range(part, total, data_len, chunk_size)
+}
+
+/// List the parts stored for `user` in `service` and their sizes,
+/// including gaps.
+///
+/// A shortcut for [`Entry::inspect`] on an entry with the default
+/// configuration, for tools that are given a service and user to examine.
+/// Credentials written with a builder option that changes part names, such
+/// as [`EntryBuilder::namespace`] or [`EntryBuilder::part_width`], need an
+/// entry built the same way instead.
+pub fn inspect(service: &str, user: &str) -> Result<Vec<PartInfo>> {
+    Entry::new(service, user)?.inspect()
+}
+
+/// Report the default credential store and the library versions in use.
+///
+/// Entries given another backend with `Entry::with_backend` do not change
+/// this; it describes what [`Entry::new`] uses.
+pub fn backend_info() -> BackendInfo {
+    BackendInfo::current()
+}
+
+/// Check that the platform keyring works end to end.
+///
+/// A small and a three-part probe secret are written to a reserved entry
+/// (user `probe` in service `keyring-cursed-self-test`), read back and
+/// compared, and then deleted, also when the test fails. The report gives
+/// the chunk size writes really use and how long each round trip took,
+/// which helps diagnose a user's environment.
+pub fn self_test() -> Result<SelfTestReport> {
+    let entry = Entry::new(self_test::SELF_TEST_SERVICE, self_test::SELF_TEST_USER)?;
+    self_test::run(&entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_errors_without_platform_code_are_permanent() {
+        let unknown: Box<dyn std::error::Error + Send + Sync> = "busy".into();
+        let err = Error::from(keyring::Error::PlatformFailure(unknown));
+        assert_eq!(err.platform_code(), None);
+        assert!(!err.is_transient());
+        assert!(!Error::from(keyring::Error::NoEntry).is_transient());
+        assert!(!Error::BadEncoding.is_transient());
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    #[test]
+    fn test_keychain_failures_report_their_status() {
+        let status = security_framework::base::Error::from_code(-25308);
+        let err = Error::from(keyring::Error::PlatformFailure(Box::new(status)));
+        assert_eq!(err.platform_code(), Some(-25308));
+        assert!(err.is_transient());
+    }
+
+    #[test]
+    fn test_exit_codes_follow_the_documented_table() {
+        let unknown: Box<dyn std::error::Error + Send + Sync> = "busy".into();
+        let cases = [
+            (Error::from(keyring::Error::PlatformFailure(unknown)), 1),
+            (Error::InvalidArgument("bad".into()), 2),
+            (Error::EmptyField(Field::User), 2),
+            (Error::from(keyring::Error::NoEntry), 4),
+            (Error::Expired, 4),
+            (Error::CorruptedSecret("bad".into()), 5),
+            (Error::NotOurFormat, 5),
+            (Error::ConcurrentModification { part: 2 }, 6),
+            (Error::Cancelled, 7),
+        ];
+        for (err, code) in cases {
+            assert_eq!(err.exit_code(), code, "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_validation_matches_construction() {
+        assert!(matches!(
+            validate_user(""),
+            Err(Error::EmptyField(Field::User))
+        ));
+        assert!(matches!(
+            validate_service(""),
+            Err(Error::EmptyField(Field::Service))
+        ));
+        validate_user("a.b%c").unwrap();
+        validate_service("service").unwrap();
+        assert!(Entry::new("service", "a.b%c").is_ok());
+    }
+
+    #[test]
+    fn test_field_errors_name_the_field() {
+        assert_eq!(
+            Error::EmptyField(Field::Service).to_string(),
+            "service cannot be empty"
+        );
+        assert_eq!(
+            Error::NotUtf8(Field::User).to_string(),
+            "user is not valid UTF-8, which the backend requires"
+        );
+    }
+}
@@ -0,0 +1,125 @@
+//! The optional manifest written by [`CommitMarker::Manifest`].
+//!
+//! The manifest lists the stored length and SHA-256 of every part, header
+//! included, part 1 first. It is written after every part, so its total
+//! says how many parts the last completed write produced even if part 1
+//! has since gone stale, and each part read is checked against it.
+//!
+//! [`CommitMarker::Manifest`]: crate::CommitMarker::Manifest
+
+use crate::format::{parse_hex_digest, CURRENT_VERSION};
+use crate::sha256::{Sha256, DIGEST_LEN};
+use crate::{Error, Result};
+
+/// The stored length and digest of one part.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ManifestPart {
+    len: usize,
+    digest: [u8; DIGEST_LEN],
+}
+
+impl ManifestPart {
+    /// Describe the bytes stored for a part.
+    pub(crate) fn of(data: &[u8]) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(data);
+        Self {
+            len: data.len(),
+            digest: hasher.finalize(),
+        }
+    }
+}
+
+/// Encode a manifest.
+/// Format: "v1/{total}/{len}:{hex digest}/..." with one entry per part.
+pub(crate) fn encode_manifest(parts: &[ManifestPart]) -> Vec<u8> {
+    use std::fmt::Write;
+
+    let mut out = format!("v{}/{}", CURRENT_VERSION, parts.len());
+    for part in parts {
+        // Writing into a String cannot fail
+        let _ = write!(out, "/{}:", part.len);
+        for byte in part.digest {
+            let _ = write!(out, "{:02x}", byte);
+        }
+    }
+    out.into_bytes()
+}
+
+/// The encoded length of a manifest for `total` parts of up to `max_len`
+/// bytes each.
+pub(crate) fn manifest_len(total: usize, max_len: usize) -> usize {
+    let entry = 1 + max_len.to_string().len() + 1 + DIGEST_LEN * 2;
+    format!("v{}/{}", CURRENT_VERSION, total).len() + total * entry
+}
+
+/// Decode a manifest, returning its parts in order.
+pub(crate) fn decode_manifest(data: &[u8]) -> Result<Vec<ManifestPart>> {
+    let invalid = || Error::CorruptedSecret("invalid manifest".into());
+    let manifest = std::str::from_utf8(data).map_err(|_| invalid())?;
+    let mut fields = manifest.split('/');
+    if fields.next() != Some(&format!("v{}", CURRENT_VERSION)) {
+        return Err(invalid());
+    }
+    let total: usize = fields
+        .next()
+        .and_then(|total| total.parse().ok())
+        .filter(|&total| total > 0)
+        .ok_or_else(invalid)?;
+
+    let parts = fields
+        .map(|field| {
+            let (len, digest) = field.split_once(':')?;
+            Some(ManifestPart {
+                len: len.parse().ok()?,
+                digest: parse_hex_digest(digest)?,
+            })
+        })
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(invalid)?;
+    if parts.len() != total {
+        return Err(invalid());
+    }
+    Ok(parts)
+}
+
+/// Check the stored bytes of `part` against the manifest.
+pub(crate) fn check_part(parts: &[ManifestPart], part: usize, data: &[u8]) -> Result<()> {
+    match parts.get(part - 1) {
+        Some(expected) if *expected == ManifestPart::of(data) => Ok(()),
+        _ => Err(Error::CorruptedSecret(format!(
+            "part {} does not match the manifest",
+            part
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_round_trip() {
+        let parts = [ManifestPart::of(b"v1/1/2|a"), ManifestPart::of(b"v1/2/2|b")];
+        let encoded = encode_manifest(&parts);
+        assert!(encoded.starts_with(b"v1/2/8:"));
+        assert_eq!(decode_manifest(&encoded).unwrap(), parts);
+        assert!(encoded.len() <= manifest_len(2, 8));
+
+        assert!(check_part(&parts, 2, b"v1/2/2|b").is_ok());
+        assert!(check_part(&parts, 2, b"v1/2/2|c").is_err());
+        assert!(check_part(&parts, 3, b"v1/2/2|b").is_err());
+    }
+
+    #[test]
+    fn test_decode_manifest_rejects_malformed_data() {
+        let valid = encode_manifest(&[ManifestPart::of(b"x")]);
+        let truncated = &valid[..valid.len() - 1];
+        for bad in [&b""[..], b"v1/0", b"v2/1/1:00", b"v1/2/1:00", truncated] {
+            assert!(matches!(
+                decode_manifest(bad),
+                Err(Error::CorruptedSecret(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use crate::entry::Entry;
+use crate::{Error, Result};
+
+/// The same secret stored in several independent entries, so losing one
+/// store does not lose the credential.
+///
+/// The mirrors are ordered; the first is the primary. There is no version
+/// tracking between them, so the semantics are simple:
+///
+/// - [`MirrorEntry::set_secret`] writes to every mirror, continuing past
+///   failures, and reports which ones took the write.
+/// - [`MirrorEntry::get_secret`] returns the secret from the first mirror
+///   that can read one. A mirror that missed a write can therefore serve a
+///   stale secret if every mirror before it is unreadable.
+/// - [`MirrorEntry::reconcile`] copies the secret from the first readable
+///   mirror to every other mirror that is missing it or holds a different
+///   one. Run it after a store is reset or a write lost quorum.
+#[derive(Debug, Clone)]
+pub struct MirrorEntry {
+    mirrors: Vec<Entry>,
+}
+
+/// Which mirrors a [`MirrorEntry`] operation succeeded and failed on.
+///
+/// Mirrors are identified by their index in [`MirrorEntry::new`].
+#[derive(Debug)]
+pub struct MirrorReport {
+    /// The number of mirrors.
+    pub mirrors: usize,
+    /// The mirror the secret was read from, for [`MirrorEntry::reconcile`].
+    pub source: Option<usize>,
+    /// The mirrors that were written or deleted, in order.
+    pub written: Vec<usize>,
+    /// The mirrors that failed, with their errors, in order.
+    pub failed: Vec<(usize, Error)>,
+}
+
+impl MirrorReport {
+    fn new(mirrors: usize) -> Self {
+        Self {
+            mirrors,
+            source: None,
+            written: Vec::new(),
+            failed: Vec::new(),
+        }
+    }
+
+    /// Whether a strict majority of mirrors now hold the written state.
+    ///
+    /// For [`MirrorEntry::reconcile`], the source and mirrors that already
+    /// matched it count towards the majority too.
+    pub fn has_quorum(&self) -> bool {
+        (self.mirrors - self.failed.len()) * 2 > self.mirrors
+    }
+}
+
+impl MirrorEntry {
+    /// Mirror a secret across the given entries, the first being the primary.
+    ///
+    /// Returns [`Error::InvalidArgument`] if `mirrors` is empty.
+    pub fn new(mirrors: Vec<Entry>) -> Result<Self> {
+        if mirrors.is_empty() {
+            return Err(Error::InvalidArgument(
+                "a mirror needs at least one entry".into(),
+            ));
+        }
+        Ok(Self { mirrors })
+    }
+
+    /// The mirrored entries, primary first.
+    pub fn mirrors(&self) -> &[Entry] {
+        &self.mirrors
+    }
+
+    /// Store the secret in every mirror.
+    ///
+    /// Every mirror is attempted even if an earlier one fails. Returns the
+    /// first mirror's error if none could be written; otherwise the report
+    /// lists the failures, and [`MirrorReport::has_quorum`] says whether
+    /// enough mirrors took the write.
+    pub fn set_secret(&self, secret: impl AsRef<[u8]>) -> Result<MirrorReport> {
+        let secret = secret.as_ref();
+        self.for_each(|entry| entry.set_secret(secret))
+    }
+
+    /// Retrieve the secret from the first mirror that can read it.
+    ///
+    /// Returns [`keyring::Error::NoEntry`] if no mirror holds the secret,
+    /// and otherwise the first error other than a missing entry.
+    pub fn get_secret(&self) -> Result<Vec<u8>> {
+        self.read_first().map(|(_, secret)| secret)
+    }
+
+    /// Delete the secret from every mirror.
+    ///
+    /// Like [`Entry::delete_credential`], this is idempotent. Every mirror
+    /// is attempted; the error handling matches [`MirrorEntry::set_secret`].
+    pub fn delete_credential(&self) -> Result<MirrorReport> {
+        self.for_each(Entry::delete_credential)
+    }
+
+    /// Copy the secret from the first readable mirror to any mirror that
+    /// is missing it or holds a different secret.
+    ///
+    /// Mirrors that already match are left alone. A mirror that cannot be
+    /// read has its parts deleted with [`Entry::delete_parts_from`] before
+    /// the copy is written. Fails without writing anything if no mirror can
+    /// be read.
+    pub fn reconcile(&self) -> Result<MirrorReport> {
+        let (source, secret) = self.read_first()?;
+        let mut report = MirrorReport::new(self.mirrors.len());
+        report.source = Some(source);
+        for (i, entry) in self.mirrors.iter().enumerate() {
+            if i == source {
+                continue;
+            }
+            let result = match entry.secret_eq(&secret) {
+                Ok(true) => continue,
+                Ok(false) => entry.set_secret(&secret),
+                // An unreadable part 1 would also fail the write, so clear it
+                Err(_) => entry
+                    .delete_parts_from(1)
+                    .and_then(|_| entry.set_secret(&secret)),
+            };
+            match result {
+                Ok(()) => report.written.push(i),
+                Err(e) => report.failed.push((i, e)),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Run `op` on every mirror, failing only if it failed everywhere.
+    fn for_each(&self, op: impl Fn(&Entry) -> Result<()>) -> Result<MirrorReport> {
+        let mut report = MirrorReport::new(self.mirrors.len());
+        for (i, entry) in self.mirrors.iter().enumerate() {
+            match op(entry) {
+                Ok(()) => report.written.push(i),
+                Err(e) => report.failed.push((i, e)),
+            }
+        }
+        if report.written.is_empty() {
+            let (_, first) = report.failed.remove(0);
+            return Err(first);
+        }
+        Ok(report)
+    }
+
+    /// The index and secret of the first mirror that can read one.
+    fn read_first(&self) -> Result<(usize, Vec<u8>)> {
+        let mut first_error = None;
+        for (i, entry) in self.mirrors.iter().enumerate() {
+            match entry.get_secret() {
+                Ok(secret) => return Ok((i, secret)),
+                Err(Error::Keyring(keyring::Error::NoEntry)) => {}
+                Err(e) => {
+                    log::trace!("mirror {} unreadable: {}", i, e);
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        Err(first_error.unwrap_or(Error::Keyring(keyring::Error::NoEntry)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{spy_entry, write_raw};
+
+    fn mirror(name: &str) -> (MirrorEntry, Vec<Entry>) {
+        let entries: Vec<Entry> = (0..3)
+            .map(|i| spy_entry(&format!("{name}-{i}")).0)
+            .collect();
+        (MirrorEntry::new(entries.clone()).unwrap(), entries)
+    }
+
+    #[test]
+    fn test_mirror_writes_everywhere_and_reads_first() {
+        let (mirror, entries) = mirror("mirror-write");
+        let report = mirror.set_secret(b"secret").unwrap();
+        assert_eq!(report.written, [0, 1, 2]);
+        assert!(report.has_quorum());
+        for entry in &entries {
+            assert_eq!(entry.get_secret().unwrap(), b"secret");
+        }
+
+        // A corrupted primary falls through to the next mirror
+        write_raw(&entries[0], 1, b"garbage");
+        assert_eq!(mirror.get_secret().unwrap(), b"secret");
+        let report = mirror.reconcile().unwrap();
+        assert_eq!(report.source, Some(1));
+        assert_eq!(report.written, [0]);
+
+        mirror.delete_credential().unwrap();
+        assert!(matches!(
+            mirror.get_secret(),
+            Err(Error::Keyring(keyring::Error::NoEntry))
+        ));
+    }
+
+    #[test]
+    fn test_reconcile_repairs_stale_and_missing_mirrors() {
+        let (mirror, entries) = mirror("mirror-reconcile");
+        mirror.set_secret(b"new").unwrap();
+        entries[1].set_secret(b"old").unwrap();
+        entries[2].delete_credential().unwrap();
+
+        let report = mirror.reconcile().unwrap();
+        assert_eq!(report.source, Some(0));
+        assert_eq!(report.written, [1, 2]);
+        assert!(report.failed.is_empty());
+        for entry in &entries {
+            assert_eq!(entry.get_secret().unwrap(), b"new");
+        }
+
+        let report = mirror.reconcile().unwrap();
+        assert!(report.written.is_empty());
+    }
+
+    #[test]
+    fn test_quorum_counts_failed_mirrors() {
+        let report = MirrorReport {
+            mirrors: 3,
+            source: None,
+            written: vec![0],
+            failed: vec![(1, Error::BadEncoding), (2, Error::BadEncoding)],
+        };
+        assert!(!report.has_quorum());
+        assert!(MirrorEntry::new(Vec::new()).is_err());
+    }
+}
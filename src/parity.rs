@@ -0,0 +1,335 @@
+//! The optional XOR parity part, from which one lost part can be rebuilt.
+//!
+//! Each payload is framed as its length (4 bytes, big-endian) followed by
+//! the payload, and the frames, zero-padded to the longest, are XORed
+//! together. XORing the parity with every frame but one leaves that frame,
+//! length included, so parts of any size can be recovered. The parity part
+//! is stored as `v1/{total}[/g={generation}]|{xor}` so a reader can tell
+//! whether it belongs to the write it is reading.
+
+use std::sync::atomic::AtomicBool;
+
+use crate::entry::{check_cancelled, Entry, Layout, Reading};
+use crate::format::{check_generation, decode_part_expecting, Header, CURRENT_VERSION};
+use crate::manifest;
+use crate::{Error, Result};
+
+/// The length prefix of each frame.
+const LEN_BYTES: usize = 4;
+
+/// XOR the framed `payloads` together.
+fn xor_frames<'a>(payloads: impl Iterator<Item = &'a [u8]> + Clone) -> Vec<u8> {
+    let longest = payloads.clone().map(<[u8]>::len).max().unwrap_or(0);
+    let mut acc = vec![0; LEN_BYTES + longest];
+    for payload in payloads {
+        // Payloads fit an entry, far below u32::MAX
+        let len = (payload.len() as u32).to_be_bytes();
+        for (a, b) in acc.iter_mut().zip(len.iter().chain(payload)) {
+            *a ^= b;
+        }
+    }
+    acc
+}
+
+/// Encode the parity part over `payloads`.
+fn encode_parity<'a>(
+    total: usize,
+    generation: Option<u32>,
+    payloads: impl Iterator<Item = &'a [u8]> + Clone,
+) -> Vec<u8> {
+    let mut out = format!("v{}/{}", CURRENT_VERSION, total);
+    if let Some(generation) = generation {
+        out.push_str(&format!("/g={}", generation));
+    }
+    out.push('|');
+    let mut out = out.into_bytes();
+    out.extend_from_slice(&xor_frames(payloads));
+    out
+}
+
+/// Decode a parity part, returning its total, generation and XOR bytes.
+fn decode_parity(data: &[u8]) -> Option<(usize, Option<u32>, &[u8])> {
+    let separator = data.iter().position(|&b| b == b'|')?;
+    let header = std::str::from_utf8(&data[..separator]).ok()?;
+    let mut fields = header
+        .strip_prefix(&format!("v{}/", CURRENT_VERSION))?
+        .split('/');
+    let total = fields.next()?.parse().ok()?;
+    let generation = match fields.next() {
+        Some(field) => Some(field.strip_prefix("g=")?.parse().ok()?),
+        None => None,
+    };
+    if fields.next().is_some() {
+        return None;
+    }
+    Some((total, generation, &data[separator + 1..]))
+}
+
+/// Rebuild the one payload missing from `others` out of `parity`.
+fn recover<'a>(parity: &[u8], others: impl Iterator<Item = &'a [u8]>) -> Option<Vec<u8>> {
+    let mut acc = parity.to_vec();
+    for payload in others {
+        if LEN_BYTES + payload.len() > acc.len() {
+            return None;
+        }
+        let len = (payload.len() as u32).to_be_bytes();
+        for (a, b) in acc.iter_mut().zip(len.iter().chain(payload)) {
+            *a ^= b;
+        }
+    }
+    let (len, rest) = acc.split_first_chunk::<LEN_BYTES>()?;
+    let len = u32::from_be_bytes(*len) as usize;
+    // Everything past the frame must have cancelled out
+    if len > rest.len() || rest[len..].iter().any(|&b| b != 0) {
+        return None;
+    }
+    Some(rest[..len].to_vec())
+}
+
+/// Whether a part failed in a way a rebuilt copy would fix: it is missing
+/// or its stored bytes are damaged, rather than the store failing or a
+/// write being in progress.
+fn is_loss(e: &Error) -> bool {
+    matches!(
+        e,
+        Error::Keyring(keyring::Error::NoEntry)
+            | Error::CorruptedSecret(_)
+            | Error::InconsistentTotal { .. }
+    )
+}
+
+impl Entry {
+    /// The backend user name holding the parity part.
+    pub(crate) fn parity_user(&self) -> String {
+        // Like the marker, `#` keeps this out of the part namespace
+        format!("{}#parity", self.encoded_user)
+    }
+
+    /// Write the parity part for `secret` as laid out by `layout`, or
+    /// delete a stale one if a single part needs none.
+    pub(crate) fn write_parity(&self, layout: &Layout, secret: &[u8]) -> Result<()> {
+        if layout.total == 1 {
+            return self.delete_parity();
+        }
+        let payloads = layout.ranges.iter().map(|range| &secret[range.clone()]);
+        let data = encode_parity(layout.total, layout.generation, payloads);
+        self.backend_set(&self.parity_user(), &data)
+            .map_err(Error::from)
+    }
+
+    /// Delete the parity part, if there is one.
+    pub(crate) fn delete_parity(&self) -> Result<()> {
+        match self.backend_delete(&self.parity_user()) {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    /// Read the whole secret once like [`Entry::get_secret`], rebuilding a
+    /// single lost part after part 1 from the parity part.
+    pub(crate) fn read_with_parity(
+        &self,
+        cancel: Option<&AtomicBool>,
+        failed_after: &mut Option<Header>,
+    ) -> Result<Vec<u8>> {
+        check_cancelled(cancel)?;
+        let mut reading = self.read_first_part()?;
+        let mut payloads = vec![std::mem::take(&mut reading.payload)];
+        for part in 2..=reading.total {
+            check_cancelled(cancel)?;
+            let result = match self.read_next_part(part, &mut reading) {
+                Ok(()) => {
+                    payloads.push(std::mem::take(&mut reading.payload));
+                    continue;
+                }
+                Err(e) if is_loss(&e) => self.recover_part(part, &mut reading, payloads, e),
+                Err(e) => Err(e),
+            };
+            if result.is_err() {
+                *failed_after = Some(reading.header);
+            }
+            return result;
+        }
+        Ok(payloads.concat())
+    }
+
+    /// Rebuild part `lost`, which failed with `cause`, from the parity part
+    /// and the remaining parts, returning the whole secret.
+    fn recover_part(
+        &self,
+        lost: usize,
+        reading: &mut Reading,
+        mut payloads: Vec<Vec<u8>>,
+        cause: Error,
+    ) -> Result<Vec<u8>> {
+        let total = reading.total;
+        let parity = match self.backend_get(&self.parity_user()) {
+            Ok(data) => data,
+            Err(keyring::Error::NoEntry) => return Err(cause),
+            Err(e) => return Err(Error::from(e)),
+        };
+        // A parity part left by another write would rebuild the wrong bytes
+        let parity = match decode_parity(&parity) {
+            Some((parity_total, generation, xor))
+                if parity_total == total && generation == reading.header.generation =>
+            {
+                xor
+            }
+            _ => return Err(cause),
+        };
+
+        // The chain can only be checked once the lost payload is back
+        let mut rest = Vec::new();
+        for part in lost + 1..=total {
+            let second_loss = |e: Error| match is_loss(&e) {
+                true => Error::CorruptedSecret(format!(
+                    "parts {} and {} are both lost, and parity rebuilds only one ({})",
+                    lost, part, e
+                )),
+                false => e,
+            };
+            let data = self
+                .read_part_in(part, reading.header.slot)
+                .map_err(second_loss)?;
+            if let Some(manifest) = &reading.manifest {
+                manifest::check_part(manifest, part, &data).map_err(second_loss)?;
+            }
+            let (header, payload) =
+                decode_part_expecting(&data, part, total).map_err(second_loss)?;
+            check_generation(&header, reading.header.generation)?;
+            rest.push((header, payload.to_vec()));
+        }
+
+        let others = payloads
+            .iter()
+            .chain(rest.iter().map(|(_, payload)| payload))
+            .map(Vec::as_slice);
+        let recovered = recover(parity, others).ok_or_else(|| {
+            Error::CorruptedSecret(format!(
+                "part {} cannot be rebuilt: the parity part does not match the others",
+                lost
+            ))
+        })?;
+        reading.chain.recovered(lost, total, &recovered)?;
+        for (i, (header, payload)) in rest.iter().enumerate() {
+            reading.chain.verify(header, lost + 1 + i, total, payload)?;
+        }
+        log::warn!(
+            "part {} of {} was lost ({}); rebuilt it from parity",
+            lost,
+            self.encoded_user,
+            cause
+        );
+
+        payloads.push(recovered);
+        payloads.extend(rest.into_iter().map(|(_, payload)| payload));
+        Ok(payloads.concat())
+    }
+
+    /// Delete the parity part if parity is enabled, before a write that
+    /// does not produce one, so readers never pair it with other parts.
+    pub(crate) fn drop_parity(&self) -> Result<()> {
+        match self.parity {
+            true => self.delete_parity(),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{spy_entry, write_raw, SERVICE};
+
+    fn parity_entry(user: &str) -> (Entry, crate::backend::SpyBackend) {
+        let (mut entry, spy) = spy_entry(user);
+        entry.parity = true;
+        (entry, spy)
+    }
+
+    #[test]
+    fn test_recover_rebuilds_any_one_payload() {
+        let payloads: [&[u8]; 3] = [b"first part", b"second", b""];
+        let parity = xor_frames(payloads.iter().copied());
+        for lost in 0..payloads.len() {
+            let others = payloads
+                .iter()
+                .enumerate()
+                .filter(|&(i, _)| i != lost)
+                .map(|(_, payload)| *payload);
+            assert_eq!(recover(&parity, others).unwrap(), payloads[lost]);
+        }
+        assert!(recover(&parity, [&b"wrong"[..], b"parts"].into_iter()).is_none());
+    }
+
+    #[test]
+    fn test_parity_roundtrip() {
+        let data = encode_parity(3, Some(7), [&b"ab"[..], b"c"].into_iter());
+        let (total, generation, _) = decode_parity(&data).unwrap();
+        assert_eq!((total, generation), (3, Some(7)));
+        assert!(decode_parity(b"v1/3/x=1|").is_none());
+    }
+
+    #[test]
+    fn test_get_secret_rebuilds_a_lost_part() {
+        let (entry, spy) = parity_entry("parity");
+        let chunk = crate::max_chunk_size();
+        let secret: Vec<u8> = (0..chunk * 3 + 5).map(|i| i as u8).collect();
+        entry.set_secret(&secret).unwrap();
+        assert!(spy.contains(SERVICE, &entry.parity_user()));
+
+        entry.backend_delete(&entry.part_user(2)).unwrap();
+        assert_eq!(entry.get_secret().unwrap(), secret);
+        write_raw(&entry, 2, b"damaged");
+        assert_eq!(entry.get_secret().unwrap(), secret);
+
+        // A second loss is beyond what one parity part can fix
+        entry.backend_delete(&entry.part_user(4)).unwrap();
+        let err = entry.get_secret().unwrap_err();
+        assert!(matches!(err, Error::CorruptedSecret(ref msg) if msg.contains("both lost")));
+    }
+
+    #[test]
+    fn test_rebuilt_part_is_checked_by_the_hash_chain() {
+        let (mut entry, _spy) = parity_entry("parity-chain");
+        entry.hash_chain = true;
+        let chunk = entry.chunk_limit(false);
+        let secret: Vec<u8> = (0..chunk * 2 + 1).map(|i| i as u8).collect();
+        entry.set_secret(&secret).unwrap();
+        for lost in [2, 3] {
+            let part = entry.read_part(lost).unwrap();
+            entry.backend_delete(&entry.part_user(lost)).unwrap();
+            assert_eq!(entry.get_secret().unwrap(), secret);
+            write_raw(&entry, lost, &part);
+        }
+
+        // Parity that rebuilds different bytes is caught by the chain
+        let mut forged = entry.backend_get(&entry.parity_user()).unwrap();
+        *forged.last_mut().unwrap() ^= 1;
+        entry.backend_set(&entry.parity_user(), &forged).unwrap();
+        entry.backend_delete(&entry.part_user(2)).unwrap();
+        assert!(matches!(entry.get_secret(), Err(Error::CorruptedSecret(_))));
+    }
+
+    #[test]
+    fn test_stale_parity_is_ignored() {
+        let (entry, spy) = parity_entry("parity-stale");
+        let chunk = crate::max_chunk_size();
+        entry.set_secret(vec![1; chunk + 1]).unwrap();
+        let stale = entry.backend_get(&entry.parity_user()).unwrap();
+        entry.set_secret(vec![2; chunk + 1]).unwrap();
+        entry.backend_set(&entry.parity_user(), &stale).unwrap();
+
+        entry.backend_delete(&entry.part_user(2)).unwrap();
+        assert!(matches!(
+            entry.get_secret(),
+            Err(Error::Keyring(keyring::Error::NoEntry))
+        ));
+
+        entry.set_secret(b"single").unwrap();
+        assert!(!spy.contains(SERVICE, &entry.parity_user()));
+        entry.set_secret(vec![3; chunk + 1]).unwrap();
+        entry.delete_credential().unwrap();
+        assert!(!spy.contains(SERVICE, &entry.parity_user()));
+    }
+}
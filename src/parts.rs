@@ -0,0 +1,175 @@
+use crate::chain::ChainCheck;
+use crate::entry::Entry;
+use crate::format::{check_generation, check_header, decode_part};
+use crate::sha256::{Sha256, DIGEST_LEN};
+use crate::Result;
+
+/// An iterator over the payloads of a stored secret, created by
+/// [`Entry::parts`].
+///
+/// Each part is read from the backend only when the iterator reaches it.
+/// Part 1 determines the total; every later part is checked against it the
+/// same way [`Entry::get_secret`] does, including its hash chain link.
+/// After the first error the iterator yields nothing more.
+#[derive(Debug)]
+pub struct PartIter<'a> {
+    entry: &'a Entry,
+    next: usize,
+    total: Option<usize>,
+    /// The slot part 1 says later parts are in.
+    slot: u8,
+    /// The generation part 1 was stamped with.
+    generation: Option<u32>,
+    chain: ChainCheck,
+    done: bool,
+}
+
+impl<'a> PartIter<'a> {
+    fn new(entry: &'a Entry) -> Self {
+        Self {
+            entry,
+            next: 1,
+            total: None,
+            slot: 0,
+            generation: None,
+            chain: ChainCheck::default(),
+            done: false,
+        }
+    }
+
+    fn read_next(&mut self) -> Result<(usize, Vec<u8>)> {
+        let part = self.next;
+        let mut data = self.entry.read_part_in(part, self.slot)?;
+        let (header, payload) = decode_part(&data)?;
+        if part == 1 {
+            self.slot = header.slot;
+            self.generation = header.generation;
+        }
+        let total = *self.total.get_or_insert(header.total);
+        check_header(&header, part, total)?;
+        check_generation(&header, self.generation)?;
+        self.chain.verify(&header, part, total, payload)?;
+
+        // Strip the header in place rather than copying the payload
+        let header_len = data.len() - payload.len();
+        data.drain(..header_len);
+        Ok((part, data))
+    }
+}
+
+impl Iterator for PartIter<'_> {
+    type Item = Result<(usize, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.total.is_some_and(|total| self.next > total) {
+            return None;
+        }
+
+        let item = self.read_next();
+        match item {
+            Ok(_) => self.next += 1,
+            Err(_) => self.done = true,
+        }
+        Some(item)
+    }
+}
+
+impl std::iter::FusedIterator for PartIter<'_> {}
+
+impl Entry {
+    /// Iterate over the stored payloads one part at a time.
+    ///
+    /// Yields `(part, payload)` for parts 1 through the total recorded on
+    /// part 1, reading each lazily. Useful for hashing or streaming a large
+    /// secret without reassembling it in memory. Read and decode errors are
+    /// yielded as `Err` items, after which the iterator ends.
+    pub fn parts(&self) -> PartIter<'_> {
+        PartIter::new(self)
+    }
+
+    /// The SHA-256 of the stored secret, computed one part at a time.
+    ///
+    /// Payloads from [`Entry::parts`] are fed into the hasher as they are
+    /// read, so no more than one part is held in memory and the secret is
+    /// never reassembled. The parts are checked as [`Entry::parts`] checks
+    /// them, and the first error is returned.
+    pub fn secret_digest(&self) -> Result<[u8; DIGEST_LEN]> {
+        let mut hasher = Sha256::new();
+        for item in self.parts() {
+            let (_, payload) = item?;
+            hasher.update(&payload);
+        }
+        Ok(hasher.finalize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sha256::Sha256;
+    use crate::test_util::{spy_entry, take_ops, write_raw};
+    use crate::Error;
+
+    #[test]
+    fn test_parts_yields_payloads_lazily() {
+        let (entry, spy) = spy_entry("parts-lazy");
+        let secret: Vec<u8> = (0..crate::max_chunk_size() * 2 + 10)
+            .map(|i| i as u8)
+            .collect();
+        entry.set_secret(&secret).unwrap();
+        take_ops(&spy);
+
+        let mut parts = entry.parts();
+        let (part, first) = parts.next().unwrap().unwrap();
+        assert_eq!(part, 1);
+        assert_eq!(take_ops(&spy).len(), 1);
+
+        let mut reassembled = first;
+        for item in parts {
+            let (_, payload) = item.unwrap();
+            reassembled.extend_from_slice(&payload);
+        }
+        assert_eq!(reassembled, secret);
+        assert_eq!(take_ops(&spy).len(), 2);
+    }
+
+    #[test]
+    fn test_parts_stops_after_error() {
+        let (entry, spy) = spy_entry("parts-error");
+        write_raw(&entry, 1, b"v1/1/3|a");
+        write_raw(&entry, 2, b"v1/2/4|b");
+        write_raw(&entry, 3, b"v1/3/3|c");
+        take_ops(&spy);
+
+        let items: Vec<_> = entry.parts().collect();
+        assert_eq!(items.len(), 2);
+        assert_eq!(*items[0].as_ref().unwrap(), (1, b"a".to_vec()));
+        assert!(matches!(
+            items[1],
+            Err(Error::InconsistentTotal { part: 2, .. })
+        ));
+        assert_eq!(take_ops(&spy).len(), 2);
+    }
+
+    #[test]
+    fn test_secret_digest_matches_whole_secret() {
+        let (entry, _spy) = spy_entry("secret-digest");
+        let secret = vec![9; crate::max_chunk_size() * 2 + 1];
+        entry.set_secret(&secret).unwrap();
+        let mut hasher = Sha256::new();
+        hasher.update(&secret);
+        assert_eq!(entry.secret_digest().unwrap(), hasher.finalize());
+
+        write_raw(&entry, 2, b"v1/2/2|short");
+        assert!(entry.secret_digest().is_err());
+    }
+
+    #[test]
+    fn test_parts_of_missing_credential() {
+        let (entry, _spy) = spy_entry("parts-missing");
+        let items: Vec<_> = entry.parts().collect();
+        assert!(matches!(
+            items.as_slice(),
+            [Err(Error::Keyring(keyring::Error::NoEntry))]
+        ));
+    }
+}
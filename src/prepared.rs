@@ -0,0 +1,223 @@
+use std::sync::atomic::AtomicBool;
+
+use crate::builder::CommitMarker;
+use crate::entry::{check_cancelled, encode_layout_part, record, Entry};
+use crate::{Error, Result};
+
+/// A secret staged by [`Entry::prepare`], waiting to be committed.
+///
+/// The staged parts are already encoded for their final position, under
+/// names no reader looks at, so the stored credential is unchanged until
+/// [`PreparedWrite::commit`]. Dropping the handle without committing
+/// aborts: the staged parts are deleted on a best-effort basis, and a
+/// failure to delete them is logged rather than reported.
+#[derive(Debug)]
+pub struct PreparedWrite {
+    entry: Entry,
+    total: usize,
+    /// The generation the staged parts are stamped with.
+    generation: Option<u32>,
+    finished: bool,
+}
+
+impl PreparedWrite {
+    /// The number of parts the staged secret will be stored in.
+    pub fn part_count(&self) -> usize {
+        self.total
+    }
+
+    /// Move the staged parts into place and delete the staging copies.
+    ///
+    /// Parts are copied in the same order [`Entry::set_secret`] writes
+    /// them, so part 1 is replaced last, and surplus parts from the old
+    /// credential are removed. The staged parts are read back rather than
+    /// kept in memory, so the handle never holds the secret. If the copy
+    /// fails, the handle is dropped and the staging parts deleted; the
+    /// stored credential is then left as an interrupted
+    /// [`Entry::set_secret`] would leave it.
+    pub fn commit(mut self) -> Result<()> {
+        let entry = &self.entry;
+        let total = self.total;
+        entry.invalidate_cache();
+
+        // Re-read the old header, since it may have changed while staged
+        let old = entry.stored_header()?;
+        if entry.commit_marker != CommitMarker::Part1 {
+            entry.delete_marker()?;
+        }
+        // The staged parts were never read as plaintext, so there is none
+        entry.drop_parity()?;
+        let mut manifest = entry.manifest_for(total);
+        for part in (2..=total).rev() {
+            let data = copy_staged(entry, part, total)?;
+            record(&mut manifest, part, &data);
+        }
+        if let Some(old) = old.filter(|old| old.slot == 0 && old.total > total) {
+            entry.delete_parts(total + 1..=old.total)?;
+        }
+        entry.barrier()?;
+        let part1 = copy_staged(entry, 1, total)?;
+        if entry.verify_writes && entry.read_part(1)? != part1 {
+            return Err(Error::CorruptedSecret("write verification failed".into()));
+        }
+        record(&mut manifest, 1, &part1);
+        entry.write_marker(total, &manifest)?;
+        // Parts left under the alternate names by Entry::replace_secret
+        if let Some(old) = old.filter(|old| old.slot != 0) {
+            entry.delete_parts_in(2..=old.total, old.slot)?;
+        }
+        if old.is_some_and(|old| old.typed) {
+            entry.delete_content_type()?;
+        }
+        entry.remember_total(total, self.generation);
+
+        self.finished = true;
+        delete_staged(entry, total)
+    }
+
+    /// Delete the staged parts, leaving the stored credential untouched.
+    pub fn abort(mut self) -> Result<()> {
+        self.finished = true;
+        delete_staged(&self.entry, self.total)
+    }
+}
+
+impl Drop for PreparedWrite {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        if let Err(e) = delete_staged(&self.entry, self.total) {
+            log::warn!(
+                "failed to delete parts staged for an abandoned write: {}",
+                e
+            );
+        }
+    }
+}
+
+impl Entry {
+    /// Stage `secret` without touching the stored credential.
+    ///
+    /// Every part is encoded exactly as [`Entry::set_secret`] would write
+    /// it, using the chunk size recorded by the current credential, and
+    /// stored under a staging name. Call [`PreparedWrite::commit`] to make
+    /// it the stored secret, or [`PreparedWrite::abort`] to discard it;
+    /// this allows a write to be coordinated with another resource, such as
+    /// a database transaction.
+    ///
+    /// If staging fails part way, the parts already staged are deleted.
+    /// Only one write should be staged per entry at a time, since a second
+    /// one reuses the same staging names.
+    pub fn prepare(&self, secret: impl AsRef<[u8]>) -> Result<PreparedWrite> {
+        self.stage(secret.as_ref(), None)
+    }
+
+    /// The body of [`Entry::prepare`], checking `cancel` before each part.
+    pub(crate) fn stage(
+        &self,
+        secret: &[u8],
+        cancel: Option<&AtomicBool>,
+    ) -> Result<PreparedWrite> {
+        let (layout, _) = self.plan(secret, None, true)?;
+
+        // Created first so a failed write below cleans up after itself
+        let prepared = PreparedWrite {
+            entry: self.clone(),
+            total: layout.total,
+            generation: layout.generation,
+            finished: false,
+        };
+        let mut buf = Vec::new();
+        for part in 1..=layout.total {
+            check_cancelled(cancel)?;
+            encode_layout_part(part, &layout, secret, &mut buf)?;
+            self.backend_set(&self.staged_user(part), &buf)?;
+        }
+        self.warn_if_many_parts(layout.total, secret.len());
+        Ok(prepared)
+    }
+
+    /// The backend user name staging the given part number.
+    pub(crate) fn staged_user(&self, part: usize) -> String {
+        // Part names end in digits, so the suffix keeps these apart
+        format!("{}#staged", self.part_user(part))
+    }
+}
+
+/// Copy a staged part of `total` to its real name, returning the copied
+/// bytes.
+fn copy_staged(entry: &Entry, part: usize, total: usize) -> Result<Vec<u8>> {
+    let data = entry.backend_get(&entry.staged_user(part))?;
+    let user = entry.part_user(part);
+    entry.backend_set(&user, &data)?;
+    entry.describe_part(&user, part, total);
+    Ok(data)
+}
+
+/// Delete the first `total` staged parts, skipping any that are missing.
+fn delete_staged(entry: &Entry, total: usize) -> Result<()> {
+    for part in (1..=total).rev() {
+        match entry.backend_delete(&entry.staged_user(part)) {
+            Ok(()) | Err(keyring::Error::NoEntry) => continue,
+            Err(e) => return Err(Error::from(e)),
+        }
+    }
+    Ok(())
+}
+
+/// Delete staged parts 1, 2, ... until one is missing, for when their
+/// total is unknown.
+#[cfg(any(test, feature = "testing"))]
+pub(crate) fn delete_unknown_staged(entry: &Entry) -> Result<()> {
+    for part in 1..=crate::chunk::MAX_PARTS {
+        match entry.backend_delete(&entry.staged_user(part)) {
+            Ok(()) => continue,
+            Err(keyring::Error::NoEntry) => break,
+            Err(e) => return Err(Error::from(e)),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{part_exists, spy_entry, SERVICE};
+
+    #[test]
+    fn test_prepare_stages_until_commit() {
+        let (entry, spy) = spy_entry("prepare-commit");
+        entry
+            .set_secret(vec![1; crate::max_chunk_size() * 3])
+            .unwrap();
+
+        let staged = vec![2; crate::max_chunk_size() + 1];
+        let prepared = entry.prepare(&staged).unwrap();
+        assert_eq!(prepared.part_count(), 2);
+        assert!(spy.contains(SERVICE, &entry.staged_user(2)));
+        assert_eq!(
+            entry.get_secret().unwrap(),
+            vec![1; crate::max_chunk_size() * 3]
+        );
+
+        prepared.commit().unwrap();
+        assert_eq!(entry.get_secret().unwrap(), staged);
+        assert!(!part_exists(&spy, &entry, 3));
+        for part in 1..=2 {
+            assert!(!spy.contains(SERVICE, &entry.staged_user(part)));
+        }
+    }
+
+    #[test]
+    fn test_abort_and_drop_remove_staging() {
+        let (entry, spy) = spy_entry("prepare-abort");
+        entry.set_secret(b"kept").unwrap();
+
+        entry.prepare(b"discarded").unwrap().abort().unwrap();
+        assert!(!spy.contains(SERVICE, &entry.staged_user(1)));
+
+        drop(entry.prepare(b"dropped").unwrap());
+        assert!(!spy.contains(SERVICE, &entry.staged_user(1)));
+        assert_eq!(entry.get_secret().unwrap(), b"kept");
+    }
+}
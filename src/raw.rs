@@ -0,0 +1,165 @@
+use crate::builder::CommitMarker;
+use crate::chunk::MAX_PARTS;
+use crate::entry::{record, Entry};
+use crate::format::decode_part;
+use crate::{Error, Result};
+
+impl Entry {
+    /// Export the stored parts exactly as the backend holds them.
+    ///
+    /// Parts `1, 2, ...` are read until one is missing and concatenated as
+    /// netstrings (`{len}:{bytes},`), so payloads may contain any byte. Parts
+    /// are not decoded or validated, which makes this work for any format
+    /// version, including credentials this crate cannot read. The commit
+    /// marker and any content type entry are not included. Returns an
+    /// empty vector if nothing is stored.
+    ///
+    /// If part 1 can be decoded, later parts are read from the slot it
+    /// names, so a secret stored by [`Entry::replace_secret`] exports whole.
+    pub fn export_raw(&self) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        let mut slot = 0;
+        for part in 1..=MAX_PARTS {
+            match self.read_part_in(part, slot) {
+                Ok(data) => {
+                    if part == 1 {
+                        slot = raw_slot(&data);
+                    }
+                    out.extend_from_slice(data.len().to_string().as_bytes());
+                    out.push(b':');
+                    out.extend_from_slice(&data);
+                    out.push(b',');
+                }
+                Err(Error::Keyring(keyring::Error::NoEntry)) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(out)
+    }
+
+    /// Restore parts previously returned by [`Entry::export_raw`].
+    ///
+    /// The whole export is parsed before anything is written, so malformed
+    /// data returns [`Error::InvalidArgument`] and leaves the store as it
+    /// was. Parts are written back byte for byte, last to first, and stored
+    /// parts following the imported ones are deleted as by
+    /// [`Entry::delete_parts_from`]. Under [`CommitMarker::Sentinel`] the
+    /// marker is rewritten to the number of parts imported, and under
+    /// [`CommitMarker::Manifest`] a manifest of them is written. Importing an
+    /// empty export deletes the stored parts. Later parts are written to the
+    /// slot a decodable part 1 names, mirroring [`Entry::export_raw`].
+    pub fn import_raw(&self, data: &[u8]) -> Result<()> {
+        let parts = parse_export(data)?;
+        if parts.len() > self.max_parts {
+            return Err(Error::InvalidArgument(format!(
+                "export holds {} parts, which exceeds maximum parts ({})",
+                parts.len(),
+                self.max_parts
+            )));
+        }
+        self.check_part_width(parts.len())?;
+
+        self.invalidate_cache();
+        if self.commit_marker != CommitMarker::Part1 {
+            self.delete_marker()?;
+        }
+        self.drop_parity()?;
+        let slot = parts.first().map_or(0, |part1| raw_slot(part1));
+        for (i, part) in parts.iter().enumerate().skip(1).rev() {
+            self.backend_set(&self.slot_user(i + 1, slot), part)?;
+        }
+        self.delete_parts_from(parts.len() + 1)?;
+        let Some(part1) = parts.first() else {
+            return Ok(());
+        };
+        self.barrier()?;
+        self.backend_set(&self.part_user(1), part1)?;
+        let mut manifest = self.manifest_for(parts.len());
+        for (i, part) in parts.iter().enumerate() {
+            record(&mut manifest, i + 1, part);
+        }
+        self.write_marker(parts.len(), &manifest)
+    }
+}
+
+/// The slot a raw part 1 names, or the primary one if it cannot be decoded.
+fn raw_slot(part1: &[u8]) -> u8 {
+    decode_part(part1).map_or(0, |(header, _)| header.slot)
+}
+
+/// Split an export into its netstring-framed parts.
+fn parse_export(mut data: &[u8]) -> Result<Vec<&[u8]>> {
+    let malformed = || Error::InvalidArgument("malformed raw export".into());
+    let mut parts = Vec::new();
+    while !data.is_empty() {
+        let colon = data.iter().position(|&b| b == b':').ok_or_else(malformed)?;
+        let len = std::str::from_utf8(&data[..colon])
+            .ok()
+            .filter(|digits| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|digits| digits.parse::<usize>().ok())
+            .ok_or_else(malformed)?;
+        let rest = &data[colon + 1..];
+        if rest.len() <= len || rest[len] != b',' {
+            return Err(malformed());
+        }
+        parts.push(&rest[..len]);
+        data = &rest[len + 1..];
+    }
+    Ok(parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{part_exists, spy_entry, write_raw};
+
+    #[test]
+    fn test_raw_round_trip_is_byte_exact() {
+        let (entry, spy) = spy_entry("raw-round-trip");
+        entry
+            .set_secret(vec![b','; crate::max_chunk_size() + 10])
+            .unwrap();
+        let export = entry.export_raw().unwrap();
+        let part1 = entry.read_part(1).unwrap();
+
+        entry.set_secret(b"replaced").unwrap();
+        write_raw(&entry, 3, b"stray");
+        entry.import_raw(&export).unwrap();
+
+        assert_eq!(entry.read_part(1).unwrap(), part1);
+        assert!(!part_exists(&spy, &entry, 3));
+        assert_eq!(entry.export_raw().unwrap(), export);
+    }
+
+    #[test]
+    fn test_raw_transports_undecodable_parts() {
+        let (entry, _spy) = spy_entry("raw-garbage");
+        write_raw(&entry, 1, b"v9/not:ours,");
+        write_raw(&entry, 2, b"");
+        let export = entry.export_raw().unwrap();
+        assert_eq!(export, b"12:v9/not:ours,,0:,");
+
+        entry.delete_parts_from(1).unwrap();
+        entry.import_raw(&export).unwrap();
+        assert_eq!(entry.read_part(1).unwrap(), b"v9/not:ours,");
+        assert_eq!(entry.read_part(2).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_import_raw_rejects_malformed_data_without_writing() {
+        let (entry, spy) = spy_entry("raw-malformed");
+        entry.set_secret(b"kept").unwrap();
+        for bad in [&b"5:abc,"[..], b"3:abc", b"x:abc,", b":,", b"3:abc;"] {
+            assert!(matches!(
+                entry.import_raw(bad),
+                Err(Error::InvalidArgument(_))
+            ));
+        }
+        assert!(part_exists(&spy, &entry, 1));
+        assert_eq!(entry.get_secret().unwrap(), b"kept");
+
+        entry.import_raw(b"").unwrap();
+        assert!(!entry.exists().unwrap());
+        assert_eq!(entry.export_raw().unwrap(), b"");
+    }
+}
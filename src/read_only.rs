@@ -0,0 +1,68 @@
+use crate::entry::Entry;
+use crate::Result;
+
+/// An entry that can read its credential but never modify it, created by
+/// [`Entry::read_only`].
+///
+/// Only read operations are exposed, so code holding a `ReadOnlyEntry`
+/// cannot write or delete parts.
+#[derive(Debug, Clone)]
+pub struct ReadOnlyEntry {
+    entry: Entry,
+}
+
+impl ReadOnlyEntry {
+    /// Retrieve binary data from the credential store.
+    ///
+    /// See [`Entry::get_secret`].
+    pub fn get_secret(&self) -> Result<Vec<u8>> {
+        self.entry.get_secret()
+    }
+
+    /// Retrieve a password (UTF-8 string) from the credential store.
+    ///
+    /// See [`Entry::get_password`].
+    pub fn get_password(&self) -> Result<String> {
+        self.entry.get_password()
+    }
+
+    /// Whether a credential is stored.
+    ///
+    /// See [`Entry::exists`].
+    pub fn exists(&self) -> Result<bool> {
+        self.entry.exists()
+    }
+
+    /// The number of parts the stored secret is split into.
+    ///
+    /// See [`Entry::part_count`].
+    pub fn part_count(&self) -> Result<usize> {
+        self.entry.part_count()
+    }
+}
+
+impl Entry {
+    /// Give up write access to this entry's credential.
+    pub fn read_only(self) -> ReadOnlyEntry {
+        ReadOnlyEntry { entry: self }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{spy_entry, take_ops};
+
+    #[test]
+    fn test_read_only_entry_only_reads() {
+        let (entry, spy) = spy_entry("read-only");
+        entry.set_password("secret").unwrap();
+        take_ops(&spy);
+
+        let entry = entry.read_only();
+        assert!(entry.exists().unwrap());
+        assert_eq!(entry.part_count().unwrap(), 1);
+        assert_eq!(entry.get_password().unwrap(), "secret");
+        assert_eq!(entry.get_secret().unwrap(), b"secret");
+        assert!(take_ops(&spy).iter().all(|(kind, _)| *kind == "get"));
+    }
+}
@@ -0,0 +1,712 @@
+use std::collections::HashMap;
+
+use crate::chain;
+use crate::chunk::MAX_PARTS;
+use crate::entry::{Entry, WriteMode};
+use crate::format::{decode_part, decode_part_expecting, encode_part, Header};
+use crate::{Error, Result};
+
+/// Something [`Entry::repair`] found wrong with the stored parts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RepairProblem {
+    /// The part exists but its header could not be decoded.
+    Unreadable { part: usize },
+    /// The part is stored at one index but its header claims another.
+    Misplaced { part: usize, claimed: usize },
+    /// The part's header claims a total that disagrees with the chosen one.
+    InconsistentTotal { part: usize, total: usize },
+    /// A part within the chosen total is not stored.
+    Missing { part: usize },
+    /// The part agrees with the chosen total but was written by a
+    /// different write than part 1, as a torn write leaves it.
+    /// `generation` is the one its header records.
+    StaleGeneration {
+        part: usize,
+        generation: Option<u32>,
+    },
+}
+
+impl RepairProblem {
+    /// The part index the problem was found at.
+    pub fn part(&self) -> usize {
+        match self {
+            RepairProblem::Unreadable { part }
+            | RepairProblem::Misplaced { part, .. }
+            | RepairProblem::InconsistentTotal { part, .. }
+            | RepairProblem::Missing { part }
+            | RepairProblem::StaleGeneration { part, .. } => *part,
+        }
+    }
+}
+
+/// What [`Entry::repair`] did about the problems it found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RepairAction {
+    /// The credential was already consistent, or nothing was stored.
+    Nothing,
+    /// A consistent set of parts was found and rewritten as a clean
+    /// credential; surplus parts were removed.
+    Rewrote { total: usize },
+    /// No consistent majority exists. Nothing was changed.
+    Conflict,
+}
+
+/// The outcome of [`Entry::repair`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepairReport {
+    /// The part indices that held data, in ascending order.
+    pub parts_found: Vec<usize>,
+    /// The total most parts agree on, if any.
+    pub total: Option<usize>,
+    /// Everything that disagreed with a clean layout of `total` parts.
+    pub problems: Vec<RepairProblem>,
+    /// What was done about it.
+    pub action: RepairAction,
+}
+
+/// The outcome of [`Entry::compact`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactReport {
+    /// The number of parts stored before, orphans included.
+    pub parts_before: usize,
+    /// The number of parts the secret is stored in now.
+    pub parts_after: usize,
+}
+
+/// Decode parts `2, 3, ...` of a secret that ends with them, or say why
+/// they cannot be part of one.
+fn check_survivors(
+    survivors: &[Vec<u8>],
+) -> std::result::Result<(Vec<Header>, Vec<&[u8]>), String> {
+    let total = survivors.len() + 1;
+    let mut headers: Vec<Header> = Vec::new();
+    let mut payloads = Vec::new();
+    for (i, data) in survivors.iter().enumerate() {
+        let (header, payload) =
+            decode_part_expecting(data, i + 2, total).map_err(|e| e.to_string())?;
+        if headers
+            .first()
+            .is_some_and(|first| first.generation != header.generation)
+        {
+            return Err("the surviving parts are from different writes".into());
+        }
+        headers.push(header);
+        payloads.push(payload);
+    }
+    Ok((headers, payloads))
+}
+
+/// A part read during the scan; `None` if it could not be decoded.
+type Scanned = (usize, Option<(Header, Vec<u8>)>);
+
+impl Entry {
+    /// Detect and repair parts left inconsistent by a botched write.
+    ///
+    /// Every stored part is read and its header checked against its index.
+    /// The total claimed by most decodable parts is chosen; if parts
+    /// `1..=total` are all present and agree on it, the secret is rewritten
+    /// as a clean credential and any surplus parts are deleted. If there is
+    /// no clear majority, or it is incomplete, the report describes the
+    /// conflict and nothing is changed. A part within the total whose
+    /// generation differs from part 1's is left by a torn write, which
+    /// mixes two secrets, so it is reported as
+    /// [`RepairProblem::StaleGeneration`] and is a conflict too.
+    pub fn repair(&self) -> Result<RepairReport> {
+        let (scanned, slot) = self.scan_parts()?;
+        let parts_found = scanned.iter().map(|(part, _)| *part).collect();
+
+        let total = majority_total(&scanned);
+        let part1_generation = match scanned.first() {
+            Some((1, Some((header, _)))) => Some(header.generation),
+            _ => None,
+        };
+        let mut problems = Vec::new();
+        for (part, decoded) in &scanned {
+            match decoded {
+                None => problems.push(RepairProblem::Unreadable { part: *part }),
+                Some((header, _)) => {
+                    if header.part != *part {
+                        problems.push(RepairProblem::Misplaced {
+                            part: *part,
+                            claimed: header.part,
+                        });
+                    }
+                    if Some(header.total) != total {
+                        problems.push(RepairProblem::InconsistentTotal {
+                            part: *part,
+                            total: header.total,
+                        });
+                    } else if part1_generation.is_some_and(|g| g != header.generation) {
+                        problems.push(RepairProblem::StaleGeneration {
+                            part: *part,
+                            generation: header.generation,
+                        });
+                    }
+                }
+            }
+        }
+
+        let Some(total) = total else {
+            let action = if scanned.is_empty() {
+                RepairAction::Nothing
+            } else {
+                RepairAction::Conflict
+            };
+            return Ok(RepairReport {
+                parts_found,
+                total: None,
+                problems,
+                action,
+            });
+        };
+
+        for part in 1..=total {
+            if !scanned.iter().any(|(found, _)| *found == part) {
+                problems.push(RepairProblem::Missing { part });
+            }
+        }
+
+        // The chosen layout is only trustworthy if parts 1..=total have no problems
+        let intact = problems.iter().all(|problem| problem.part() > total);
+
+        let action = if problems.is_empty() {
+            RepairAction::Nothing
+        } else if intact {
+            let mut secret = Vec::new();
+            for (_, decoded) in scanned.iter().take(total) {
+                if let Some((_, payload)) = decoded {
+                    secret.extend_from_slice(payload);
+                }
+            }
+            self.set_secret(&secret)?;
+
+            // set_secret only knows about the chosen total, so remove the rest
+            for (part, _) in scanned.iter().filter(|(part, _)| *part > total) {
+                self.delete_parts_in(*part..=*part, slot)?;
+            }
+            RepairAction::Rewrote { total }
+        } else {
+            RepairAction::Conflict
+        };
+
+        Ok(RepairReport {
+            parts_found,
+            total: Some(total),
+            problems,
+            action,
+        })
+    }
+
+    /// Rebuild part 1 when only its header was damaged.
+    ///
+    /// Parts `2, 3, ...` are read until one is missing, under both the
+    /// plain and the alternate names [`Entry::replace_secret`] uses, and
+    /// the total is taken from their headers, which must agree and be
+    /// complete. Exactly one set of names must hold such parts, unless
+    /// neither holds any and part 1 was the whole secret. Part 1's
+    /// payload is whatever follows the first `|` in the stored bytes; if
+    /// the later parts carry hash chain links, the first of them must match
+    /// it. Part 1 is then written again with a correct header, and the
+    /// secret stored again as by [`Entry::set_secret`] so any commit marker
+    /// is rewritten too. The secret is re-split at this entry's chunk size,
+    /// since the damaged header's record of the old one is lost. An expiry or content type recorded on the damaged
+    /// header is lost.
+    ///
+    /// Does nothing if part 1 already decodes; use [`Entry::repair`] for
+    /// other inconsistencies. Returns [`Error::CorruptedSecret`] if part 1
+    /// is missing or has no separator, since its payload is then
+    /// unrecoverable, or if the surviving parts are inconsistent or both
+    /// sets of names hold a complete run of them. Returns
+    /// [`keyring::Error::NoEntry`] if no part is stored at all.
+    pub fn rebuild_part1(&self) -> Result<()> {
+        let part1 = match self.read_part(1) {
+            Ok(data) => Some(data),
+            Err(Error::Keyring(keyring::Error::NoEntry)) => None,
+            Err(e) => return Err(e),
+        };
+        if let Some(data) = &part1 {
+            if decode_part(data).is_ok() {
+                return Ok(());
+            }
+        }
+
+        // Survivors are read first so a missing part 1 can be told apart
+        // from a missing credential. A damaged part 1 no longer says which
+        // slot is in use, so both are read
+        let runs = [self.survivors_in(0)?, self.survivors_in(1)?];
+        let unrecoverable =
+            |why: &str| Error::CorruptedSecret(format!("part 1 cannot be rebuilt: {}", why));
+        let Some(part1) = part1 else {
+            return Err(match runs.iter().all(Vec::is_empty) {
+                true => Error::Keyring(keyring::Error::NoEntry),
+                false => unrecoverable("it is missing and its payload is lost"),
+            });
+        };
+        let separator = part1
+            .iter()
+            .position(|&b| b == b'|')
+            .ok_or_else(|| unrecoverable("its payload cannot be located"))?;
+
+        // Rebuild from the one slot whose parts complete a secret
+        let mut consistent = Vec::new();
+        let mut inconsistent = None;
+        for (slot, run) in (0u8..).zip(&runs).filter(|(_, run)| !run.is_empty()) {
+            match check_survivors(run) {
+                Ok((headers, payloads)) => consistent.push((slot, headers, payloads)),
+                Err(why) => inconsistent = inconsistent.or(Some(why)),
+            }
+        }
+        let (slot, headers, payloads) = match (consistent.pop(), inconsistent) {
+            (Some(_), _) if !consistent.is_empty() => {
+                return Err(unrecoverable(
+                    "both sets of part names hold a complete secret",
+                ))
+            }
+            (Some(run), _) => run,
+            (None, Some(why)) => return Err(unrecoverable(&why)),
+            // Nothing follows part 1, so it was the whole secret
+            (None, None) => (0, Vec::new(), Vec::new()),
+        };
+        let total = headers.len() + 1;
+        let payload1 = &part1[separator + 1..];
+        let payloads: Vec<&[u8]> = std::iter::once(payload1).chain(payloads).collect();
+
+        // The survivors' links vouch for part 1's payload, if they have any.
+        // Part 1's length is only the chunk size for a full first chunk, so
+        // the entry's own is recorded for the write below to reuse
+        let mut header = Header {
+            chunk_size: Some(self.chunk_limit(false)),
+            slot,
+            generation: headers.first().and_then(|header| header.generation),
+            ..Header::new(1, total)
+        };
+        if headers.first().is_some_and(|header| header.chain.is_some()) {
+            let links = chain::links(payloads.iter().copied());
+            if headers
+                .iter()
+                .zip(&links[1..])
+                .any(|(h, link)| h.chain != Some(*link))
+            {
+                return Err(unrecoverable("its payload does not match the hash chain"));
+            }
+            header.chain = links.first().copied();
+        }
+        let secret = payloads.concat();
+
+        self.invalidate_cache();
+        self.backend_set(&self.part_user(1), &encode_part(&header, payload1))?;
+        self.set_secret(&secret)
+    }
+
+    /// Rewrite the credential as a clean layout at the full chunk size.
+    ///
+    /// Repeated updates keep the chunk size recorded by the first write and
+    /// can leave orphaned parts beyond the total, e.g. after an interrupted
+    /// cleanup. This reads and verifies the secret, then stores it again in
+    /// place the way [`Entry::set_secret`] does, at this entry's current
+    /// chunk size, keeping any expiry and content type. Part 1 is still written last, so an
+    /// interruption leaves either the old or the new layout readable.
+    /// Orphaned parts left after the new total are deleted once part 1 is
+    /// written, using the same scan as [`Entry::repair`].
+    ///
+    /// Nothing is written if the secret cannot be read, so a corrupted
+    /// credential is left for [`Entry::repair`].
+    pub fn compact(&self) -> Result<CompactReport> {
+        let secret = self.get_secret()?;
+        let expires = self.stored_header()?.and_then(|header| header.expires);
+        let content_type = self.content_type()?;
+        let (scanned, slot) = self.scan_parts()?;
+
+        self.store(
+            &secret,
+            expires,
+            WriteMode::Compact,
+            content_type.as_deref(),
+        )?;
+        let parts_after = self.part_count()?;
+        for (part, _) in scanned.iter().filter(|(part, _)| *part > parts_after) {
+            self.delete_parts_in(*part..=*part, slot)?;
+        }
+        Ok(CompactReport {
+            parts_before: scanned.len(),
+            parts_after,
+        })
+    }
+
+    /// Re-split the credential into parts of `new_chunk_size` bytes.
+    ///
+    /// Like [`Entry::compact`], but at the given chunk size instead of the
+    /// full one, for adapting a stored credential to a lower limit. The
+    /// secret is read and verified, then written again without leaving the
+    /// crate. With the part 1 commit marker the new parts go to the unused
+    /// slot, as [`Entry::replace_secret`] describes, so the old layout stays
+    /// readable until part 1 switches to the new one; other markers
+    /// overwrite in place. Every part is read back and compared before
+    /// part 1 is written, and part 1 after, so a backend that silently
+    /// shortens values fails with [`Error::CorruptedSecret`] before the old
+    /// parts are deleted. Part 1 records the new chunk size, so later
+    /// updates keep it. Any expiry and content type are kept.
+    ///
+    /// Returns [`Error::InvalidArgument`] without writing anything if
+    /// `new_chunk_size` is 0 or larger than the entry's own chunk limit.
+    pub fn reshape(&self, new_chunk_size: usize) -> Result<()> {
+        let secret = self.get_secret()?;
+        let expires = self.stored_header()?.and_then(|header| header.expires);
+        let limit = self.chunk_limit(expires.is_some());
+        if new_chunk_size == 0 || new_chunk_size > limit {
+            return Err(Error::InvalidArgument(format!(
+                "chunk size {} is outside 1..={}",
+                new_chunk_size, limit
+            )));
+        }
+        let content_type = self.content_type()?;
+        self.store(
+            &secret,
+            expires,
+            WriteMode::Reshape(new_chunk_size),
+            content_type.as_deref(),
+        )
+    }
+
+    /// Read parts `2, 3, ...` in `slot` until one is missing.
+    fn survivors_in(&self, slot: u8) -> Result<Vec<Vec<u8>>> {
+        let mut survivors = Vec::new();
+        for part in 2..=MAX_PARTS {
+            match self.read_part_in(part, slot) {
+                Ok(data) => survivors.push(data),
+                Err(Error::Keyring(keyring::Error::NoEntry)) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(survivors)
+    }
+
+    /// Read every stored part, continuing past gaps while some header still
+    /// claims a higher total.
+    ///
+    /// Later parts are read from the slot part 1 names, which is returned
+    /// alongside them.
+    fn scan_parts(&self) -> Result<(Vec<Scanned>, u8)> {
+        let mut scanned = Vec::new();
+        let mut highest_claimed = 0;
+        let mut slot = 0;
+        for part in 1..=MAX_PARTS {
+            match self.read_part_in(part, slot) {
+                Ok(data) => {
+                    let decoded = decode_part(&data)
+                        .ok()
+                        .map(|(header, payload)| (header, payload.to_vec()));
+                    if let Some((header, _)) = &decoded {
+                        highest_claimed = highest_claimed.max(header.total);
+                        if part == 1 {
+                            slot = header.slot;
+                        }
+                    }
+                    scanned.push((part, decoded));
+                }
+                Err(Error::Keyring(keyring::Error::NoEntry)) if part > highest_claimed => break,
+                Err(Error::Keyring(keyring::Error::NoEntry)) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok((scanned, slot))
+    }
+}
+
+/// The total claimed by strictly more decodable parts than any other.
+fn majority_total(scanned: &[Scanned]) -> Option<usize> {
+    let mut votes: HashMap<usize, usize> = HashMap::new();
+    for (_, decoded) in scanned {
+        if let Some((header, _)) = decoded {
+            *votes.entry(header.total).or_default() += 1;
+        }
+    }
+
+    let mut ranked: Vec<_> = votes.into_iter().collect();
+    ranked.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+    match ranked.as_slice() {
+        [] => None,
+        [(total, _)] => Some(*total),
+        [(total, first), (_, second), ..] if first > second => Some(*total),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{part_exists, spy_entry, take_ops, write_raw};
+
+    /// Replace part 1's header with garbage, keeping its payload.
+    fn corrupt_header(entry: &Entry) {
+        let part1 = entry.read_part(1).unwrap();
+        let (_, payload) = decode_part(&part1).unwrap();
+        write_raw(entry, 1, &[&b"v1/?/?|"[..], payload].concat());
+    }
+
+    #[test]
+    fn test_rebuild_part1_rewrites_corrupt_header() {
+        let spy = crate::backend::SpyBackend::new();
+        let entry = Entry::builder(crate::test_util::SERVICE)
+            .hash_chain(true)
+            .build("rebuild-part1")
+            .unwrap()
+            .with_backend(Box::new(spy.clone()));
+        let secret: Vec<u8> = (0..crate::max_chunk_size() * 2 + 7)
+            .map(|i| i as u8)
+            .collect();
+        entry.set_secret(&secret).unwrap();
+        corrupt_header(&entry);
+        assert!(entry.get_secret().is_err());
+
+        entry.rebuild_part1().unwrap();
+        assert_eq!(entry.get_secret().unwrap(), secret);
+        entry.rebuild_part1().unwrap();
+
+        // A payload the chain does not vouch for is not written back
+        write_raw(&entry, 1, b"v1/?/?|forged");
+        assert!(matches!(
+            entry.rebuild_part1(),
+            Err(Error::CorruptedSecret(_))
+        ));
+    }
+
+    #[test]
+    fn test_rebuild_part1_reports_lost_payload() {
+        let (entry, _spy) = spy_entry("rebuild-part1-lost");
+        assert!(matches!(
+            entry.rebuild_part1(),
+            Err(Error::Keyring(keyring::Error::NoEntry))
+        ));
+
+        entry
+            .set_secret(vec![1; crate::max_chunk_size() + 1])
+            .unwrap();
+        entry.backend_delete(&entry.part_user(1)).unwrap();
+        assert!(matches!(
+            entry.rebuild_part1(),
+            Err(Error::CorruptedSecret(_))
+        ));
+        write_raw(&entry, 1, b"no separator");
+        assert!(matches!(
+            entry.rebuild_part1(),
+            Err(Error::CorruptedSecret(_))
+        ));
+    }
+
+    #[test]
+    fn test_rebuild_part1_keeps_the_full_chunk_size() {
+        let (entry, _spy) = spy_entry("rebuild-part1-short");
+        entry.set_secret(b"7 bytes").unwrap();
+        corrupt_header(&entry);
+        entry.rebuild_part1().unwrap();
+        assert_eq!(entry.get_secret().unwrap(), b"7 bytes");
+
+        let large = vec![1; crate::max_chunk_size() * 2 + 1];
+        entry.set_secret(&large).unwrap();
+        let (fresh, _spy) = spy_entry("rebuild-part1-fresh");
+        fresh.set_secret(&large).unwrap();
+        assert_eq!(entry.part_count().unwrap(), fresh.part_count().unwrap());
+    }
+
+    #[test]
+    fn test_rebuild_part1_after_replace_secret() {
+        let (entry, spy) = spy_entry("rebuild-part1-slot");
+        let chunk = crate::max_chunk_size();
+        entry.set_secret(vec![1; chunk + 1]).unwrap();
+        let secret: Vec<u8> = (0..chunk * 2 + 7).map(|i| i as u8).collect();
+        entry.replace_secret(&secret).unwrap();
+        corrupt_header(&entry);
+
+        // The same parts under both sets of names leave no way to choose
+        let copies: Vec<_> = (2..=3)
+            .map(|part| entry.read_part_in(part, 1).unwrap())
+            .collect();
+        for (part, data) in (2..).zip(&copies) {
+            write_raw(&entry, part, data);
+        }
+        assert!(matches!(
+            entry.rebuild_part1(),
+            Err(Error::CorruptedSecret(_))
+        ));
+
+        entry.delete_parts_from(2).unwrap();
+        entry.rebuild_part1().unwrap();
+        assert_eq!(entry.get_secret().unwrap(), secret);
+        assert!(!spy.contains(crate::test_util::SERVICE, &entry.slot_user(2, 1)));
+    }
+
+    #[test]
+    fn test_compact_uses_full_chunks_and_drops_orphans() {
+        let (entry, spy) = spy_entry("compact");
+        let secret = vec![5; crate::max_chunk_size_for(crate::Platform::Windows) * 3];
+        entry
+            .compat_write(&secret, &[crate::Platform::Windows])
+            .unwrap();
+        write_raw(&entry, 4, b"v1/4/4|orphan");
+
+        let report = entry.compact().unwrap();
+        assert_eq!(report.parts_before, 4);
+        assert_eq!(
+            report.parts_after,
+            crate::chunk::chunks_needed(secret.len(), crate::max_chunk_size())
+        );
+        assert_eq!(entry.get_secret().unwrap(), secret);
+        assert!(!part_exists(&spy, &entry, 4));
+    }
+
+    #[test]
+    fn test_compact_leaves_unreadable_credential_alone() {
+        let (entry, spy) = spy_entry("compact-corrupt");
+        write_raw(&entry, 1, b"v1/1/2|one");
+        take_ops(&spy);
+        assert!(entry.compact().is_err());
+        assert!(!take_ops(&spy).iter().any(|(kind, _)| *kind != "get"));
+    }
+
+    #[test]
+    fn test_reshape_resplits_at_the_new_size() {
+        let (entry, spy) = spy_entry("reshape");
+        let chunk = crate::max_chunk_size();
+        let secret: Vec<u8> = (0..=255).cycle().take(chunk / 2 * 4).collect();
+        entry.set_secret(&secret).unwrap();
+
+        entry.reshape(chunk / 2).unwrap();
+        assert_eq!(entry.get_secret().unwrap(), secret);
+        assert_eq!(entry.part_count().unwrap(), 4);
+        let header = entry.stored_header().unwrap().unwrap();
+        assert_eq!((header.chunk_size, header.slot), (Some(chunk / 2), 1));
+        assert!(!part_exists(&spy, &entry, 2));
+
+        // Later updates keep the new geometry
+        entry.set_secret(&secret[..chunk / 2 * 2]).unwrap();
+        assert_eq!(entry.part_count().unwrap(), 2);
+        entry.reshape(chunk).unwrap();
+        assert_eq!(entry.part_count().unwrap(), 1);
+        assert_eq!(entry.get_secret().unwrap(), secret[..chunk / 2 * 2]);
+    }
+
+    #[test]
+    fn test_reshape_rejects_sizes_outside_the_limit() {
+        let (entry, spy) = spy_entry("reshape-limit");
+        entry.set_secret(b"small").unwrap();
+        take_ops(&spy);
+        for size in [0, entry.chunk_limit(false) + 1] {
+            assert!(matches!(
+                entry.reshape(size),
+                Err(Error::InvalidArgument(_))
+            ));
+        }
+        assert!(!take_ops(&spy).iter().any(|(kind, _)| *kind != "get"));
+    }
+
+    #[test]
+    fn test_repair_consistent_credential_does_nothing() {
+        let (entry, spy) = spy_entry("repair-clean");
+        entry
+            .set_secret(vec![1; crate::max_chunk_size() * 2])
+            .unwrap();
+        take_ops(&spy);
+
+        let report = entry.repair().unwrap();
+        assert_eq!(report.parts_found, [1, 2]);
+        assert_eq!(report.total, Some(2));
+        assert!(report.problems.is_empty());
+        assert_eq!(report.action, RepairAction::Nothing);
+        assert!(!take_ops(&spy).iter().any(|(kind, _)| *kind != "get"));
+    }
+
+    #[test]
+    fn test_repair_reports_a_torn_write() {
+        let (entry, spy) = spy_entry("repair-torn");
+        write_raw(&entry, 1, b"v1/1/3/g=2|one ");
+        write_raw(&entry, 2, b"v1/2/3/g=1|two ");
+        write_raw(&entry, 3, b"v1/3/3/g=2|three");
+        assert!(matches!(
+            entry.get_secret(),
+            Err(Error::ConcurrentModification { part: 2 })
+        ));
+        take_ops(&spy);
+
+        let report = entry.repair().unwrap();
+        assert_eq!(report.total, Some(3));
+        assert_eq!(
+            report.problems,
+            [RepairProblem::StaleGeneration {
+                part: 2,
+                generation: Some(1)
+            }]
+        );
+        assert_eq!(report.action, RepairAction::Conflict);
+        assert!(!take_ops(&spy).iter().any(|(kind, _)| *kind != "get"));
+    }
+
+    #[test]
+    fn test_repair_rewrites_majority_and_drops_stray_part() {
+        let (entry, spy) = spy_entry("repair-stray");
+        write_raw(&entry, 1, b"v1/1/3|one ");
+        write_raw(&entry, 2, b"v1/2/3|two ");
+        write_raw(&entry, 3, b"v1/3/3|three");
+        // Left behind by a concurrent write that used a different total
+        write_raw(&entry, 4, b"v1/3/4|stray");
+
+        let report = entry.repair().unwrap();
+        assert_eq!(report.parts_found, [1, 2, 3, 4]);
+        assert_eq!(report.total, Some(3));
+        assert_eq!(
+            report.problems,
+            [
+                RepairProblem::Misplaced {
+                    part: 4,
+                    claimed: 3
+                },
+                RepairProblem::InconsistentTotal { part: 4, total: 4 },
+            ]
+        );
+        assert_eq!(report.action, RepairAction::Rewrote { total: 3 });
+
+        assert!(!part_exists(&spy, &entry, 4));
+        assert_eq!(entry.get_secret().unwrap(), b"one two three");
+    }
+
+    #[test]
+    fn test_repair_reports_conflict_without_writing() {
+        let (entry, spy) = spy_entry("repair-conflict");
+        write_raw(&entry, 1, b"v1/1/2|a");
+        write_raw(&entry, 2, b"v1/2/3|b");
+        write_raw(&entry, 3, b"v1/3/3|c");
+        write_raw(&entry, 4, b"v1/4/4|d");
+        take_ops(&spy);
+
+        // Totals 2, 3 (twice) and 4: 3 wins, but parts 1 and 2 disagree with it
+        let report = entry.repair().unwrap();
+        assert_eq!(report.total, Some(3));
+        assert_eq!(report.action, RepairAction::Conflict);
+        assert!(report
+            .problems
+            .contains(&RepairProblem::InconsistentTotal { part: 1, total: 2 }));
+        assert!(!take_ops(&spy).iter().any(|(kind, _)| *kind != "get"));
+        for part in 1..=4 {
+            assert!(part_exists(&spy, &entry, part));
+        }
+    }
+
+    #[test]
+    fn test_repair_tie_is_a_conflict() {
+        let (entry, _spy) = spy_entry("repair-tie");
+        write_raw(&entry, 1, b"v1/1/1|a");
+        write_raw(&entry, 2, b"v1/2/2|b");
+
+        let report = entry.repair().unwrap();
+        assert_eq!(report.total, None);
+        assert_eq!(report.action, RepairAction::Conflict);
+    }
+
+    #[test]
+    fn test_repair_nothing_stored() {
+        let (entry, _spy) = spy_entry("repair-empty");
+        let report = entry.repair().unwrap();
+        assert!(report.parts_found.is_empty());
+        assert_eq!(report.action, RepairAction::Nothing);
+    }
+}
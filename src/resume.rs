@@ -0,0 +1,254 @@
+use crate::entry::{encode_layout_part, Entry, Layout, WriteMode};
+use crate::format::{parse_hex_digest, CURRENT_VERSION};
+use crate::sha256::{Sha256, DIGEST_LEN};
+use crate::{Error, Result};
+
+/// How far an earlier [`Entry::set_secret_resumable`] of the same secret
+/// got, as recorded in the progress entry.
+#[derive(Debug)]
+pub(crate) struct Progress {
+    fingerprint: [u8; DIGEST_LEN],
+    /// The lowest part written so far; parts are written last to first.
+    written_from: usize,
+}
+
+impl Entry {
+    /// Store a secret like [`Entry::set_secret`], so that a failed write can
+    /// be retried without writing every part again.
+    ///
+    /// After each part is written, a `{user}#progress` entry records the
+    /// lowest part written so far, together with a fingerprint of the
+    /// secret and its layout. Calling this again with the same secret after
+    /// a failure reads that entry and, for the parts it says were written,
+    /// reads each one back and only writes it if it differs; the remaining
+    /// parts, part 1 and any commit marker are then written as usual. This
+    /// trades one extra write per part for not resending completed parts.
+    ///
+    /// A progress entry for a different secret, or for the same secret laid
+    /// out differently, is stale and ignored, and read-back parts that no
+    /// longer match are written again, so a write in between is never
+    /// mistaken for progress. The entry is deleted once the write
+    /// succeeds, or once any other write or delete of this credential
+    /// does, since those supersede it. A single-part secret needs no
+    /// progress and is written as by [`Entry::set_secret`].
+    pub fn set_secret_resumable(&self, secret: impl AsRef<[u8]>) -> Result<()> {
+        self.store(secret.as_ref(), None, WriteMode::Resumable, None)
+    }
+
+    /// The progress of an earlier attempt at writing `secret` as `layout`,
+    /// or a fresh start if there is none or it was for something else.
+    pub(crate) fn progress_for(&self, secret: &[u8], layout: &Layout) -> Result<Progress> {
+        let mut hasher = Sha256::new();
+        hasher.update(
+            format!(
+                "{}/{}/{}|",
+                layout.total, layout.chunk_size, self.hash_chain
+            )
+            .as_bytes(),
+        );
+        hasher.update(secret);
+        let fingerprint = hasher.finalize();
+
+        let recorded = match self.backend_get(&self.progress_user()) {
+            Ok(data) => decode_progress(&data),
+            Err(keyring::Error::NoEntry) => None,
+            Err(e) => return Err(Error::from(e)),
+        };
+        let written_from = match recorded {
+            Some((recorded, written_from)) if recorded == fingerprint => written_from,
+            _ => layout.total + 1,
+        };
+        Ok(Progress {
+            fingerprint,
+            written_from,
+        })
+    }
+
+    /// Write one part of a resumable write and record that it was written.
+    ///
+    /// Parts an earlier attempt wrote are read back instead, and written
+    /// only if they differ. `buf` holds the encoded part afterwards.
+    pub(crate) fn write_resumable_part(
+        &self,
+        progress: &mut Progress,
+        part: usize,
+        layout: &Layout,
+        secret: &[u8],
+        buf: &mut Vec<u8>,
+    ) -> Result<()> {
+        encode_layout_part(part, layout, secret, buf)?;
+        let user = self.slot_user(part, layout.slot);
+        if part >= progress.written_from {
+            match self.backend_get(&user) {
+                Ok(stored) if stored == *buf => return Ok(()),
+                Ok(_) | Err(keyring::Error::NoEntry) => {}
+                Err(e) => return Err(Error::from(e)),
+            }
+        }
+        self.backend_set(&user, buf)?;
+        self.describe_part(&user, part, layout.total);
+        progress.written_from = progress.written_from.min(part);
+        self.backend_set(&self.progress_user(), &encode_progress(progress))?;
+        Ok(())
+    }
+
+    /// Delete the progress entry of a finished or superseded resumable write.
+    pub(crate) fn delete_progress(&self) -> Result<()> {
+        match self.backend_delete(&self.progress_user()) {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    /// The backend user name holding the progress of a resumable write.
+    pub(crate) fn progress_user(&self) -> String {
+        format!("{}#progress", self.encoded_user)
+    }
+}
+
+/// Encode progress.
+/// Format: "v1/{lowest part written}/{hex fingerprint}"
+fn encode_progress(progress: &Progress) -> Vec<u8> {
+    use std::fmt::Write;
+
+    let mut out = format!("v{}/{}/", CURRENT_VERSION, progress.written_from);
+    for byte in progress.fingerprint {
+        // Writing into a String cannot fail
+        let _ = write!(out, "{:02x}", byte);
+    }
+    out.into_bytes()
+}
+
+/// Decode progress, or `None` if it is not in the current format.
+fn decode_progress(data: &[u8]) -> Option<([u8; DIGEST_LEN], usize)> {
+    let progress = std::str::from_utf8(data).ok()?;
+    let mut fields = progress.split('/');
+    if fields.next()? != format!("v{}", CURRENT_VERSION) {
+        return None;
+    }
+    let written_from = fields.next()?.parse().ok()?;
+    let fingerprint = parse_hex_digest(fields.next()?)?;
+    match fields.next() {
+        None => Some((fingerprint, written_from)),
+        Some(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{Backend, SpyBackend};
+    use crate::test_util::{take_ops, FaultyBackend, SERVICE};
+
+    #[test]
+    fn test_resumed_write_skips_written_parts() {
+        let spy = SpyBackend::new();
+        let user = "resumable";
+        let secret: Vec<u8> = (0..crate::max_chunk_size() * 3 + 1)
+            .map(|i| i as u8)
+            .collect();
+
+        // Parts 4 and 3 and their progress go through before the failure
+        let failing = Entry::new(SERVICE, user)
+            .unwrap()
+            .with_backend(Box::new(FaultyBackend::failing_after(spy.clone(), 4)));
+        assert!(failing.set_secret_resumable(&secret).is_err());
+        assert!(spy.contains(SERVICE, &failing.progress_user()));
+
+        let entry = Entry::new(SERVICE, user)
+            .unwrap()
+            .with_backend(Box::new(spy.clone()));
+        take_ops(&spy);
+        entry.set_secret_resumable(&secret).unwrap();
+        let sets: Vec<_> = take_ops(&spy)
+            .into_iter()
+            .filter(|(kind, _)| *kind == "set")
+            .map(|(_, user)| user)
+            .collect();
+        assert_eq!(
+            sets,
+            [
+                entry.part_user(2),
+                entry.progress_user(),
+                entry.part_user(1)
+            ]
+        );
+        assert_eq!(entry.get_secret().unwrap(), secret);
+        assert!(!spy.contains(SERVICE, &entry.progress_user()));
+    }
+
+    #[test]
+    fn test_other_writes_and_deletes_clean_up_progress() {
+        let secret = vec![1; crate::max_chunk_size() * 3];
+        let cleanups: [fn(&Entry); 4] = [
+            |entry| entry.set_secret(b"small").unwrap(),
+            |entry| {
+                entry
+                    .set_secret(vec![2; crate::max_chunk_size() * 2])
+                    .unwrap()
+            },
+            |entry| entry.delete_credential().unwrap(),
+            |entry| entry.delete_force().unwrap(),
+        ];
+        for (i, cleanup) in cleanups.into_iter().enumerate() {
+            let spy = SpyBackend::new();
+            let user = format!("resumable-cleanup-{i}");
+            let entry = Entry::new(SERVICE, &user)
+                .unwrap()
+                .with_backend(Box::new(spy.clone()));
+            entry.set_secret(vec![0; secret.len()]).unwrap();
+
+            // Part 3 and its progress go through before the failure
+            let failing = Entry::new(SERVICE, &user)
+                .unwrap()
+                .with_backend(Box::new(FaultyBackend::failing_after(spy.clone(), 2)));
+            assert!(failing.set_secret_resumable(&secret).is_err());
+            assert!(spy.contains(SERVICE, &entry.progress_user()));
+
+            cleanup(&entry);
+            assert!(!spy.contains(SERVICE, &entry.progress_user()));
+            if i < 2 {
+                entry.delete_credential().unwrap();
+            }
+            let left: Vec<_> = spy
+                .operations()
+                .iter()
+                .map(|op| op.user().to_string())
+                .filter(|user| spy.contains(SERVICE, user))
+                .collect();
+            assert!(left.is_empty(), "cleanup {i} left {left:?}");
+        }
+    }
+
+    #[test]
+    fn test_stale_progress_is_ignored() {
+        let spy = SpyBackend::new();
+        let entry = Entry::new(SERVICE, "resumable-stale")
+            .unwrap()
+            .with_backend(Box::new(spy.clone()));
+        let secret = vec![1; crate::max_chunk_size() * 2];
+        let layout = entry.plan(&secret, None, true).unwrap().0;
+
+        // Progress claiming every part of this secret, but for other parts
+        let progress = entry.progress_for(&secret, &layout).unwrap();
+        let claimed = Progress {
+            written_from: 1,
+            ..progress
+        };
+        entry
+            .set_secret(vec![2; crate::max_chunk_size() * 2])
+            .unwrap();
+        spy.set_secret(SERVICE, &entry.progress_user(), &encode_progress(&claimed))
+            .unwrap();
+
+        entry.set_secret_resumable(&secret).unwrap();
+        assert_eq!(entry.get_secret().unwrap(), secret);
+
+        let other = vec![3; crate::max_chunk_size() * 2];
+        spy.set_secret(SERVICE, &entry.progress_user(), &encode_progress(&claimed))
+            .unwrap();
+        let fresh = entry.progress_for(&other, &layout).unwrap();
+        assert_eq!(fresh.written_from, layout.total + 1);
+        assert_eq!(decode_progress(b"v1/2/nothex"), None);
+    }
+}
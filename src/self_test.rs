@@ -0,0 +1,116 @@
+use std::time::{Duration, Instant};
+
+use crate::entry::Entry;
+use crate::{Error, Result};
+
+/// The service [`self_test`](crate::self_test) stores its probes under.
+pub(crate) const SELF_TEST_SERVICE: &str = "keyring-cursed-self-test";
+
+/// The user [`self_test`](crate::self_test) stores its probes under.
+pub(crate) const SELF_TEST_USER: &str = "probe";
+
+/// What [`self_test`](crate::self_test) measured.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelfTestReport {
+    /// The payload size of part 1 of the large probe, i.e. the chunk size
+    /// writes actually use on this system.
+    pub chunk_size: usize,
+    /// The number of parts the large probe was split into.
+    pub large_parts: usize,
+    /// How long writing and reading back the single-part probe took.
+    pub small_round_trip: Duration,
+    /// How long writing and reading back the large probe took.
+    pub large_round_trip: Duration,
+}
+
+/// Run the self-test against `entry`, deleting the probe afterwards.
+pub(crate) fn run(entry: &Entry) -> Result<SelfTestReport> {
+    let result = probe(entry);
+    // Clean up whatever the probe left, even if it failed part way
+    let cleanup = entry
+        .delete_credential()
+        .or_else(|_| entry.delete_parts_from(1).map(|_| ()));
+    let report = result?;
+    cleanup?;
+    Ok(report)
+}
+
+fn probe(entry: &Entry) -> Result<SelfTestReport> {
+    let small = pattern(32);
+    let small_round_trip = round_trip(entry, &small)?;
+
+    // Three parts: enough to exercise part ordering and a short last part
+    let large = pattern(entry.chunk_limit(false) * 2 + 1);
+    let large_round_trip = round_trip(entry, &large)?;
+    let large_parts = entry.part_count()?;
+
+    let chunk_size = match entry.parts().next() {
+        Some(part) => part?.1.len(),
+        None => return Err(Error::CorruptedSecret("self-test probe vanished".into())),
+    };
+    if large_parts != 3 || chunk_size * 2 + 1 != large.len() {
+        return Err(Error::CorruptedSecret(format!(
+            "self-test probe of {} bytes was stored in {} parts of {} bytes",
+            large.len(),
+            large_parts,
+            chunk_size
+        )));
+    }
+
+    Ok(SelfTestReport {
+        chunk_size,
+        large_parts,
+        small_round_trip,
+        large_round_trip,
+    })
+}
+
+/// Write `secret`, read it back and check it, returning how long it took.
+fn round_trip(entry: &Entry, secret: &[u8]) -> Result<Duration> {
+    let start = Instant::now();
+    entry.set_secret(secret)?;
+    let read = entry.get_secret()?;
+    let elapsed = start.elapsed();
+    if read != secret {
+        return Err(Error::CorruptedSecret(format!(
+            "self-test probe of {} bytes read back as {} different bytes",
+            secret.len(),
+            read.len()
+        )));
+    }
+    Ok(elapsed)
+}
+
+/// Non-repeating-looking bytes, so misplaced parts do not compare equal.
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::SpyBackend;
+    use crate::test_util::{spy_entry, FaultyBackend};
+
+    #[test]
+    fn test_self_test_reports_and_cleans_up() {
+        let (entry, spy) = spy_entry(SELF_TEST_USER);
+        let report = run(&entry).unwrap();
+        assert_eq!(report.chunk_size, crate::max_chunk_size());
+        assert_eq!(report.large_parts, 3);
+        assert!(!entry.exists().unwrap());
+        assert!(spy.take_operations().len() > 6);
+    }
+
+    #[test]
+    fn test_self_test_failure_still_cleans_up() {
+        let spy = SpyBackend::new();
+        let entry = Entry::new(SELF_TEST_SERVICE, SELF_TEST_USER)
+            .unwrap()
+            .with_backend(Box::new(FaultyBackend::truncating(spy.clone(), 100)));
+        assert!(matches!(run(&entry), Err(Error::CorruptedSecret(_))));
+        for part in 1..=3 {
+            assert!(!spy.contains(SELF_TEST_SERVICE, &entry.part_user(part)));
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::backend::{Backend, Operation, SpyBackend};
+use crate::Entry;
+
+pub(crate) const SERVICE: &str = "keyring-cursed-test";
+
+/// An entry for `user` backed by a fresh spy.
+pub(crate) fn spy_entry(user: &str) -> (Entry, SpyBackend) {
+    let spy = SpyBackend::new();
+    let entry = Entry::new(SERVICE, user)
+        .unwrap()
+        .with_backend(Box::new(spy.clone()));
+    (entry, spy)
+}
+
+/// Take the recorded operations as (kind, part user) pairs.
+pub(crate) fn take_ops(spy: &SpyBackend) -> Vec<(&'static str, String)> {
+    spy.take_operations()
+        .into_iter()
+        .map(|op| {
+            let kind = match op {
+                Operation::Get { .. } => "get",
+                Operation::Set { .. } => "set",
+                Operation::Delete { .. } => "delete",
+                Operation::UpdateAttributes { .. } => "attributes",
+            };
+            (kind, op.user().to_string())
+        })
+        .collect()
+}
+
+pub(crate) fn part_exists(spy: &SpyBackend, entry: &Entry, part: usize) -> bool {
+    spy.contains(SERVICE, &entry.part_user(part))
+}
+
+/// Store raw bytes as the given part, bypassing the chunking logic.
+pub(crate) fn write_raw(entry: &Entry, part: usize, data: &[u8]) {
+    let user = entry.part_user(part);
+    entry.backend.set_secret(SERVICE, &user, data).unwrap();
+}
+
+/// A spy that keeps only a prefix of every value, like a backend with an
+/// undocumented size limit, or that fails writes after a number of them,
+/// like one that goes away part way through.
+#[derive(Debug)]
+pub(crate) struct FaultyBackend {
+    spy: SpyBackend,
+    keep: usize,
+    writes: AtomicUsize,
+}
+
+impl FaultyBackend {
+    /// Keep only the first `keep` bytes of every value written.
+    pub(crate) fn truncating(spy: SpyBackend, keep: usize) -> Self {
+        Self {
+            spy,
+            keep,
+            writes: usize::MAX.into(),
+        }
+    }
+
+    /// Fail every write after the first `writes`.
+    pub(crate) fn failing_after(spy: SpyBackend, writes: usize) -> Self {
+        Self {
+            spy,
+            keep: usize::MAX,
+            writes: writes.into(),
+        }
+    }
+}
+
+impl Backend for FaultyBackend {
+    fn get_secret(&self, service: &str, user: &str) -> keyring::Result<Vec<u8>> {
+        self.spy.get_secret(service, user)
+    }
+
+    fn set_secret(&self, service: &str, user: &str, secret: &[u8]) -> keyring::Result<()> {
+        if self.writes.fetch_sub(1, Ordering::Relaxed) == 0 {
+            self.writes.store(0, Ordering::Relaxed);
+            return Err(keyring::Error::NoStorageAccess("backend went away".into()));
+        }
+        let kept = secret.len().min(self.keep);
+        self.spy.set_secret(service, user, &secret[..kept])
+    }
+
+    fn delete_credential(&self, service: &str, user: &str) -> keyring::Result<()> {
+        self.spy.delete_credential(service, user)
+    }
+}
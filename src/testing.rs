@@ -0,0 +1,180 @@
+//! Isolated entries for tests that run against a real keyring.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::backend::{Backend, SpyBackend};
+use crate::entry::Entry;
+use crate::prepared::delete_unknown_staged;
+use crate::Result;
+
+/// The prefix of every service name [`TestEntry`] generates.
+pub const TEST_SERVICE_PREFIX: &str = "keyring-cursed-test-";
+
+/// An entry under a random service and user, deleted when dropped.
+///
+/// Each `TestEntry` gets names no other test uses, so tests can run in
+/// parallel against the platform keyring without seeing each other's
+/// credentials, and a failing test leaves nothing behind. Dereferences to
+/// [`Entry`], so every entry method is available.
+///
+/// Dropping it deletes the credential as [`Entry::delete_force`] does,
+/// together with the progress of an interrupted
+/// [`Entry::set_secret_resumable`] and any parts staged by
+/// [`Entry::prepare`]. Cleanup on drop is best-effort: failures are logged, not reported, and
+/// a process that aborts skips it. Any leftovers are under services
+/// starting with [`TEST_SERVICE_PREFIX`].
+#[derive(Debug)]
+pub struct TestEntry {
+    entry: Entry,
+    service: String,
+    user: String,
+}
+
+impl TestEntry {
+    /// An isolated entry stored in the platform keyring.
+    pub fn new() -> Result<Self> {
+        let (service, user) = random_names();
+        Ok(Self {
+            entry: Entry::new(&service, &user)?,
+            service,
+            user,
+        })
+    }
+
+    /// An isolated entry stored in the given backend.
+    pub fn with_backend(backend: Box<dyn Backend>) -> Result<Self> {
+        let (service, user) = random_names();
+        Ok(Self {
+            entry: Entry::new(&service, &user)?.with_backend(backend),
+            service,
+            user,
+        })
+    }
+
+    /// An isolated entry stored in a fresh [`SpyBackend`], returned with it.
+    pub fn in_memory() -> Result<(Self, SpyBackend)> {
+        let spy = SpyBackend::new();
+        Ok((Self::with_backend(Box::new(spy.clone()))?, spy))
+    }
+
+    /// The generated service name.
+    pub fn service(&self) -> &str {
+        &self.service
+    }
+
+    /// The generated user name.
+    pub fn user(&self) -> &str {
+        &self.user
+    }
+
+    /// The wrapped entry.
+    pub fn entry(&self) -> &Entry {
+        &self.entry
+    }
+
+    /// Store `secret`, read it back and panic unless it comes back intact.
+    pub fn assert_round_trip(&self, secret: impl AsRef<[u8]>) {
+        let secret = secret.as_ref();
+        if let Err(e) = self.entry.set_secret(secret) {
+            panic!("storing {} bytes failed: {}", secret.len(), e);
+        }
+        match self.entry.get_secret() {
+            Ok(read) => assert!(
+                read == secret,
+                "{} bytes were stored but {} different bytes read back",
+                secret.len(),
+                read.len()
+            ),
+            Err(e) => panic!("reading back {} bytes failed: {}", secret.len(), e),
+        }
+    }
+}
+
+impl Deref for TestEntry {
+    type Target = Entry;
+
+    fn deref(&self) -> &Entry {
+        &self.entry
+    }
+}
+
+impl Drop for TestEntry {
+    fn drop(&mut self) {
+        // Each step runs even if one before it failed, so as little as
+        // possible is left behind
+        let results = [
+            self.entry.delete_force(),
+            self.entry.delete_progress(),
+            delete_unknown_staged(&self.entry),
+        ];
+        for e in results.into_iter().filter_map(Result::err) {
+            log::warn!("failed to clean up test entry: {}", e);
+        }
+    }
+}
+
+/// A service and user unique to this call.
+fn random_names() -> (String, String) {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    // RandomState is seeded randomly per process; the counter separates
+    // entries created by one process
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.write_u32(std::process::id());
+    let id = hasher.finish();
+    (
+        format!("{}{:016x}", TEST_SERVICE_PREFIX, id),
+        format!("user-{:016x}", id.rotate_left(32)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::FaultyBackend;
+
+    #[test]
+    fn test_test_entries_are_isolated_and_cleaned_up() {
+        let (first, spy) = TestEntry::in_memory().unwrap();
+        let (second, _) = TestEntry::in_memory().unwrap();
+        assert_ne!(first.service(), second.service());
+        assert!(first.service().starts_with(TEST_SERVICE_PREFIX));
+
+        first.assert_round_trip(vec![3; crate::max_chunk_size() + 1]);
+        let service = first.service().to_string();
+        let part2 = first.part_user(2);
+        drop(first);
+        assert!(!spy.contains(&service, &part2));
+
+        // Everything else an entry can store goes too
+        let (mut entry, spy) = TestEntry::in_memory().unwrap();
+        entry.entry.parity = true;
+        entry
+            .set_secret_with_type(vec![4; crate::max_chunk_size() + 1], "text/plain")
+            .unwrap();
+        std::mem::forget(entry.prepare(vec![5; crate::max_chunk_size() * 2]).unwrap());
+        spy.set_secret(entry.service(), &entry.progress_user(), b"v1/2/00")
+            .unwrap();
+        let service = entry.service().to_string();
+        drop(entry);
+        let left: Vec<_> = spy
+            .operations()
+            .iter()
+            .map(|op| op.user().to_string())
+            .filter(|user| spy.contains(&service, user))
+            .collect();
+        assert!(left.is_empty(), "left {left:?}");
+    }
+
+    #[test]
+    #[should_panic(expected = "different bytes")]
+    fn test_assert_round_trip_panics_on_mismatch() {
+        // Cuts the payload short, leaving the header intact
+        let truncating = FaultyBackend::truncating(SpyBackend::new(), 24);
+        let entry = TestEntry::with_backend(Box::new(truncating)).unwrap();
+        entry.assert_round_trip(b"a secret longer than what is kept");
+    }
+}